@@ -1,8 +1,13 @@
-use crate::detector::FileCategory;
+use crate::config::{Config, CustomCategory, SizeRule};
+use crate::download_source;
+use crate::detector::{
+    self, DetectionMethod, ExtensionTable, FileCategory, FileDetector, FilenameTable,
+};
+use crate::utils::file::{fold_extension, normalized_extension};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CategoryRule {
@@ -10,73 +15,500 @@ pub struct CategoryRule {
     pub destination: PathBuf,
 }
 
+/// Where a routed file's category came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum CategorySource {
+    /// A user-defined `[[categories]]` entry matched the file
+    CustomCategory,
+    /// Content detection (magic bytes, falling back to the extension)
+    Detected,
+    /// An `[extension_overrides]` entry bypassed categorization
+    ExtensionOverride,
+    /// The path is a macOS bundle, organized as an application
+    Bundle,
+}
+
+/// Which rule chose a routed file's destination
+#[derive(Debug, Clone, PartialEq)]
+pub enum DestinationSource {
+    /// A `[[size_rules]]` entry overrode the category destination
+    SizeRule { larger_than: u64 },
+    /// A `[mime_destinations]` entry for the detected MIME type
+    MimeRule { mime_prefix: String },
+    /// The category's own destination
+    CategoryRule,
+    /// An `[extension_overrides]` entry for this extension
+    ExtensionOverride { extension: String },
+    /// A `[source_routing]` entry for the domain the file was downloaded from
+    SourceRule { domain: String },
+    /// The configured `bundle_destination`, for bundles
+    BundleDestination,
+}
+
+/// The outcome of routing a file, with the rules that decided it
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub category: FileCategory,
+    pub category_source: CategorySource,
+    /// MIME type detected from the file's contents, if any
+    pub mime: Option<String>,
+    /// How the category was detected; `None` when detection didn't run
+    pub detection_method: Option<DetectionMethod>,
+    /// Top-level destination, or `None` if no rule covers the category
+    pub destination: Option<PathBuf>,
+    pub destination_source: DestinationSource,
+}
+
+impl Route {
+    /// Human-readable description of why the file was routed this way
+    pub fn explain(&self) -> String {
+        if let (DestinationSource::ExtensionOverride { extension }, Some(destination)) =
+            (&self.destination_source, &self.destination)
+        {
+            return format!(
+                "extension override for '.{}' bypassed categorization; destination {}",
+                extension,
+                destination.display()
+            );
+        }
+
+        let category_reason = match self.category_source {
+            CategorySource::CustomCategory => "custom category rule",
+            CategorySource::Detected => "file type detection",
+            CategorySource::ExtensionOverride => "extension override",
+            CategorySource::Bundle => "macOS bundle",
+        };
+
+        let destination_reason = match &self.destination_source {
+            DestinationSource::SizeRule { larger_than } => {
+                format!("size rule (larger than {} bytes)", larger_than)
+            }
+            DestinationSource::MimeRule { mime_prefix } => {
+                format!("MIME rule for '{}'", mime_prefix)
+            }
+            DestinationSource::CategoryRule => "category rule".to_string(),
+            DestinationSource::ExtensionOverride { extension } => {
+                format!("extension override for '.{}'", extension)
+            }
+            DestinationSource::SourceRule { domain } => {
+                format!("download source rule for '{}'", domain)
+            }
+            DestinationSource::BundleDestination => "bundle destination".to_string(),
+        };
+
+        match &self.destination {
+            Some(destination) => format!(
+                "category '{}' chosen by {}; destination {} chosen by {}",
+                self.category.name(),
+                category_reason,
+                destination.display(),
+                destination_reason
+            ),
+            None => format!(
+                "category '{}' chosen by {}; no destination configured",
+                self.category.name(),
+                category_reason
+            ),
+        }
+    }
+}
+
 pub struct Categorizer {
     rules: HashMap<FileCategory, CategoryRule>,
+    size_rules: Vec<SizeRule>,
+    custom_categories: Vec<CustomCategory>,
+    extension_overrides: HashMap<String, PathBuf>,
+    mime_destinations: HashMap<String, PathBuf>,
+    source_routes: HashMap<String, PathBuf>,
+    extra_extensions: ExtensionTable,
+    extra_filenames: FilenameTable,
+    inspect_text: bool,
 }
 
 impl Categorizer {
     pub fn new() -> Result<Self> {
         let home_dir = dirs::home_dir().context("Could not determine home directory")?;
 
-        let mut rules = HashMap::new();
+        let rules = Self::default_rules(&home_dir, |category| match category {
+            FileCategory::Document | FileCategory::Archive | FileCategory::DiskImage => {
+                dirs::document_dir()
+            }
+            FileCategory::Image => dirs::picture_dir(),
+            FileCategory::Video | FileCategory::Subtitle => dirs::video_dir(),
+            FileCategory::Audio => dirs::audio_dir(),
+            FileCategory::Font => dirs::font_dir(),
+            FileCategory::Torrent => dirs::download_dir(),
+            _ => None,
+        });
 
-        rules.insert(
-            FileCategory::Document,
-            CategoryRule {
-                name: "Documents".to_string(),
-                destination: home_dir.join("Documents"),
-            },
-        );
+        Ok(Self {
+            rules,
+            size_rules: Vec::new(),
+            custom_categories: Vec::new(),
+            extension_overrides: HashMap::new(),
+            mime_destinations: HashMap::new(),
+            source_routes: HashMap::new(),
+            extra_extensions: ExtensionTable::new(),
+            extra_filenames: FilenameTable::new(),
+            inspect_text: false,
+        })
+    }
 
-        rules.insert(
-            FileCategory::Image,
-            CategoryRule {
-                name: "Images".to_string(),
-                destination: home_dir.join("Pictures"),
-            },
-        );
+    /// Categorizer with every categorization and routing rule from `config` applied
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self::new()?
+            .with_size_rules(config.size_rules.clone())
+            .with_custom_categories(config.categories.clone())
+            .with_unknown_destination(config.unknown_destination.clone())
+            .with_disk_image_destination(config.disk_image_destination.clone())
+            .with_torrent_destination(config.torrent_destination.clone())
+            .with_extension_overrides(config.extension_overrides.clone())
+            .with_mime_destinations(config.mime_destinations.clone())
+            .with_source_routes(config.source_routing.clone())
+            .with_extra_extensions(detector::extension_table(&config.detector.extensions)?)
+            .with_extra_filenames(detector::filename_table(&config.detector.filenames)?)
+            .with_text_inspection(config.detector.inspect_text))
+    }
 
-        rules.insert(
-            FileCategory::Video,
-            CategoryRule {
-                name: "Videos".to_string(),
-                destination: home_dir.join("Videos"),
-            },
-        );
+    /// Built-in category destinations, using the OS's own folder for each category
+    /// (`user_dir`, e.g. XDG user dirs or a localized `~/Documentos`) when it has
+    /// one and a folder under `home_dir` otherwise
+    fn default_rules(
+        home_dir: &Path,
+        user_dir: impl Fn(&FileCategory) -> Option<PathBuf>,
+    ) -> HashMap<FileCategory, CategoryRule> {
+        let defaults = [
+            (FileCategory::Document, "Documents", home_dir.join("Documents")),
+            (FileCategory::Image, "Images", home_dir.join("Pictures")),
+            (FileCategory::Video, "Videos", home_dir.join("Videos")),
+            (FileCategory::Audio, "Music", home_dir.join("Music")),
+            (FileCategory::Archive, "Archives", home_dir.join("Documents")),
+            (FileCategory::DiskImage, "Disk Images", home_dir.join("Documents")),
+            (FileCategory::Code, "Projects", home_dir.join("Projects")),
+            (FileCategory::Font, "Fonts", home_dir.join("Documents").join("Fonts")),
+            (FileCategory::Subtitle, "Subtitles", home_dir.join("Videos")),
+            (FileCategory::Torrent, "Torrents", home_dir.join("Downloads")),
+        ];
 
-        rules.insert(
-            FileCategory::Audio,
-            CategoryRule {
-                name: "Music".to_string(),
-                destination: home_dir.join("Music"),
-            },
-        );
+        defaults
+            .into_iter()
+            .map(|(category, name, fallback)| {
+                let mut destination = user_dir(&category).unwrap_or(fallback);
+                // Archives and disk images live inside the documents folder,
+                // subtitles next to videos and torrents in the downloads folder
+                if category == FileCategory::Archive {
+                    destination = destination.join("Archives");
+                } else if category == FileCategory::DiskImage {
+                    destination = destination.join("Disk Images");
+                } else if category == FileCategory::Subtitle {
+                    destination = destination.join("Subtitles");
+                } else if category == FileCategory::Torrent {
+                    destination = destination.join("Torrents");
+                }
+                let rule = CategoryRule {
+                    name: name.to_string(),
+                    destination,
+                };
+                (category, rule)
+            })
+            .collect()
+    }
 
-        rules.insert(
-            FileCategory::Archive,
-            CategoryRule {
-                name: "Archives".to_string(),
-                destination: home_dir.join("Documents").join("Archives"),
-            },
-        );
+    /// Add user-defined categories, which take precedence over the built-in ones
+    pub fn with_custom_categories(mut self, custom_categories: Vec<CustomCategory>) -> Self {
+        for custom in &custom_categories {
+            self.rules.insert(
+                FileCategory::Custom(custom.name.clone()),
+                CategoryRule {
+                    name: custom.name.clone(),
+                    destination: custom.destination.clone(),
+                },
+            );
+        }
+        self.custom_categories = custom_categories;
+        self
+    }
 
-        rules.insert(
-            FileCategory::Code,
-            CategoryRule {
-                name: "Projects".to_string(),
-                destination: home_dir.join("Projects"),
-            },
-        );
+    /// Send files with these extensions straight to a fixed destination,
+    /// bypassing detection and every other rule
+    pub fn with_extension_overrides(mut self, overrides: HashMap<String, PathBuf>) -> Self {
+        self.extension_overrides = overrides
+            .into_iter()
+            .map(|(extension, destination)| (fold_extension(&extension), destination))
+            .collect();
+        self
+    }
+
+    /// Classify these extensions (see `detector::extension_table`) before the built-in ones
+    pub fn with_extra_extensions(mut self, extra_extensions: ExtensionTable) -> Self {
+        self.extra_extensions = extra_extensions;
+        self
+    }
+
+    /// Classify extensionless files with these names (see `detector::filename_table`)
+    /// before the built-in names
+    pub fn with_extra_filenames(mut self, extra_filenames: FilenameTable) -> Self {
+        self.extra_filenames = extra_filenames;
+        self
+    }
+
+    /// Classify `.txt` and unrecognized extensionless files as code, documents or
+    /// unknown by sampling their contents
+    pub fn with_text_inspection(mut self, inspect_text: bool) -> Self {
+        self.inspect_text = inspect_text;
+        self
+    }
+
+    /// Send detected files whose MIME type starts with a key (e.g. "application/pdf"
+    /// or "text/") to that key's destination instead of their category's
+    pub fn with_mime_destinations(mut self, mime_destinations: HashMap<String, PathBuf>) -> Self {
+        self.mime_destinations = mime_destinations
+            .into_iter()
+            .map(|(mime, destination)| (mime.to_lowercase(), destination))
+            .collect();
+        self
+    }
+
+    /// Send files downloaded from these domains, or their subdomains, to that
+    /// domain's destination instead of their category's
+    pub fn with_source_routes(mut self, source_routes: HashMap<String, PathBuf>) -> Self {
+        self.source_routes = source_routes
+            .into_iter()
+            .map(|(domain, destination)| (domain.trim_matches('.').to_lowercase(), destination))
+            .collect();
+        self
+    }
+
+    /// Most specific configured domain the file at `path` was downloaded from, with
+    /// its destination; `None` without source routes or recorded download URLs
+    fn match_source_route(&self, path: &Path) -> Option<(&String, &PathBuf)> {
+        if self.source_routes.is_empty() {
+            return None;
+        }
+        let hosts: Vec<String> = download_source::download_urls(path)
+            .iter()
+            .filter_map(|url| download_source::host(url))
+            .collect();
+        self.source_routes
+            .iter()
+            .filter(|(domain, _)| {
+                hosts.iter().any(|host| download_source::host_in_domain(host, domain))
+            })
+            .max_by_key(|(domain, _)| domain.len())
+    }
+
+    /// Longest configured MIME prefix matching `mime`, with its destination
+    fn match_mime_destination(&self, mime: &str) -> Option<(&String, &PathBuf)> {
+        self.mime_destinations
+            .iter()
+            .filter(|(prefix, _)| mime.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+    }
+
+    /// Send files of unknown type to a catch-all destination instead of skipping them
+    pub fn with_unknown_destination(mut self, destination: Option<PathBuf>) -> Self {
+        if let Some(destination) = destination {
+            self.rules.insert(
+                FileCategory::Unknown,
+                CategoryRule {
+                    name: "Unknown".to_string(),
+                    destination,
+                },
+            );
+        }
+        self
+    }
+
+    /// Send disk images somewhere other than the default "Disk Images" folder
+    pub fn with_disk_image_destination(mut self, destination: Option<PathBuf>) -> Self {
+        if let Some(destination) = destination {
+            self.rules.insert(
+                FileCategory::DiskImage,
+                CategoryRule {
+                    name: "Disk Images".to_string(),
+                    destination,
+                },
+            );
+        }
+        self
+    }
+
+    /// Send torrent files somewhere other than the default "Torrents" folder
+    pub fn with_torrent_destination(mut self, destination: Option<PathBuf>) -> Self {
+        if let Some(destination) = destination {
+            self.rules.insert(
+                FileCategory::Torrent,
+                CategoryRule {
+                    name: "Torrents".to_string(),
+                    destination,
+                },
+            );
+        }
+        self
+    }
+
+    /// Find the first user-defined category whose match conditions accept the file
+    pub fn match_custom_category(&self, path: &Path) -> Option<FileCategory> {
+        self.custom_categories
+            .iter()
+            .find(|custom| custom.matcher.matches(path))
+            .map(|custom| FileCategory::Custom(custom.name.clone()))
+    }
 
-        Ok(Self { rules })
+    /// Route files above configured sizes to their override destinations
+    pub fn with_size_rules(mut self, size_rules: Vec<SizeRule>) -> Self {
+        self.size_rules = size_rules;
+        self
     }
 
     pub fn get_destination(&self, category: &FileCategory) -> Option<&PathBuf> {
         self.rules.get(category).map(|rule| &rule.destination)
     }
 
+    /// Every folder a rule can send files to, sorted and without duplicates
+    pub fn destination_roots(&self) -> Vec<PathBuf> {
+        let mut roots: Vec<PathBuf> = self
+            .rules
+            .values()
+            .map(|rule| &rule.destination)
+            .chain(self.extension_overrides.values())
+            .chain(self.mime_destinations.values())
+            .chain(self.source_routes.values())
+            .chain(self.size_rules.iter().map(|rule| &rule.destination))
+            .cloned()
+            .collect();
+        roots.sort();
+        roots.dedup();
+        roots
+    }
+
+    /// Every category with a destination, in the order they are checked: custom
+    /// categories first, then the built-in ones
+    pub fn category_rules(&self) -> Vec<(&FileCategory, &CategoryRule)> {
+        let custom = self
+            .custom_categories
+            .iter()
+            .map(|custom| FileCategory::Custom(custom.name.clone()));
+        let built_in = [
+            FileCategory::Document,
+            FileCategory::Image,
+            FileCategory::Video,
+            FileCategory::Audio,
+            FileCategory::Archive,
+            FileCategory::DiskImage,
+            FileCategory::Code,
+            FileCategory::Font,
+            FileCategory::Subtitle,
+            FileCategory::Torrent,
+            FileCategory::Unknown,
+        ];
+        custom
+            .chain(built_in)
+            .filter_map(|category| self.rules.get_key_value(&category))
+            .collect()
+    }
+
+    /// Decide the category and top-level destination for a file
+    ///
+    /// Rules are applied with a fixed precedence, highest first:
+    /// 0. An extension override decides the destination outright, skipping the rest
+    /// 1. Category: the first matching custom category, else content detection
+    ///    (magic bytes, falling back to the extension)
+    /// 2. Destination: the most specific source rule for the domain the file was
+    ///    downloaded from, else the first matching size rule for that category,
+    ///    else the longest matching MIME rule (detected categories only), else the
+    ///    category's own destination
+    pub fn route(&self, path: &Path, size: u64) -> Result<Route> {
+        let extension = normalized_extension(path);
+        if let Some(destination) = self.extension_overrides.get(&extension) {
+            return Ok(Route {
+                category: FileCategory::Unknown,
+                category_source: CategorySource::ExtensionOverride,
+                mime: None,
+                detection_method: None,
+                destination: Some(destination.clone()),
+                destination_source: DestinationSource::ExtensionOverride { extension },
+            });
+        }
+
+        let (category, category_source, detection) = match self.match_custom_category(path) {
+            Some(custom) => (custom, CategorySource::CustomCategory, None),
+            None => {
+                let detection = FileDetector::detect_with(
+                    path,
+                    &self.extra_extensions,
+                    &self.extra_filenames,
+                    self.inspect_text,
+                )?;
+                (detection.category.clone(), CategorySource::Detected, Some(detection))
+            }
+        };
+        let detection_method = detection.as_ref().map(|detection| detection.method);
+        let mime = detection.and_then(|detection| detection.mime);
+
+        if let Some((domain, destination)) = self.match_source_route(path) {
+            return Ok(Route {
+                category,
+                category_source,
+                mime,
+                detection_method,
+                destination: Some(destination.clone()),
+                destination_source: DestinationSource::SourceRule {
+                    domain: domain.clone(),
+                },
+            });
+        }
+
+        if let Some(rule) = self.size_rules.iter().find(|rule| rule.matches(&category, size)) {
+            return Ok(Route {
+                category,
+                category_source,
+                mime,
+                detection_method,
+                destination: Some(rule.destination.clone()),
+                destination_source: DestinationSource::SizeRule {
+                    larger_than: rule.larger_than,
+                },
+            });
+        }
+
+        if let Some((mime_prefix, destination)) =
+            mime.as_deref().and_then(|mime| self.match_mime_destination(mime))
+        {
+            return Ok(Route {
+                destination: Some(destination.clone()),
+                destination_source: DestinationSource::MimeRule {
+                    mime_prefix: mime_prefix.clone(),
+                },
+                category,
+                category_source,
+                mime,
+                detection_method,
+            });
+        }
+
+        Ok(Route {
+            destination: self.get_destination(&category).cloned(),
+            category,
+            category_source,
+            mime,
+            detection_method,
+            destination_source: DestinationSource::CategoryRule,
+        })
+    }
+
     pub fn _with_custom_rules(rules: HashMap<FileCategory, CategoryRule>) -> Self {
-        Self { rules }
+        Self {
+            rules,
+            size_rules: Vec::new(),
+            custom_categories: Vec::new(),
+            extension_overrides: HashMap::new(),
+            mime_destinations: HashMap::new(),
+            source_routes: HashMap::new(),
+            extra_extensions: ExtensionTable::new(),
+            extra_filenames: FilenameTable::new(),
+            inspect_text: false,
+        }
     }
 
     pub fn ensure_destinations_exist(&self) -> Result<()> {
@@ -92,6 +524,15 @@ impl Categorizer {
                 ))?;
             }
         }
+        for rule in &self.size_rules {
+            if !rule.destination.exists() {
+                log::info!("Creating size rule destination: {:?}", rule.destination);
+                std::fs::create_dir_all(&rule.destination).context(format!(
+                    "Failed to create directory: {:?}",
+                    rule.destination
+                ))?;
+            }
+        }
         Ok(())
     }
 }
@@ -101,3 +542,287 @@ impl Default for Categorizer {
         Self::new().expect("Failed to create default categorizer")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CategoryMatch;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_custom_category_takes_precedence_over_image() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let invoice = temp_dir.path().join("Invoice_March.png");
+        let photo = temp_dir.path().join("beach.png");
+        fs::write(&invoice, b"not really a png")?;
+        fs::write(&photo, b"not really a png")?;
+
+        let categorizer = Categorizer::_with_custom_rules(HashMap::new()).with_custom_categories(vec![
+            CustomCategory {
+                name: "Invoices".to_string(),
+                destination: PathBuf::from("/home/user/Documents/Invoices"),
+                matcher: CategoryMatch {
+                    extensions: vec!["png".to_string(), "pdf".to_string()],
+                    globs: vec!["*invoice*".to_string()],
+                    mime: vec![],
+                },
+            },
+        ]);
+
+        assert_eq!(FileDetector::detect_category(&invoice)?, FileCategory::Image);
+        assert_eq!(
+            categorizer.match_custom_category(&invoice),
+            Some(FileCategory::Custom("Invoices".to_string()))
+        );
+        assert_eq!(
+            categorizer.get_destination(&FileCategory::Custom("Invoices".to_string())),
+            Some(&PathBuf::from("/home/user/Documents/Invoices"))
+        );
+        assert_eq!(categorizer.match_custom_category(&photo), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_rule_threshold() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let video = temp_dir.path().join("movie.mp4");
+        fs::write(&video, b"")?;
+
+        let mut rules = HashMap::new();
+        rules.insert(
+            FileCategory::Video,
+            CategoryRule {
+                name: "Videos".to_string(),
+                destination: PathBuf::from("/home/user/Videos"),
+            },
+        );
+
+        let categorizer = Categorizer::_with_custom_rules(rules).with_size_rules(vec![SizeRule {
+            category: Some(FileCategory::Video),
+            larger_than: 2_000_000_000,
+            destination: PathBuf::from("/mnt/external/Videos"),
+        }]);
+
+        let under = categorizer.route(&video, 2_000_000_000)?;
+        assert_eq!(under.destination, Some(PathBuf::from("/home/user/Videos")));
+        assert_eq!(under.destination_source, DestinationSource::CategoryRule);
+
+        let over = categorizer.route(&video, 2_000_000_001)?;
+        assert_eq!(over.destination, Some(PathBuf::from("/mnt/external/Videos")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_rule_destinations_are_created() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let external = temp_dir.path().join("external").join("Videos");
+        let rules = HashMap::new();
+        let categorizer = Categorizer::_with_custom_rules(rules).with_size_rules(vec![SizeRule {
+            category: None,
+            larger_than: 2_000_000_000,
+            destination: external.clone(),
+        }]);
+
+        categorizer.ensure_destinations_exist()?;
+        assert!(external.is_dir());
+        Ok(())
+    }
+
+    #[test]
+    fn test_route_precedence_and_explanation() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let scan = temp_dir.path().join("invoice_scan.png");
+        fs::write(&scan, b"")?;
+
+        let invoices = FileCategory::Custom("Invoices".to_string());
+        let categorizer = Categorizer::_with_custom_rules(HashMap::new())
+            .with_custom_categories(vec![CustomCategory {
+                name: "Invoices".to_string(),
+                destination: PathBuf::from("/home/user/Invoices"),
+                matcher: CategoryMatch {
+                    globs: vec!["invoice*".to_string()],
+                    ..Default::default()
+                },
+            }])
+            .with_size_rules(vec![
+                SizeRule {
+                    category: Some(invoices.clone()),
+                    larger_than: 100,
+                    destination: PathBuf::from("/mnt/archive/Invoices"),
+                },
+                SizeRule {
+                    category: None,
+                    larger_than: 100,
+                    destination: PathBuf::from("/mnt/archive"),
+                },
+            ]);
+
+        // Custom category beats Image detection, and the first matching size rule wins
+        let route = categorizer.route(&scan, 500)?;
+        assert_eq!(route.category, invoices);
+        assert_eq!(route.category_source, CategorySource::CustomCategory);
+        assert_eq!(route.destination, Some(PathBuf::from("/mnt/archive/Invoices")));
+        assert_eq!(
+            route.explain(),
+            "category 'Invoices' chosen by custom category rule; \
+             destination /mnt/archive/Invoices chosen by size rule (larger than 100 bytes)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_extension_override_bypasses_detection() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let notes = temp_dir.path().join("Notes.TXT");
+        fs::write(&notes, b"plain text")?;
+
+        let mut rules = HashMap::new();
+        rules.insert(
+            FileCategory::Document,
+            CategoryRule {
+                name: "Documents".to_string(),
+                destination: PathBuf::from("/home/user/Documents"),
+            },
+        );
+        let mut overrides = HashMap::new();
+        overrides.insert(".txt".to_string(), PathBuf::from("/home/user/Notes"));
+
+        let categorizer = Categorizer::_with_custom_rules(rules).with_extension_overrides(overrides);
+        assert_eq!(FileDetector::detect_category(&notes)?, FileCategory::Document);
+
+        let route = categorizer.route(&notes, 10)?;
+        assert_eq!(route.destination, Some(PathBuf::from("/home/user/Notes")));
+        assert_eq!(route.category_source, CategorySource::ExtensionOverride);
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_rules_prefer_os_user_dirs() {
+        let home = Path::new("/home/user");
+
+        // Localized/XDG folders win where the OS reports one
+        let rules = Categorizer::default_rules(home, |category| match category {
+            FileCategory::Document | FileCategory::Archive => Some(home.join("Documentos")),
+            _ => None,
+        });
+        assert_eq!(rules[&FileCategory::Document].destination, home.join("Documentos"));
+        assert_eq!(
+            rules[&FileCategory::Archive].destination,
+            home.join("Documentos").join("Archives")
+        );
+
+        // Otherwise fall back to the conventional folders under home
+        let rules = Categorizer::default_rules(home, |_| None);
+        assert_eq!(rules[&FileCategory::Document].destination, home.join("Documents"));
+        assert_eq!(rules[&FileCategory::Image].destination, home.join("Pictures"));
+        assert_eq!(
+            rules[&FileCategory::Archive].destination,
+            home.join("Documents").join("Archives")
+        );
+        assert_eq!(
+            rules[&FileCategory::Subtitle].destination,
+            home.join("Videos").join("Subtitles")
+        );
+        assert_eq!(
+            rules[&FileCategory::DiskImage].destination,
+            home.join("Documents").join("Disk Images")
+        );
+        assert_eq!(
+            rules[&FileCategory::Torrent].destination,
+            home.join("Downloads").join("Torrents")
+        );
+    }
+
+    #[test]
+    fn test_disk_images_route_to_their_own_destination() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let home = Path::new("/home/user");
+        let default_rules = Categorizer::default_rules(home, |_| None);
+        let installers = PathBuf::from("/home/user/Installers");
+        let categorizer = Categorizer::_with_custom_rules(default_rules.clone())
+            .with_disk_image_destination(Some(installers.clone()));
+
+        // A bzip2-compressed .dmg looks like an archive from its magic bytes alone
+        let files = [
+            ("Firefox.dmg", b"BZh91AY&SY".as_slice()),
+            ("ubuntu-24.04.iso", &[0; 64]),
+            ("raspios.IMG", &[0; 64]),
+        ];
+        for (name, contents) in files {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, contents)?;
+            let route = categorizer.route(&path, contents.len() as u64)?;
+            assert_eq!(route.category, FileCategory::DiskImage, "{}", name);
+            assert_eq!(route.destination.as_ref(), Some(&installers), "{}", name);
+        }
+
+        // Without an override they go to the default folder
+        let categorizer = Categorizer::_with_custom_rules(default_rules);
+        let route = categorizer.route(&temp_dir.path().join("raspios.IMG"), 64)?;
+        assert_eq!(route.destination, Some(home.join("Documents").join("Disk Images")));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_source_routing_by_download_domain() -> Result<()> {
+        if !crate::utils::file::xattrs_supported() {
+            return Ok(());
+        }
+        let temp_dir = TempDir::new()?;
+        let archive = temp_dir.path().join("release.zip");
+        fs::write(&archive, b"PK\x03\x04")?;
+        let url = b"https://codeload.github.com/owner/repo/zip/main";
+        xattr::set(&archive, "user.xdg.origin.url", url)?;
+
+        let source_routes = HashMap::from([
+            ("github.com".to_string(), PathBuf::from("/home/user/Projects")),
+            ("codeload.github.com".to_string(), PathBuf::from("/home/user/Projects/Code")),
+            ("mybank.com".to_string(), PathBuf::from("/home/user/Documents/Finance")),
+        ]);
+        let categorizer = Categorizer::_with_custom_rules(HashMap::new())
+            .with_source_routes(source_routes);
+
+        let route = categorizer.route(&archive, 4)?;
+        assert_eq!(route.destination, Some(PathBuf::from("/home/user/Projects/Code")));
+        assert_eq!(
+            route.destination_source,
+            DestinationSource::SourceRule {
+                domain: "codeload.github.com".to_string()
+            }
+        );
+
+        // No recorded source: routed as usual
+        let other = temp_dir.path().join("notes.zip");
+        fs::write(&other, b"PK\x03\x04")?;
+        assert_eq!(categorizer.route(&other, 4)?.destination, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mime_destination_longest_prefix_wins() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        // A PDF with a misleading extension; routing follows the contents
+        let scan = temp_dir.path().join("scan.dat");
+        fs::write(&scan, b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+
+        let mime_destinations = HashMap::from([
+            ("application/".to_string(), PathBuf::from("/home/user/Documents/Apps")),
+            ("application/pdf".to_string(), PathBuf::from("/home/user/Documents/PDFs")),
+        ]);
+        let categorizer = Categorizer::_with_custom_rules(HashMap::new())
+            .with_mime_destinations(mime_destinations);
+
+        let route = categorizer.route(&scan, 16)?;
+        assert_eq!(route.mime.as_deref(), Some("application/pdf"));
+        assert_eq!(route.destination, Some(PathBuf::from("/home/user/Documents/PDFs")));
+        assert_eq!(
+            route.destination_source,
+            DestinationSource::MimeRule {
+                mime_prefix: "application/pdf".to_string()
+            }
+        );
+        Ok(())
+    }
+}
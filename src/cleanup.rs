@@ -0,0 +1,142 @@
+use crate::config::CleanupRule;
+use crate::utils::file::{is_hidden_file, DateSource};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Find files older than their rule's age in every cleanup folder and pass each
+/// to `dispose` (e.g. moving it to the trash), returning the disposed paths
+///
+/// A file's age is that of its `date_source` timestamp. Only regular, non-hidden
+/// files are considered; symlinks and folders are never touched, and rules pointing
+/// at the home folder or a filesystem root are refused.
+pub fn sweep_old_files(
+    rules: &[CleanupRule],
+    date_source: DateSource,
+    mut dispose: impl FnMut(&Path) -> Result<()>,
+) -> Result<Vec<PathBuf>> {
+    let now = SystemTime::now();
+    let mut swept = Vec::new();
+
+    for rule in rules {
+        if !is_safe_folder(&rule.folder) {
+            log::error!("Refusing to clean up {:?}: folder is too broad", rule.folder);
+            continue;
+        }
+        if !rule.folder.is_dir() {
+            log::warn!("Cleanup folder {:?} does not exist, skipping", rule.folder);
+            continue;
+        }
+
+        let max_age = Duration::from_secs(rule.older_than_days * SECONDS_PER_DAY);
+        let expired = expired_files(&rule.folder, rule.recursive, date_source, now, max_age)?;
+        for path in expired {
+            match dispose(&path) {
+                Ok(()) => {
+                    log::info!("Cleaned up {:?}", path);
+                    swept.push(path);
+                }
+                Err(e) => log::warn!("Failed to clean up {:?}: {}", path, e),
+            }
+        }
+    }
+
+    Ok(swept)
+}
+
+/// Regular files under `folder` dated more than `max_age` before `now`
+fn expired_files(
+    folder: &Path,
+    recursive: bool,
+    date_source: DateSource,
+    now: SystemTime,
+    max_age: Duration,
+) -> Result<Vec<PathBuf>> {
+    let mut expired = Vec::new();
+
+    let entries = fs::read_dir(folder).context(format!("Failed to read {:?}", folder))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if is_hidden_file(&path) {
+            continue;
+        }
+
+        // symlink_metadata so links are never followed out of the folder
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        if metadata.is_dir() {
+            if recursive {
+                expired.extend(expired_files(&path, recursive, date_source, now, max_age)?);
+            }
+        } else if metadata.is_file() {
+            let age = date_source
+                .date_of(&path, &metadata)
+                .ok()
+                .and_then(|date| now.duration_since(date).ok());
+            if age.is_some_and(|age| age > max_age) {
+                expired.push(path);
+            }
+        }
+    }
+
+    Ok(expired)
+}
+
+/// Whether a folder is specific enough to sweep automatically
+fn is_safe_folder(folder: &Path) -> bool {
+    folder.parent().is_some() && dirs::home_dir().is_none_or(|home| home != folder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn age_file(path: &Path, days: u64) -> Result<()> {
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(SystemTime::now() - Duration::from_secs(days * SECONDS_PER_DAY))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_old_files_are_swept() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let archive = temp_dir.path().join("Archive");
+        fs::create_dir_all(archive.join("nested"))?;
+
+        let old = archive.join("old.zip");
+        let fresh = archive.join("fresh.zip");
+        let old_nested = archive.join("nested").join("old.pdf");
+        let old_hidden = archive.join(".old-hidden");
+        for path in [&old, &fresh, &old_nested, &old_hidden] {
+            fs::write(path, b"data")?;
+        }
+        age_file(&old, 40)?;
+        age_file(&fresh, 5)?;
+        age_file(&old_nested, 40)?;
+        age_file(&old_hidden, 40)?;
+
+        let rules = vec![CleanupRule {
+            folder: archive.clone(),
+            older_than_days: 30,
+            recursive: false,
+        }];
+        let remove = |path: &Path| Ok(fs::remove_file(path)?);
+        let swept = sweep_old_files(&rules, DateSource::Modified, remove)?;
+
+        assert_eq!(swept, vec![old.clone()]);
+        assert!(!old.exists());
+        assert!(fresh.exists());
+        assert!(old_nested.exists());
+        assert!(old_hidden.exists());
+        Ok(())
+    }
+}
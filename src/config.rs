@@ -1,12 +1,310 @@
+use crate::config_env::apply_env_overrides;
+use crate::config_template::default_config_template;
+use crate::detector::FileCategory;
+use crate::matcher::{FolderCase, SimilarityMetric, TokenMatching};
+use crate::mover::{ConflictStrategy, ConflictSuffix};
+use crate::utils::file::{fold_extension, normalized_extension, DateSource};
+use crate::utils::path::expand_path;
+use crate::watcher::DroppedFolders;
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Where files of unrecognized type are moved; they are left in place when unset
+    #[serde(default)]
+    pub unknown_destination: Option<PathBuf>,
+
+    /// Where disk images (.dmg, .iso, .img) go instead of "Disk Images" in the
+    /// documents folder
+    #[serde(default)]
+    pub disk_image_destination: Option<PathBuf>,
+
+    /// Where torrent files go instead of "Torrents" in the downloads folder
+    #[serde(default)]
+    pub torrent_destination: Option<PathBuf>,
+
+    /// Where macOS app bundles (.app, .framework, ...) go instead of "Applications"
+    /// in the home folder
+    #[serde(default)]
+    pub bundle_destination: Option<PathBuf>,
+
+    /// Organize files already in the watched folder at startup, then keep watching
+    #[serde(default)]
+    pub process_existing: bool,
+
+    /// How long to wait at startup for a missing watched folder to appear (e.g. an
+    /// external drive being mounted) before giving up
+    #[serde(default = "default_watch_dir_wait_secs")]
+    pub watch_dir_wait_secs: u64,
+
+    /// Also watch (and scan) the subfolders of the watched folder
+    #[serde(default)]
+    pub recursive: bool,
+
+    /// Folders inside the watched folder whose files are never organized, besides
+    /// the destinations, which are always left alone; relative to the watched folder
+    #[serde(default)]
+    pub ignore_dirs: Vec<PathBuf>,
+
+    /// What to do with folders dropped into the watched folder
+    #[serde(default)]
+    pub dropped_folders: DroppedFolders,
+
+    /// Names of files or folders marking the folder that holds them as a project,
+    /// kept intact with `dropped_folders = "organize_unless_project"`
+    #[serde(default = "default_project_markers")]
+    pub project_markers: Vec<String>,
+
+    /// How long a dropped folder's contents must stay unchanged before its files
+    /// are organized, so a folder still being copied isn't picked apart
+    #[serde(default = "default_dropped_folder_settle_secs")]
+    pub dropped_folder_settle_secs: u64,
+
+    /// Use the names of the subfolders a file was found in, below the watched
+    /// folder, as extra words when matching destination subfolders
+    #[serde(default)]
+    pub use_source_path_context: bool,
+
+    /// Tag organized files with their category and matched subfolder: Finder tags
+    /// on macOS, `user.autofile.*` extended attributes on Linux
+    #[serde(default)]
+    pub tag_files: bool,
+
+    /// Append each organized file, with when and where it came from, to a
+    /// `.autofile-manifest.jsonl` in the folder it was moved to
+    #[serde(default)]
+    pub folder_manifest: bool,
+
+    /// Match files to subfolders of their destination by name similarity; when off,
+    /// files go straight to top-level destinations and the model is never loaded
+    #[serde(default = "default_true")]
+    pub semantic_matching: bool,
+
+    /// Recreate a file's folders below the watched folder under its destination
+    /// instead of matching a subfolder
+    #[serde(default)]
+    pub preserve_source_structure: bool,
+
+    /// Remove source subfolders below the watched folder once organizing has moved
+    /// everything out of them; folders that were empty already are kept
+    #[serde(default)]
+    pub remove_empty_source_dirs: bool,
+
+    /// Never delete or replace a file: organized files are copied, leaving their
+    /// source in place, converters keep originals and name conflicts are renamed.
+    /// Overrides every setting that would delete something
+    #[serde(default)]
+    pub safe_mode: bool,
+
+    /// Organize symlinks in the watched folder (moving the link, not its target);
+    /// they are skipped otherwise
+    #[serde(default)]
+    pub follow_symlinks: bool,
+
+    /// Files modified more recently than this many seconds ago are retried later
+    #[serde(default)]
+    pub min_file_age_secs: Option<u64>,
+
+    /// Which timestamp dates a file for `min_file_age_secs` and cleanup ages
+    #[serde(default)]
+    pub date_source: DateSource,
+
+    /// Milliseconds to wait between moves into cloud-synced folders, so a batch of
+    /// files doesn't set off a burst of uploads
+    #[serde(default)]
+    pub sync_move_delay_ms: Option<u64>,
+
+    /// Folders treated as cloud-synced besides the detected Dropbox, iCloud Drive,
+    /// OneDrive and Google Drive folders
+    #[serde(default)]
+    pub sync_folders: Vec<PathBuf>,
+
+    /// Retry files that some program still has open, e.g. one writing or mmapping
+    /// them; Linux and macOS only
+    #[serde(default)]
+    pub defer_open_files: bool,
+
+    /// Run slow preprocessing (HEIC conversion, image renaming) only on files that
+    /// will be moved, rather than on every file before detection
+    #[serde(default = "default_true")]
+    pub lazy_preprocessing: bool,
+
+    /// Leave zero-byte files alone; apps often create them before writing content
+    #[serde(default = "default_true")]
+    pub skip_empty_files: bool,
+
+    /// Limit for copies across filesystems in bytes per second (accepts "20MB");
+    /// unlimited when unset
+    #[serde(default, deserialize_with = "deserialize_optional_size")]
+    pub copy_rate_limit: Option<u64>,
+
+    /// Copy across filesystems under a temporary name, renamed once complete
+    #[serde(default)]
+    pub staged_copies: bool,
+
+    /// What to do when the destination already has a file of the same name
+    #[serde(default)]
+    pub conflict_strategy: ConflictStrategy,
+
+    /// Suffix given to renamed files, where `{n}` is the number (e.g. "_v{n}")
+    #[serde(default)]
+    pub conflict_suffix_format: ConflictSuffix,
+
+    /// Category name to the suffix its renamed files get instead
+    #[serde(default)]
+    pub conflict_suffix_formats: HashMap<FileCategory, ConflictSuffix>,
+
+    /// Run semantic subfolder matching inside extension override destinations
+    #[serde(default = "default_true")]
+    pub match_within_overrides: bool,
+
+    /// Only files in these categories (e.g. "image", or a custom category's name)
+    /// are organized; all categories are when empty
+    #[serde(default)]
+    pub enabled_categories: Vec<FileCategory>,
+
+    /// Extension (without the dot) to destination; consulted before any other rule
+    #[serde(default)]
+    pub extension_overrides: HashMap<String, PathBuf>,
+
+    /// MIME type or prefix (e.g. "application/pdf", "text/") to destination
+    /// for detected files; the longest matching prefix wins
+    #[serde(default)]
+    pub mime_destinations: HashMap<String, PathBuf>,
+
+    /// Domain a file was downloaded from (per its browser-set extended attributes)
+    /// to destination; subdomains match too, and the most specific domain wins
+    #[serde(default)]
+    pub source_routing: HashMap<String, PathBuf>,
+
+    #[serde(default)]
+    pub detector: DetectorConfig,
+
     #[serde(default)]
     pub matcher: MatcherConfig,
+
+    /// Where to move files that repeatedly fail to organize; disabled when absent
+    #[serde(default)]
+    pub quarantine: Option<QuarantineConfig>,
+
+    /// Serve Prometheus metrics over HTTP; disabled when absent
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+
+    /// External commands run after each organized file
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    #[serde(default)]
+    pub image_renamer: ImageRenamerConfig,
+
+    /// File photos, screenshots and icons into separate folders within the images
+    /// destination; disabled when absent
+    #[serde(default)]
+    pub image_kinds: Option<ImageKindsConfig>,
+
+    /// Rules for tidying file names as they are moved
+    #[serde(default)]
+    pub normalize_filenames: NormalizeFilenamesConfig,
+
+    /// Skip files identical to ones already organized; disabled when absent
+    #[serde(default)]
+    pub dedup: Option<DedupConfig>,
+
+    /// Gzip files of some types on their way into their destination; disabled when absent
+    #[serde(default)]
+    pub compress: Option<CompressConfig>,
+
+    /// Rules that send files above a size threshold to a different destination
+    /// Evaluated in order; the first matching rule wins
+    #[serde(default)]
+    pub size_rules: Vec<SizeRule>,
+
+    /// User-defined categories, checked in order before the built-in categories
+    #[serde(default)]
+    pub categories: Vec<CustomCategory>,
+
+    /// Folders swept of old files by `autofile cleanup`
+    #[serde(default)]
+    pub cleanup: Vec<CleanupRule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            unknown_destination: None,
+            disk_image_destination: None,
+            torrent_destination: None,
+            bundle_destination: None,
+            process_existing: false,
+            watch_dir_wait_secs: default_watch_dir_wait_secs(),
+            recursive: false,
+            ignore_dirs: Vec::new(),
+            dropped_folders: DroppedFolders::default(),
+            project_markers: default_project_markers(),
+            dropped_folder_settle_secs: default_dropped_folder_settle_secs(),
+            use_source_path_context: false,
+            tag_files: false,
+            folder_manifest: false,
+            semantic_matching: true,
+            preserve_source_structure: false,
+            remove_empty_source_dirs: false,
+            safe_mode: false,
+            follow_symlinks: false,
+            min_file_age_secs: None,
+            date_source: DateSource::default(),
+            sync_move_delay_ms: None,
+            sync_folders: Vec::new(),
+            defer_open_files: false,
+            lazy_preprocessing: true,
+            skip_empty_files: true,
+            copy_rate_limit: None,
+            staged_copies: false,
+            conflict_strategy: ConflictStrategy::default(),
+            conflict_suffix_format: ConflictSuffix::default(),
+            conflict_suffix_formats: HashMap::new(),
+            match_within_overrides: true,
+            enabled_categories: Vec::new(),
+            extension_overrides: HashMap::new(),
+            mime_destinations: HashMap::new(),
+            source_routing: HashMap::new(),
+            detector: DetectorConfig::default(),
+            matcher: MatcherConfig::default(),
+            quarantine: None,
+            metrics: None,
+            hooks: HooksConfig::default(),
+            image_renamer: ImageRenamerConfig::default(),
+            image_kinds: None,
+            normalize_filenames: NormalizeFilenamesConfig::default(),
+            dedup: None,
+            compress: None,
+            size_rules: Vec::new(),
+            categories: Vec::new(),
+            cleanup: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DetectorConfig {
+    /// Extra extensions per category name (e.g. code = ["zig", "nim"]), checked
+    /// before the built-in extension table
+    #[serde(default)]
+    pub extensions: HashMap<String, Vec<String>>,
+
+    /// Names of files without an extension per category name (e.g. code = ["BUILD"]),
+    /// matched case-insensitively before the built-in names like Makefile and LICENSE
+    #[serde(default)]
+    pub filenames: HashMap<String, Vec<String>>,
+
+    /// Tell code, prose and binary data apart by sampling the contents of `.txt` and
+    /// extensionless files, instead of trusting the extension
+    #[serde(default)]
+    pub inspect_text: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,70 +312,918 @@ pub struct MatcherConfig {
     /// Folders to exclude from semantic matching (e.g., "Archive", "Old Files")
     #[serde(default)]
     pub excluded_folders: Vec<String>,
+
+    /// Matches scoring less than this far above the similarity threshold count
+    /// as uncertain and are confirmed with the user in interactive mode
+    #[serde(default = "default_uncertain_band")]
+    pub uncertain_band: f32,
+
+    /// Reuse interactive answers for files whose names differ only by numbers
+    #[serde(default = "default_true")]
+    pub remember_answers: bool,
+
+    /// Words with no semantic signal (e.g. "IMG", "Screenshot", "download")
+    /// Files whose names contain only these words, numbers and hashes skip matching
+    #[serde(default = "default_boilerplate_words")]
+    pub boilerplate_words: Vec<String>,
+
+    /// How many folder levels below a category destination matching may descend
+    /// Unlimited when unset
+    #[serde(default)]
+    pub max_match_depth: Option<usize>,
+
+    /// Only match at folder levels with at least this many candidate subfolders;
+    /// in sparser trees files stay at the current level
+    #[serde(default = "default_min_folders_to_match")]
+    pub min_folders_to_match: usize,
+
+    /// How much higher than its parent folder a subfolder must score for files to
+    /// go into it, on top of the match threshold (e.g. 0.05). When unset, any
+    /// subfolder reaching the threshold is descended into
+    #[serde(default)]
+    pub recursion_margin: Option<f32>,
+
+    /// Also score folders against each word of a file name, so "2023_invoice_acme"
+    /// can match an "Acme" folder. Only the whole name is used when off
+    #[serde(default)]
+    pub token_matching: TokenMatching,
+
+    /// Embedding model, by fastembed name (e.g. "MultilingualE5Small" for folders
+    /// named in languages other than English). Changing it re-embeds every folder
+    #[serde(default = "default_embedding_model")]
+    pub model: String,
+
+    /// Words embedded together with every file name, e.g. "German", for models
+    /// that match better knowing the language of the names. None by default
+    #[serde(default)]
+    pub language_hint: Option<String>,
+
+    /// Similarity a folder must reach for files to be matched into it
+    #[serde(default = "default_similarity_threshold")]
+    pub similarity_threshold: f32,
+
+    /// How file and folder embeddings are compared; the similarity threshold means
+    /// something different under each metric (see `SimilarityMetric`)
+    #[serde(default)]
+    pub similarity_metric: SimilarityMetric,
+
+    /// Keep folder name embeddings in a cache file in the config dir, so restarts
+    /// only embed folders that were added or changed since
+    #[serde(default = "default_true")]
+    pub persist_embeddings: bool,
+
+    /// Categories whose files are matched to subfolders; files of other categories
+    /// go straight to the category destination. All are matched when empty
+    #[serde(default)]
+    pub match_categories: Vec<FileCategory>,
+
+    /// Extra words embedded together with file names of a category (by category
+    /// name, e.g. "code" = "source code project") to nudge matches toward
+    /// folders fitting the category. No hints are used by default
+    #[serde(default)]
+    pub category_hints: HashMap<String, String>,
+
+    /// Whether folders whose names differ only in case ("Invoices", "invoices")
+    /// count as one. Probed per destination by default
+    #[serde(default)]
+    pub folder_case: FolderCase,
+
+    /// Folder names (e.g. "Invoice", "invoices-2023") to treat as another folder
+    /// in the same parent (e.g. "Invoices"), which files matching them go to instead
+    #[serde(default)]
+    pub folder_aliases: HashMap<String, String>,
 }
 
 impl Default for MatcherConfig {
     fn default() -> Self {
         Self {
             excluded_folders: vec![],
+            uncertain_band: default_uncertain_band(),
+            remember_answers: true,
+            boilerplate_words: default_boilerplate_words(),
+            max_match_depth: None,
+            min_folders_to_match: default_min_folders_to_match(),
+            recursion_margin: None,
+            token_matching: TokenMatching::default(),
+            model: default_embedding_model(),
+            language_hint: None,
+            similarity_threshold: default_similarity_threshold(),
+            similarity_metric: SimilarityMetric::default(),
+            persist_embeddings: true,
+            match_categories: Vec::new(),
+            category_hints: HashMap::new(),
+            folder_case: FolderCase::default(),
+            folder_aliases: HashMap::new(),
         }
     }
 }
 
-impl Default for Config {
+fn default_embedding_model() -> String {
+    "BGESmallENV15".to_string()
+}
+
+fn default_min_folders_to_match() -> usize {
+    1
+}
+
+fn default_boilerplate_words() -> Vec<String> {
+    [
+        "img", "image", "dsc", "dcim", "pxl", "vid", "photo", "screenshot", "screen", "shot",
+        "download", "scan", "untitled", "file", "copy", "at",
+    ]
+    .iter()
+    .map(|word| word.to_string())
+    .collect()
+}
+
+fn default_similarity_threshold() -> f32 {
+    0.7
+}
+
+fn default_uncertain_band() -> f32 {
+    0.05
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_project_markers() -> Vec<String> {
+    [".git", "package.json", "Cargo.toml", "pyproject.toml", "go.mod", "Makefile"]
+        .iter()
+        .map(|marker| marker.to_string())
+        .collect()
+}
+
+fn default_dropped_folder_settle_secs() -> u64 {
+    2
+}
+
+fn default_watch_dir_wait_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineConfig {
+    /// Folder that receives failing files and their `.error` descriptions
+    pub destination: PathBuf,
+
+    /// Consecutive failures before a file is quarantined
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+}
+
+fn default_max_failures() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Address to serve `/metrics` on, e.g. "127.0.0.1:9464"
+    pub listen: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HooksConfig {
+    /// Commands run after a file is moved, each as a program followed by its arguments
+    /// The final path is appended as the last argument
+    #[serde(default)]
+    pub post_move: Vec<Vec<String>>,
+
+    /// Commands run after a file lands in a folder (or below it), keyed by folder
+    /// Only the closest configured folder's commands run. A command that moves the
+    /// file may print its new path, which runs that folder's commands in turn
+    #[serde(default)]
+    pub folders: HashMap<PathBuf, Vec<Vec<String>>>,
+
+    /// Seconds a hook may run before it is killed
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
     fn default() -> Self {
         Self {
-            matcher: MatcherConfig::default(),
+            post_move: Vec::new(),
+            folders: HashMap::new(),
+            timeout_secs: default_hook_timeout(),
+        }
+    }
+}
+
+fn default_hook_timeout() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageRenamerConfig {
+    /// Name given to images with generic names and no EXIF metadata, without the
+    /// extension; `{width}`, `{height}` and `{hash}` (short content hash) are filled in
+    #[serde(default = "default_image_name_template")]
+    pub name_template: String,
+}
+
+impl Default for ImageRenamerConfig {
+    fn default() -> Self {
+        Self {
+            name_template: default_image_name_template(),
+        }
+    }
+}
+
+fn default_image_name_template() -> String {
+    "{width}x{height}_{hash}".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageKindsConfig {
+    #[serde(default = "default_photos_folder")]
+    pub photos_folder: String,
+
+    #[serde(default = "default_screenshots_folder")]
+    pub screenshots_folder: String,
+
+    #[serde(default = "default_icons_folder")]
+    pub icons_folder: String,
+
+    /// Square images up to this many pixels wide are icons
+    #[serde(default = "default_max_icon_size")]
+    pub max_icon_size: u32,
+
+    /// File name patterns (case-insensitive) of screenshots
+    #[serde(default = "default_screenshot_globs")]
+    pub screenshot_globs: Vec<String>,
+
+    /// Aspect ratios of screens (e.g. "16:9"); images without camera metadata in
+    /// either orientation of these are screenshots
+    #[serde(default = "default_screenshot_aspect_ratios")]
+    pub screenshot_aspect_ratios: Vec<String>,
+}
+
+impl Default for ImageKindsConfig {
+    fn default() -> Self {
+        Self {
+            photos_folder: default_photos_folder(),
+            screenshots_folder: default_screenshots_folder(),
+            icons_folder: default_icons_folder(),
+            max_icon_size: default_max_icon_size(),
+            screenshot_globs: default_screenshot_globs(),
+            screenshot_aspect_ratios: default_screenshot_aspect_ratios(),
         }
     }
 }
 
+fn default_photos_folder() -> String {
+    "Photos".to_string()
+}
+
+fn default_screenshots_folder() -> String {
+    "Screenshots".to_string()
+}
+
+fn default_icons_folder() -> String {
+    "Icons".to_string()
+}
+
+fn default_max_icon_size() -> u32 {
+    256
+}
+
+fn default_screenshot_globs() -> Vec<String> {
+    ["screenshot*", "screen shot*", "scr_*"]
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .collect()
+}
+
+fn default_screenshot_aspect_ratios() -> Vec<String> {
+    ["16:9", "16:10", "19.5:9"]
+        .iter()
+        .map(|ratio| ratio.to_string())
+        .collect()
+}
+
+/// Changes made to file names when they are moved; all off by default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizeFilenamesConfig {
+    #[serde(default)]
+    pub lowercase: bool,
+
+    /// Replace each run of whitespace with this (e.g. "_")
+    #[serde(default)]
+    pub replace_spaces: Option<String>,
+
+    /// Transliterate to ASCII, so "Résumé" becomes "Resume"
+    #[serde(default)]
+    pub ascii_only: bool,
+
+    /// Apply the same rules to the extension; it keeps its case otherwise
+    #[serde(default = "default_true")]
+    pub extension: bool,
+}
+
+impl Default for NormalizeFilenamesConfig {
+    fn default() -> Self {
+        Self {
+            lowercase: false,
+            replace_spaces: None,
+            ascii_only: false,
+            extension: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DedupConfig {
+    /// Move duplicates to the trash instead of leaving them in place
+    #[serde(default)]
+    pub trash_duplicates: bool,
+
+    /// How many organized files to remember; the oldest are forgotten first
+    #[serde(default = "default_dedup_entries")]
+    pub max_entries: usize,
+
+    /// Where the hash index is stored (defaults to `hashes.jsonl` in the config directory)
+    #[serde(default)]
+    pub index_path: Option<PathBuf>,
+}
+
+fn default_dedup_entries() -> usize {
+    10_000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupRule {
+    /// Folder to sweep
+    pub folder: PathBuf,
+
+    /// Files last modified more than this many days ago are moved to the trash
+    pub older_than_days: u64,
+
+    /// Also sweep files in subfolders
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+/// Types that are already compressed, so gzipping them only costs time
+const INCOMPRESSIBLE_EXTENSIONS: &[&str] = &[
+    "gz", "tgz", "bz2", "xz", "zst", "zip", "7z", "rar", "jpg", "jpeg", "png", "gif", "webp",
+    "heic", "mp3", "m4a", "aac", "ogg", "flac", "mp4", "mkv", "mov", "webm", "pdf", "docx",
+    "xlsx", "pptx", "dmg", "iso",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressConfig {
+    /// Extensions without the dot (e.g. "log") of files to gzip, compared case-insensitively
+    #[serde(default)]
+    pub extensions: Vec<String>,
+
+    /// Categories whose files are gzipped; images, audio, video, archives and disk
+    /// images are already compressed and never are
+    #[serde(default)]
+    pub categories: Vec<FileCategory>,
+
+    /// Move originals to the trash once compressed, rather than deleting them
+    #[serde(default = "default_true")]
+    pub trash_originals: bool,
+}
+
+impl CompressConfig {
+    /// Whether `path`, detected as `category`, should be gzipped when moved
+    pub fn applies(&self, path: &Path, category: &FileCategory) -> bool {
+        let extension = normalized_extension(path);
+        let compressed = INCOMPRESSIBLE_EXTENSIONS.contains(&extension.as_str())
+            || matches!(
+                category,
+                FileCategory::Image
+                    | FileCategory::Video
+                    | FileCategory::Audio
+                    | FileCategory::Archive
+                    | FileCategory::DiskImage
+            );
+        !compressed
+            && (self.categories.contains(category)
+                || self.extensions.iter().any(|listed| fold_extension(listed) == extension))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SizeRule {
+    /// Category this rule applies to; applies to every category when omitted
+    #[serde(default)]
+    pub category: Option<FileCategory>,
+
+    /// Files strictly larger than this many bytes are routed by this rule
+    /// Accepts a byte count or a human-readable size such as "2GB" or "500MiB"
+    #[serde(deserialize_with = "deserialize_size")]
+    pub larger_than: u64,
+
+    /// Destination for files exceeding the threshold
+    pub destination: PathBuf,
+}
+
+impl SizeRule {
+    pub fn matches(&self, category: &FileCategory, size: u64) -> bool {
+        self.category.as_ref().is_none_or(|c| c == category) && size > self.larger_than
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomCategory {
+    /// Category name, used in logs and wherever a category can be referenced in config
+    pub name: String,
+
+    /// Where files in this category are moved
+    pub destination: PathBuf,
+
+    /// Conditions a file must satisfy to belong to this category
+    #[serde(rename = "match", default)]
+    pub matcher: CategoryMatch,
+}
+
+/// Conditions for a custom category
+/// Every non-empty list must have at least one matching entry; an empty match never matches
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CategoryMatch {
+    /// File extensions without the dot (e.g. "pdf"), compared case-insensitively
+    #[serde(default)]
+    pub extensions: Vec<String>,
+
+    /// Glob patterns matched case-insensitively against the file name (e.g. "*invoice*")
+    #[serde(default)]
+    pub globs: Vec<String>,
+
+    /// MIME types or prefixes detected from file contents (e.g. "application/pdf", "image/")
+    #[serde(default)]
+    pub mime: Vec<String>,
+}
+
+impl CategoryMatch {
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() && self.globs.is_empty() && self.mime.is_empty() {
+            return false;
+        }
+
+        if !self.extensions.is_empty() {
+            let extension = normalized_extension(path);
+            if !self.extensions.iter().any(|e| fold_extension(e) == extension) {
+                return false;
+            }
+        }
+
+        if !self.globs.is_empty() {
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let options = glob::MatchOptions {
+                case_sensitive: false,
+                ..Default::default()
+            };
+            let matched = self.globs.iter().any(|pattern| match glob::Pattern::new(pattern) {
+                Ok(pattern) => pattern.matches_with(file_name, options),
+                Err(e) => {
+                    log::warn!("Invalid glob pattern {:?} in custom category: {}", pattern, e);
+                    false
+                }
+            });
+            if !matched {
+                return false;
+            }
+        }
+
+        if !self.mime.is_empty() {
+            let mime = infer::get_from_path(path)
+                .ok()
+                .flatten()
+                .map(|kind| kind.mime_type());
+            match mime {
+                Some(mime) if self.mime.iter().any(|m| mime.starts_with(m.as_str())) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Parse a human-readable size like "2GB", "1.5 MiB" or "1024" into bytes
+/// Decimal units (KB, MB, GB, TB) are powers of 1000, binary units (KiB, ...) powers of 1024
+pub fn parse_size(input: &str) -> Result<u64> {
+    let trimmed = input.trim();
+    let split_at = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split_at);
+
+    let value: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size: {:?}", input))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1_000,
+        "mb" | "m" => 1_000_000,
+        "gb" | "g" => 1_000_000_000,
+        "tb" | "t" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        other => anyhow::bail!("Unknown size unit {:?} in {:?}", other, input),
+    };
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> std::result::Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawSize {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match RawSize::deserialize(deserializer)? {
+        RawSize::Bytes(bytes) => Ok(bytes),
+        RawSize::Text(text) => parse_size(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+fn deserialize_optional_size<'de, D>(deserializer: D) -> std::result::Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Size(#[serde(deserialize_with = "deserialize_size")] u64);
+
+    Ok(Option::<Size>::deserialize(deserializer)?.map(|Size(bytes)| bytes))
+}
+
+/// Profile used when none is selected
+pub const DEFAULT_PROFILE: &str = "default";
+
 impl Config {
-    /// Load configuration from the default location
-    /// If the config file doesn't exist, create it with default values
+    /// Load the active profile's configuration (see `active_profile`)
+    /// If the config file doesn't exist, create it with default values; when it
+    /// can't be created (e.g. a read-only home), the defaults are used unsaved
     pub fn load() -> Result<Self> {
-        let config_path = Self::get_config_path()?;
+        Self::load_profile(&Self::active_profile(None))
+    }
 
-        if !config_path.exists() {
+    /// Load the configuration of the named profile
+    /// Only the default profile is created if missing, so a mistyped name is an error
+    /// rather than a fresh profile; others are created with `autofile init`.
+    pub fn load_profile(name: &str) -> Result<Self> {
+        Self::load_from(&Self::existing_profile_path(&Self::config_dir()?, name)?)
+    }
+
+    /// Profile to use: `selected` (e.g. from `--profile`), else `$AUTOFILE_PROFILE`,
+    /// else `DEFAULT_PROFILE`
+    pub fn active_profile(selected: Option<&str>) -> String {
+        selected
+            .map(str::to_string)
+            .or_else(|| std::env::var("AUTOFILE_PROFILE").ok().filter(|name| !name.is_empty()))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    /// Names of the profiles that exist, sorted
+    pub fn list_profiles() -> Result<Vec<String>> {
+        Self::profiles_in(&Self::config_dir()?)
+    }
+
+    /// `AUTOFILE_*` environment variables override keys of the file (see `config_env`)
+    fn load_from(config_path: &Path) -> Result<Self> {
+        let mut table = if config_path.exists() {
+            let contents = fs::read_to_string(config_path)
+                .context("Failed to read config file")?;
+            log::info!("Loaded config from {:?}", config_path);
+            contents.parse::<toml::Table>().context("Failed to parse config file")?
+        } else {
             log::info!("Config file not found, creating default config at {:?}", config_path);
             let default_config = Config::default();
-            default_config.save()?;
-            return Ok(default_config);
-        }
+            if let Err(e) = default_config.save_to(config_path) {
+                log::warn!("{:#}; running with the default config", e);
+            }
+            toml::Table::try_from(default_config).context("Failed to serialize config")?
+        };
 
-        let contents = fs::read_to_string(&config_path)
-            .context("Failed to read config file")?;
+        let overrides = apply_env_overrides(&mut table, std::env::vars_os())?;
+        let mut config: Config = toml::Value::Table(table).try_into().with_context(|| {
+            if overrides.is_empty() {
+                "Failed to parse config file".to_string()
+            } else {
+                format!("Failed to parse config file with {}", overrides.join(", "))
+            }
+        })?;
+        config.expand_paths();
+        config.check_category_names()?;
+        Ok(config)
+    }
 
-        let config: Config = toml::from_str(&contents)
-            .context("Failed to parse config file")?;
+    /// Refuse custom categories named like a built-in one, which category lists like
+    /// `enabled_categories` couldn't tell apart from it
+    fn check_category_names(&self) -> Result<()> {
+        for custom in &self.categories {
+            if !matches!(FileCategory::from(custom.name.clone()), FileCategory::Custom(_)) {
+                anyhow::bail!(
+                    "Custom category '{}' has the name of a built-in category; rename it",
+                    custom.name
+                );
+            }
+        }
+        Ok(())
+    }
 
-        log::info!("Loaded config from {:?}", config_path);
-        Ok(config)
+    /// Expand `~` and environment variables in every configured path
+    pub fn expand_paths(&mut self) {
+        let expand = |path: &mut PathBuf| *path = expand_path(path);
+
+        self.unknown_destination.iter_mut().for_each(expand);
+        self.ignore_dirs.iter_mut().for_each(expand);
+        self.sync_folders.iter_mut().for_each(expand);
+        self.disk_image_destination.iter_mut().for_each(expand);
+        self.torrent_destination.iter_mut().for_each(expand);
+        self.bundle_destination.iter_mut().for_each(expand);
+        self.extension_overrides.values_mut().for_each(expand);
+        self.mime_destinations.values_mut().for_each(expand);
+        self.source_routing.values_mut().for_each(expand);
+        if let Some(quarantine) = &mut self.quarantine {
+            expand(&mut quarantine.destination);
+        }
+        if let Some(dedup) = &mut self.dedup {
+            dedup.index_path.iter_mut().for_each(expand);
+        }
+        self.size_rules
+            .iter_mut()
+            .for_each(|rule| expand(&mut rule.destination));
+        self.categories
+            .iter_mut()
+            .for_each(|category| expand(&mut category.destination));
+        self.cleanup
+            .iter_mut()
+            .for_each(|rule| expand(&mut rule.folder));
+        self.hooks.folders = std::mem::take(&mut self.hooks.folders)
+            .into_iter()
+            .map(|(folder, commands)| (expand_path(&folder), commands))
+            .collect();
     }
 
-    /// Save configuration to the default location
+    /// Save configuration to the active profile
     pub fn save(&self) -> Result<()> {
-        let config_path = Self::get_config_path()?;
+        self.save_profile(&Self::active_profile(None))
+    }
+
+    /// Save configuration to the named profile
+    pub fn save_profile(&self, name: &str) -> Result<()> {
+        self.save_to(&Self::profile_path(&Self::config_dir()?, name)?)
+    }
 
+    fn save_to(&self, config_path: &Path) -> Result<()> {
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent)
-                .context("Failed to create config directory")?;
+                .context(format!("Failed to create config directory {:?}", parent))?;
         }
 
         let contents = toml::to_string_pretty(self)
             .context("Failed to serialize config")?;
 
-        fs::write(&config_path, contents)
-            .context("Failed to write config file")?;
+        fs::write(config_path, contents)
+            .context(format!("Failed to write config file {:?}", config_path))?;
 
         log::info!("Saved config to {:?}", config_path);
         Ok(())
     }
 
-    /// Get the path to the config file
-    fn get_config_path() -> Result<PathBuf> {
+    /// Write the annotated default config to the named profile's file, refusing
+    /// to replace an existing one unless `force`; returns the file's path
+    pub fn init_profile(name: &str, force: bool) -> Result<PathBuf> {
+        let path = Self::profile_path(&Self::config_dir()?, name)?;
+        Self::init_at(&path, force)?;
+        Ok(path)
+    }
+
+    fn init_at(config_path: &Path, force: bool) -> Result<()> {
+        if config_path.exists() && !force {
+            anyhow::bail!("{:?} already exists; use --force to replace it", config_path);
+        }
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)
+                .context("Failed to create config directory")?;
+        }
+        fs::write(config_path, default_config_template()?)
+            .context("Failed to write config file")?;
+        Ok(())
+    }
+
+    /// Directory holding the config file and AutoFile's persistent state
+    pub fn config_dir() -> Result<PathBuf> {
         let config_dir = dirs::config_dir()
             .context("Could not determine config directory")?;
-        Ok(config_dir.join("autofile").join("config.toml"))
+        Ok(config_dir.join("autofile"))
+    }
+
+    /// Path of a profile's config file: `profiles/<name>.toml` in `config_dir`
+    /// The default profile keeps using an existing `config.toml` from before profiles.
+    fn profile_path(config_dir: &Path, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !name.starts_with('.');
+        if !valid {
+            anyhow::bail!("Invalid profile name '{}'", name);
+        }
+
+        let path = config_dir.join("profiles").join(format!("{}.toml", name));
+        let legacy = config_dir.join("config.toml");
+        if name == DEFAULT_PROFILE && !path.exists() && legacy.exists() {
+            return Ok(legacy);
+        }
+        Ok(path)
+    }
+
+    /// `profile_path`, failing when a profile other than the default doesn't exist
+    fn existing_profile_path(config_dir: &Path, name: &str) -> Result<PathBuf> {
+        let path = Self::profile_path(config_dir, name)?;
+        if name != DEFAULT_PROFILE && !path.exists() {
+            let existing = Self::profiles_in(config_dir)?;
+            let existing = if existing.is_empty() {
+                "none".to_string()
+            } else {
+                existing.join(", ")
+            };
+            anyhow::bail!(
+                "Profile '{}' doesn't exist (existing profiles: {}); create it with \
+                 `autofile init --profile {}`",
+                name,
+                existing,
+                name
+            );
+        }
+        Ok(path)
+    }
+
+    fn profiles_in(config_dir: &Path) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        if let Ok(entries) = fs::read_dir(config_dir.join("profiles")) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "toml") {
+                    if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        let has_default = names.iter().any(|name| name == DEFAULT_PROFILE);
+        if !has_default && config_dir.join("config.toml").exists() {
+            names.push(DEFAULT_PROFILE.to_string());
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_writes_parsable_defaults() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join("profiles").join("default.toml");
+        Config::init_at(&path, false)?;
+
+        let written: Config = toml::from_str(&fs::read_to_string(&path)?)?;
+        assert_eq!(toml::to_string(&written)?, toml::to_string(&Config::default())?);
+
+        // An existing config is only replaced with force
+        fs::write(&path, "recursive = true")?;
+        assert!(Config::init_at(&path, false).is_err());
+        assert_eq!(fs::read_to_string(&path)?, "recursive = true");
+        Config::init_at(&path, true)?;
+        assert!(fs::read_to_string(&path)?.starts_with("# AutoFile configuration"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unwritable_config_dir_falls_back_to_defaults() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        // A file where the config directory should be can't be written into, even by root
+        let blocker = temp_dir.path().join("autofile");
+        fs::write(&blocker, b"")?;
+        let path = blocker.join("profiles").join("default.toml");
+
+        let config = Config::load_from(&path)?;
+        assert_eq!(toml::to_string(&config)?, toml::to_string(&Config::default())?);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_size() -> Result<()> {
+        assert_eq!(parse_size("1024")?, 1024);
+        assert_eq!(parse_size("2GB")?, 2_000_000_000);
+        assert_eq!(parse_size("1.5 MiB")?, 1_572_864);
+        assert!(parse_size("12 parsecs").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflict_suffix_formats_from_toml() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+            [conflict_suffix_formats]
+            document = "_v{n}"
+            "#,
+        )?;
+        assert_eq!(config.conflict_suffix_format, ConflictSuffix::default());
+        assert_eq!(
+            config.conflict_suffix_formats[&FileCategory::Document],
+            ConflictSuffix::new("_v{n}")?
+        );
+
+        let missing_placeholder = toml::from_str::<Config>(r#"conflict_suffix_format = "_copy""#);
+        assert!(missing_placeholder.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_rules_from_toml() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+            copy_rate_limit = "20MB"
+
+            [[size_rules]]
+            category = "video"
+            larger_than = "2GB"
+            destination = "/Volumes/External/Videos"
+            "#,
+        )?;
+
+        let rule = &config.size_rules[0];
+        assert_eq!(rule.category, Some(FileCategory::Video));
+        assert_eq!(rule.larger_than, 2_000_000_000);
+        assert!(rule.matches(&FileCategory::Video, 2_000_000_001));
+        assert!(!rule.matches(&FileCategory::Image, 2_000_000_001));
+        assert_eq!(config.copy_rate_limit, Some(20_000_000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_selected_profile_is_loaded() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let profiles = temp_dir.path().join("profiles");
+        fs::create_dir(&profiles)?;
+        fs::write(profiles.join("laptop.toml"), "min_file_age_secs = 5\n")?;
+        fs::write(profiles.join("media-server.toml"), "min_file_age_secs = 600\n")?;
+
+        let load = |name: &str| {
+            Config::load_from(&Config::existing_profile_path(temp_dir.path(), name)?)
+        };
+        assert_eq!(load("laptop")?.min_file_age_secs, Some(5));
+        assert_eq!(load("media-server")?.min_file_age_secs, Some(600));
+        assert_eq!(Config::profiles_in(temp_dir.path())?, vec!["laptop", "media-server"]);
+
+        // A missing profile is likely a typo, so it isn't created
+        let error = load("labtop").unwrap_err().to_string();
+        assert!(error.contains("existing profiles: laptop, media-server"), "{}", error);
+        assert!(!profiles.join("labtop.toml").exists());
+        // Except for the default one
+        assert_eq!(load(DEFAULT_PROFILE)?.min_file_age_secs, None);
+        assert!(profiles.join("default.toml").exists());
+        assert!(Config::profile_path(temp_dir.path(), "../escape").is_err());
+        assert_eq!(Config::active_profile(Some("laptop")), "laptop");
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_category_named_like_built_in_is_rejected() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join("config.toml");
+        let category = |name: &str| {
+            format!("[[categories]]\nname = \"{}\"\ndestination = \"/tmp/Pictures\"\n", name)
+        };
+
+        fs::write(&path, category("Torrent"))?;
+        let error = Config::load_from(&path).unwrap_err();
+        assert!(error.to_string().contains("name of a built-in category"));
+
+        fs::write(&path, category("Screenshots"))?;
+        assert_eq!(Config::load_from(&path)?.categories[0].name, "Screenshots");
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_profile_keeps_legacy_config() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        fs::write(temp_dir.path().join("config.toml"), "process_existing = true\n")?;
+
+        let path = Config::profile_path(temp_dir.path(), DEFAULT_PROFILE)?;
+        assert_eq!(path, temp_dir.path().join("config.toml"));
+        assert!(Config::load_from(&path)?.process_existing);
+        assert_eq!(Config::profiles_in(temp_dir.path())?, vec![DEFAULT_PROFILE]);
+        Ok(())
     }
 }
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use toml::{Table, Value};
+
+/// How an environment variable's text becomes a config value
+#[derive(Clone, Copy)]
+enum Kind {
+    Bool,
+    Integer,
+    Float,
+    Text,
+    /// Comma-separated, e.g. "Archive,Old Files"
+    List,
+}
+
+/// Environment variables overriding config keys, by dotted key path
+const ENV_OVERRIDES: &[(&str, &str, Kind)] = &[
+    ("AUTOFILE_UNKNOWN_DESTINATION", "unknown_destination", Kind::Text),
+    ("AUTOFILE_PROCESS_EXISTING", "process_existing", Kind::Bool),
+    ("AUTOFILE_RECURSIVE", "recursive", Kind::Bool),
+    ("AUTOFILE_IGNORE_DIRS", "ignore_dirs", Kind::List),
+    ("AUTOFILE_SEMANTIC_MATCHING", "semantic_matching", Kind::Bool),
+    ("AUTOFILE_SAFE_MODE", "safe_mode", Kind::Bool),
+    ("AUTOFILE_FOLLOW_SYMLINKS", "follow_symlinks", Kind::Bool),
+    ("AUTOFILE_MIN_FILE_AGE_SECS", "min_file_age_secs", Kind::Integer),
+    ("AUTOFILE_DATE_SOURCE", "date_source", Kind::Text),
+    ("AUTOFILE_SYNC_MOVE_DELAY_MS", "sync_move_delay_ms", Kind::Integer),
+    ("AUTOFILE_COPY_RATE_LIMIT", "copy_rate_limit", Kind::Text),
+    ("AUTOFILE_CONFLICT_STRATEGY", "conflict_strategy", Kind::Text),
+    ("AUTOFILE_ENABLED_CATEGORIES", "enabled_categories", Kind::List),
+    ("AUTOFILE_SIMILARITY_THRESHOLD", "matcher.similarity_threshold", Kind::Float),
+    ("AUTOFILE_SIMILARITY_METRIC", "matcher.similarity_metric", Kind::Text),
+    ("AUTOFILE_EXCLUDED_FOLDERS", "matcher.excluded_folders", Kind::List),
+    ("AUTOFILE_MAX_MATCH_DEPTH", "matcher.max_match_depth", Kind::Integer),
+    ("AUTOFILE_EMBEDDING_MODEL", "matcher.model", Kind::Text),
+    ("AUTOFILE_LANGUAGE_HINT", "matcher.language_hint", Kind::Text),
+    ("AUTOFILE_METRICS_LISTEN", "metrics.listen", Kind::Text),
+];
+
+impl Kind {
+    fn parse(self, text: &str) -> Result<Value> {
+        let trimmed = text.trim();
+        Ok(match self {
+            Kind::Bool => match trimmed.to_lowercase().as_str() {
+                "true" | "1" | "yes" => Value::Boolean(true),
+                "false" | "0" | "no" => Value::Boolean(false),
+                _ => anyhow::bail!("expected true or false"),
+            },
+            Kind::Integer => Value::Integer(trimmed.parse().context("expected a whole number")?),
+            Kind::Float => Value::Float(trimmed.parse().context("expected a number")?),
+            Kind::Text => Value::String(text.to_string()),
+            Kind::List => Value::Array(
+                text.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            ),
+        })
+    }
+}
+
+/// Override keys of the config `table` with the `AUTOFILE_*` variables among `vars`
+/// that name one, returning the names of the variables applied
+///
+/// Values are parsed by the type of their key, failing on malformed ones, including
+/// ones that aren't UTF-8; whether they are valid for the key (e.g. a known metric)
+/// is checked when the table is deserialized. Other variables may hold anything.
+pub(crate) fn apply_env_overrides(
+    table: &mut Table,
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> Result<Vec<&'static str>> {
+    let vars: Vec<(OsString, OsString)> = vars.into_iter().collect();
+    let mut applied = Vec::new();
+    for (name, path, kind) in ENV_OVERRIDES {
+        let Some((_, text)) = vars.iter().find(|(var, _)| var == name) else {
+            continue;
+        };
+        let text = text
+            .to_str()
+            .with_context(|| format!("Invalid {}={:?}: not valid UTF-8", name, text))?;
+        let value = kind.parse(text).with_context(|| format!("Invalid {}={:?}", name, text))?;
+
+        let mut sections: Vec<&str> = path.split('.').collect();
+        let key = sections.pop().expect("split yields at least one part");
+        let mut section = &mut *table;
+        for part in sections {
+            let entry = section.entry(part).or_insert_with(|| Value::Table(Table::new()));
+            section = entry
+                .as_table_mut()
+                .with_context(|| format!("Config key {} is not a table", part))?;
+        }
+        section.insert(key.to_string(), value);
+        log::info!("Using {} from {} instead of the config file", path, name);
+        applied.push(*name);
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(OsString, OsString)> {
+        pairs.iter().map(|(name, value)| (name.into(), value.into())).collect()
+    }
+
+    #[test]
+    fn test_env_overrides_file_and_defaults() -> Result<()> {
+        let mut table: Table = toml::from_str(
+            r#"
+            recursive = true
+            safe_mode = true
+
+            [matcher]
+            excluded_folders = ["Trash"]
+            "#,
+        )?;
+        let applied = apply_env_overrides(
+            &mut table,
+            vars(&[
+                ("AUTOFILE_SIMILARITY_THRESHOLD", "0.6"),
+                ("AUTOFILE_EXCLUDED_FOLDERS", "Archive, Old Files,"),
+                ("AUTOFILE_SAFE_MODE", "false"),
+                ("AUTOFILE_METRICS_LISTEN", "0.0.0.0:9464"),
+                ("AUTOFILE_UNRELATED", "ignored"),
+            ]),
+        )?;
+        assert_eq!(applied.len(), 4);
+
+        let config: Config = Value::Table(table).try_into()?;
+        // Environment over file
+        assert!(!config.safe_mode);
+        assert_eq!(config.matcher.excluded_folders, ["Archive", "Old Files"]);
+        assert!((config.matcher.similarity_threshold - 0.6).abs() < 1e-6);
+        assert_eq!(config.metrics.map(|metrics| metrics.listen).as_deref(), Some("0.0.0.0:9464"));
+        // File over defaults
+        assert!(config.recursive);
+        assert_eq!(config.matcher.uncertain_band, Config::default().matcher.uncertain_band);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_env_values_are_errors() -> Result<()> {
+        for (name, value) in [
+            ("AUTOFILE_SIMILARITY_THRESHOLD", "high"),
+            ("AUTOFILE_RECURSIVE", "sometimes"),
+            ("AUTOFILE_MIN_FILE_AGE_SECS", "30s"),
+        ] {
+            let error = apply_env_overrides(&mut Table::new(), vars(&[(name, value)])).unwrap_err();
+            assert!(error.to_string().contains(name), "{}", error);
+        }
+
+        // Well-formed but not a valid value for the key
+        let mut table = Table::new();
+        apply_env_overrides(&mut table, vars(&[("AUTOFILE_SIMILARITY_METRIC", "manhattan")]))?;
+        assert!(Value::Table(table).try_into::<Config>().is_err());
+        Ok(())
+    }
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_env_values() -> Result<()> {
+        use std::os::unix::ffi::OsStringExt;
+
+        let invalid = || OsString::from_vec(vec![b'a', 0xff]);
+        // Unrelated variables may hold anything
+        let mut table = Table::new();
+        let unrelated = vec![(OsString::from("LANG_OTHER"), invalid())];
+        assert!(apply_env_overrides(&mut table, unrelated)?.is_empty());
+
+        let ours = vec![(OsString::from("AUTOFILE_RECURSIVE"), invalid())];
+        let error = apply_env_overrides(&mut table, ours).unwrap_err();
+        assert!(error.to_string().contains("AUTOFILE_RECURSIVE"), "{}", error);
+        Ok(())
+    }
+}
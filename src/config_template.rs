@@ -0,0 +1,248 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+
+/// One-line descriptions of config keys and sections, by dotted path
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    ("unknown_destination", "Where files of unrecognized type go; left in place when unset"),
+    ("disk_image_destination", "Where disk images go instead of \"Disk Images\" in documents"),
+    ("torrent_destination", "Where torrent files go instead of \"Torrents\" in downloads"),
+    ("bundle_destination", "Where macOS app bundles go instead of \"Applications\" in home"),
+    ("process_existing", "Organize files already in the watched folder at startup"),
+    ("watch_dir_wait_secs", "Seconds to wait at startup for a missing watched folder"),
+    ("recursive", "Also watch the subfolders of the watched folder"),
+    ("ignore_dirs", "Folders inside the watched folder to leave alone, besides destinations"),
+    ("dropped_folders", "Dropped folders: \"ignore\", \"organize\" or \"organize_unless_project\""),
+    ("project_markers", "Files or folders marking a dropped folder as a project to keep intact"),
+    ("dropped_folder_settle_secs", "Seconds a dropped folder must stay unchanged"),
+    ("use_source_path_context", "Use the subfolders a file was found in as matching context"),
+    ("tag_files", "Tag organized files with their category and matched subfolder"),
+    ("folder_manifest", "List the files placed in each destination in a manifest file there"),
+    ("semantic_matching", "Match files to subfolders of their destination by meaning"),
+    ("preserve_source_structure", "Recreate a file's folders below the watched folder"),
+    ("remove_empty_source_dirs", "Remove source subfolders once everything in them is organized"),
+    ("safe_mode", "Copy files and never delete or replace anything"),
+    ("follow_symlinks", "Organize symlinks (moving the link, not its target)"),
+    ("min_file_age_secs", "Retry files modified more recently than this many seconds ago"),
+    ("date_source", "File dates for age checks: \"modified\", \"created\" or \"accessed\""),
+    ("sync_move_delay_ms", "Milliseconds to wait between moves into cloud-synced folders"),
+    ("sync_folders", "Folders treated as cloud-synced, besides detected Dropbox, iCloud etc."),
+    ("defer_open_files", "Retry files another program still has open (Linux and macOS)"),
+    ("lazy_preprocessing", "Preprocess images only once they are known to be moving"),
+    ("skip_empty_files", "Leave zero-byte placeholder files in place"),
+    ("copy_rate_limit", "Bytes per second for copies to another drive (e.g. \"20MB\")"),
+    ("staged_copies", "Copy to a hidden partial file first, renamed once complete"),
+    (
+        "conflict_strategy",
+        "On name conflicts: \"rename\", \"skip\", \"overwrite\" or \"overwrite_if_newer\"",
+    ),
+    ("conflict_suffix_format", "What \"rename\" appends to the name; {n} is the number"),
+    ("match_within_overrides", "Run subfolder matching inside extension override destinations"),
+    ("enabled_categories", "Only organize these categories; all are organized when empty"),
+    ("size_rules", "Send large files elsewhere, as [[size_rules]] tables"),
+    ("categories", "Custom categories checked before the built-in ones, as [[categories]] tables"),
+    ("cleanup", "Folders swept of old files by `autofile cleanup`, as [[cleanup]] tables"),
+    ("conflict_suffix_formats", "Conflict suffix formats by category name"),
+    ("extension_overrides", "Extensions sent straight to a fixed folder, skipping all rules"),
+    ("mime_destinations", "Destinations by detected MIME type or prefix"),
+    ("source_routing", "Destinations by the domain a file was downloaded from"),
+    ("detector", "How file categories are detected"),
+    ("detector.inspect_text", "Tell code, prose and binary .txt and extensionless files apart"),
+    ("detector.extensions", "Extra extensions per category, checked before the built-in table"),
+    ("detector.filenames", "Names of extensionless files per category"),
+    ("matcher", "Semantic subfolder matching"),
+    ("matcher.excluded_folders", "Folder names never chosen as destinations"),
+    ("matcher.uncertain_band", "With --interactive, confirm matches this close to the threshold"),
+    ("matcher.remember_answers", "Reuse interactive answers for names differing only by numbers"),
+    ("matcher.boilerplate_words", "Names of only these words, numbers and hashes skip matching"),
+    ("matcher.max_match_depth", "How many folder levels below a destination matching descends"),
+    ("matcher.min_folders_to_match", "Skip matching at levels with fewer subfolders than this"),
+    ("matcher.recursion_margin", "How much better than its parent a subfolder must match"),
+    ("matcher.token_matching", "Also score each word of a name: \"off\", \"max\" or \"mean\""),
+    ("matcher.model", "Embedding model by fastembed name, e.g. \"MultilingualE5Small\""),
+    ("matcher.language_hint", "Words embedded with every file name, e.g. \"German\""),
+    ("matcher.similarity_threshold", "Similarity a folder must reach to match"),
+    ("matcher.similarity_metric", "Compare embeddings by \"cosine\", \"dot\" or \"euclidean\""),
+    ("matcher.persist_embeddings", "Keep folder name embeddings across restarts"),
+    ("matcher.match_categories", "Only match subfolders for these categories; all when empty"),
+    ("matcher.folder_case", "Case-only differences: \"auto\", \"sensitive\" or \"insensitive\""),
+    ("matcher.category_hints", "Words embedded with the names of files in a category"),
+    ("matcher.folder_aliases", "Folder names treated as another folder in the same parent"),
+    ("hooks", "Commands run after files are organized, without a shell"),
+    ("hooks.post_move", "Commands run after each move, with the final path appended"),
+    ("hooks.timeout_secs", "Seconds a hook may run before it is killed"),
+    ("hooks.folders", "Commands run when a file lands in a folder, by folder"),
+    ("image_renamer", "Renaming of generic image names without EXIF metadata"),
+    ("image_renamer.name_template", "New name; {width}, {height} and {hash} are filled in"),
+    ("normalize_filenames", "Tidying of file names as they are moved"),
+    ("normalize_filenames.lowercase", "Lowercase names"),
+    ("normalize_filenames.replace_spaces", "Replace each run of whitespace with this"),
+    ("normalize_filenames.ascii_only", "Transliterate names to ASCII"),
+    ("normalize_filenames.extension", "Apply the same rules to the extension"),
+    ("image_kinds", "File photos, screenshots and icons into separate image folders"),
+    ("image_kinds.photos_folder", "Folder for photos with camera EXIF tags"),
+    ("image_kinds.screenshots_folder", "Folder for screenshots"),
+    ("image_kinds.icons_folder", "Folder for icons"),
+    ("image_kinds.max_icon_size", "Square images up to this many pixels wide are icons"),
+    ("image_kinds.screenshot_globs", "File name patterns of screenshots"),
+    ("image_kinds.screenshot_aspect_ratios", "Screen aspect ratios of screenshots"),
+    ("quarantine", "Where files that repeatedly fail to organize are moved"),
+    ("quarantine.destination", "Folder receiving failing files and their .error descriptions"),
+    ("quarantine.max_failures", "Consecutive failures before a file is quarantined"),
+    ("metrics", "Prometheus metrics served at http://<listen>/metrics"),
+    ("metrics.listen", "Address to serve metrics on"),
+    ("dedup", "Skip files identical to ones already organized"),
+    ("dedup.trash_duplicates", "Move duplicates to the trash instead of leaving them"),
+    ("dedup.max_entries", "How many organized files to remember"),
+    ("compress", "Gzip files of some types on their way into their destination"),
+    ("compress.extensions", "Extensions of files to gzip"),
+    ("compress.categories", "Categories of files to gzip"),
+    ("compress.trash_originals", "Trash originals once compressed, rather than deleting them"),
+];
+
+/// Keys without a default value, shown commented out with an example value
+const EXAMPLES: &[(&str, &str)] = &[
+    ("unknown_destination", "\"~/Downloads/Misc\""),
+    ("disk_image_destination", "\"~/Installers\""),
+    ("torrent_destination", "\"~/Torrents\""),
+    ("bundle_destination", "\"/Applications\""),
+    ("min_file_age_secs", "30"),
+    ("sync_move_delay_ms", "500"),
+    ("copy_rate_limit", "\"20MB\""),
+    ("matcher.max_match_depth", "2"),
+    ("matcher.recursion_margin", "0.05"),
+    ("matcher.language_hint", "\"German\""),
+    ("normalize_filenames.replace_spaces", "\"_\""),
+    ("image_kinds.photos_folder", "\"Photos\""),
+    ("image_kinds.screenshots_folder", "\"Screenshots\""),
+    ("image_kinds.icons_folder", "\"Icons\""),
+    ("image_kinds.max_icon_size", "256"),
+    ("image_kinds.screenshot_globs", "[\"screenshot*\", \"screen shot*\", \"scr_*\"]"),
+    ("image_kinds.screenshot_aspect_ratios", "[\"16:9\", \"16:10\", \"19.5:9\"]"),
+    ("quarantine.destination", "\"~/Downloads/Quarantine\""),
+    ("quarantine.max_failures", "3"),
+    ("metrics.listen", "\"127.0.0.1:9464\""),
+    ("dedup.trash_duplicates", "false"),
+    ("dedup.max_entries", "10000"),
+    ("compress.extensions", "[\"log\"]"),
+    ("compress.categories", "[]"),
+    ("compress.trash_originals", "true"),
+];
+
+const HEADER: &str = "\
+# AutoFile configuration, written by `autofile init`
+# Every key is listed with its default value; commented-out keys and sections
+# are unset. See config.example.toml for longer explanations.
+
+";
+
+fn description(key: &str) -> Option<&'static str> {
+    DESCRIPTIONS.iter().find(|(path, _)| *path == key).map(|(_, text)| *text)
+}
+
+/// The default config as TOML, with every key described by a comment and keys
+/// that have no default shown commented out
+pub fn default_config_template() -> Result<String> {
+    let defaults = toml::to_string(&Config::default()).context("Failed to serialize config")?;
+
+    // Sections in order, each with its lines; "" holds the top-level keys
+    let mut sections: Vec<(String, Vec<String>)> = vec![(String::new(), Vec::new())];
+    for line in defaults.lines().filter(|line| !line.trim().is_empty()) {
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            sections.push((name.to_string(), Vec::new()));
+            continue;
+        }
+        let (section, lines) = sections.last_mut().expect("sections start with the top level");
+        let Some((key, value)) = line.split_once(" = ") else {
+            lines.push(line.to_string());
+            continue;
+        };
+        let path = dotted(section, key);
+        push_described(lines, &path, &format!("{} = {}", key, tidy_float(value)));
+    }
+
+    for (path, example) in EXAMPLES {
+        let (section, key) = path.rsplit_once('.').unwrap_or(("", path));
+        let index = match sections.iter().position(|(name, _)| name == section) {
+            Some(index) => index,
+            None => {
+                sections.push((section.to_string(), Vec::new()));
+                sections.len() - 1
+            }
+        };
+        push_described(&mut sections[index].1, path, &format!("# {} = {}", key, example));
+    }
+
+    let mut template = HEADER.to_string();
+    for (name, lines) in &sections {
+        if !name.is_empty() {
+            template.push('\n');
+            if let Some(text) = description(name) {
+                template.push_str(&format!("# {}\n", text));
+            }
+            // Sections with nothing but examples are unset as a whole
+            let unset = lines.iter().all(|line| line.starts_with('#'))
+                && EXAMPLES.iter().any(|(path, _)| path.starts_with(&format!("{}.", name)));
+            let prefix = if unset { "# " } else { "" };
+            template.push_str(&format!("{}[{}]\n", prefix, name));
+        }
+        for line in lines {
+            template.push_str(line);
+            template.push('\n');
+        }
+    }
+    Ok(template)
+}
+
+fn dotted(section: &str, key: &str) -> String {
+    if section.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", section, key)
+    }
+}
+
+fn push_described(lines: &mut Vec<String>, path: &str, line: &str) {
+    if let Some(text) = description(path) {
+        lines.push(format!("# {}", text));
+    }
+    lines.push(line.to_string());
+}
+
+/// `0.05` rather than the `0.05000000074505806` an `f32` widens to
+fn tidy_float(value: &str) -> String {
+    match value.parse::<f64>() {
+        Ok(float) if value.contains('.') => {
+            let narrow = (float as f32).to_string();
+            if narrow.contains('.') {
+                narrow
+            } else {
+                value.to_string()
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_key_is_described() -> Result<()> {
+        let template = default_config_template()?;
+        let mut section = String::new();
+        for line in template.lines() {
+            let line = line.trim_start_matches("# ");
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name.to_string();
+            } else if let Some((key, _)) = line.split_once(" = ") {
+                let path = dotted(&section, key);
+                assert!(description(&path).is_some(), "{} has no description", path);
+            }
+        }
+        assert!(template.contains("\nuncertain_band = 0.05\n"));
+        assert!(template.contains("\n# [quarantine]\n"));
+        assert!(template.contains("\n# destination = \"~/Downloads/Quarantine\"\n"));
+        Ok(())
+    }
+}
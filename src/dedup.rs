@@ -0,0 +1,301 @@
+use crate::config::{Config, DedupConfig};
+use crate::history;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Name of the dedup index in the config dir
+pub const DEDUP_INDEX_FILE: &str = "hashes.jsonl";
+
+/// Index written as a single JSON array by earlier versions, imported when found
+const LEGACY_INDEX_FILE: &str = "hashes.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    hash: String,
+    path: PathBuf,
+}
+
+/// Bounded index of content hashes for files AutoFile has organized
+///
+/// Oldest entries are evicted once `capacity` is reached. When backed by a file,
+/// each insert is appended to it as a JSON line so the index survives restarts;
+/// the file is rewritten with only the remembered entries once evicted lines
+/// make up half of it.
+pub struct DedupIndex {
+    path: Option<PathBuf>,
+    capacity: usize,
+    entries: Mutex<VecDeque<IndexEntry>>,
+    /// Lines in the index file, including those of evicted entries
+    lines: AtomicUsize,
+}
+
+impl DedupIndex {
+    pub fn in_memory(capacity: usize) -> Self {
+        Self {
+            path: None,
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+            lines: AtomicUsize::new(0),
+        }
+    }
+
+    /// Load the index from `path`, starting empty if it doesn't exist or can't be read
+    /// An index in the earlier single-array format is converted.
+    pub fn load(path: PathBuf, capacity: usize) -> Self {
+        let (mut entries, lines, legacy) = match fs::read_to_string(&path) {
+            Ok(contents) => parse_index(&contents, &path),
+            Err(_) => (VecDeque::new(), 0, false),
+        };
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+
+        let index = Self {
+            path: Some(path),
+            capacity,
+            entries: Mutex::new(entries),
+            lines: AtomicUsize::new(lines),
+        };
+        if legacy {
+            if let Err(e) = index.rewrite(&index.entries.lock().unwrap()) {
+                log::warn!("Failed to convert dedup index: {:#}", e);
+            }
+        }
+        index
+    }
+
+    /// Find an organized file with this content hash that still exists
+    pub fn find(&self, hash: &str) -> Option<PathBuf> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .rev()
+            .find(|entry| entry.hash == hash && entry.path.exists())
+            .map(|entry| entry.path.clone())
+    }
+
+    /// Remember that a file with this hash now lives at `path`
+    pub fn insert(&self, hash: String, path: PathBuf) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = IndexEntry { hash, path };
+        entries.push_back(entry.clone());
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+
+        let Some(index_path) = &self.path else {
+            return Ok(());
+        };
+        if self.lines.load(Ordering::SeqCst) >= self.capacity.max(1) * 2 {
+            return self.rewrite(&entries);
+        }
+        if let Some(parent) = index_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create dedup index directory")?;
+        }
+        history::append_entry(index_path, &entry).context("Failed to write dedup index")?;
+        self.lines.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Replace the index file with `entries`, dropping the lines of evicted ones
+    fn rewrite(&self, entries: &VecDeque<IndexEntry>) -> Result<()> {
+        let Some(index_path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = String::new();
+        for entry in entries {
+            let line = serde_json::to_string(entry).context("Failed to serialize dedup index")?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        if let Some(parent) = index_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create dedup index directory")?;
+        }
+        let partial = index_path.with_extension("partial");
+        fs::write(&partial, contents).context("Failed to write dedup index")?;
+        fs::rename(&partial, index_path).context("Failed to replace dedup index")?;
+        self.lines.store(entries.len(), Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Entries of an index file, how many lines it has and whether it is in the legacy
+/// single-array format; unreadable lines are skipped
+fn parse_index(contents: &str, path: &Path) -> (VecDeque<IndexEntry>, usize, bool) {
+    if contents.trim_start().starts_with('[') {
+        let entries = serde_json::from_str(contents).unwrap_or_else(|e| {
+            log::warn!("Ignoring unreadable dedup index {:?}: {}", path, e);
+            VecDeque::new()
+        });
+        return (entries, 0, true);
+    }
+
+    let lines: Vec<&str> = contents.lines().filter(|line| !line.trim().is_empty()).collect();
+    let entries: VecDeque<IndexEntry> =
+        lines.iter().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    if entries.len() < lines.len() {
+        let skipped = lines.len() - entries.len();
+        log::warn!("Skipped {} unreadable line(s) of dedup index {:?}", skipped, path);
+    }
+    (entries, lines.len(), false)
+}
+
+/// Result of checking a file against the dedup index
+pub enum DedupCheck {
+    /// No identical file was organized before; record this hash after moving
+    Unique(String),
+    /// An identical file already lives at this path
+    Duplicate(PathBuf),
+}
+
+/// Detects files identical to ones already organized and applies the duplicate policy
+pub struct Deduplicator {
+    index: DedupIndex,
+    trash_duplicates: bool,
+}
+
+impl Deduplicator {
+    pub fn from_config(config: &DedupConfig) -> Self {
+        let index_path = config.index_path.clone().or_else(|| {
+            let dir = Config::config_dir().ok()?;
+            // Carry over the index of earlier versions, converted on load
+            let legacy = dir.join(LEGACY_INDEX_FILE);
+            let path = dir.join(DEDUP_INDEX_FILE);
+            if !path.exists() && legacy.exists() {
+                if let Err(e) = fs::rename(&legacy, &path) {
+                    log::warn!("Failed to move dedup index {:?}: {}", legacy, e);
+                }
+            }
+            Some(path)
+        });
+
+        let index = match index_path {
+            Some(path) => DedupIndex::load(path, config.max_entries),
+            None => {
+                log::warn!("No location for the dedup index, duplicates are only tracked in memory");
+                DedupIndex::in_memory(config.max_entries)
+            }
+        };
+
+        Self {
+            index,
+            trash_duplicates: config.trash_duplicates,
+        }
+    }
+
+    /// Look for an already organized copy of `path`, applying the duplicate policy
+    /// (leave in place or trash) to `path` if one is found
+    pub fn check(&self, path: &Path) -> Result<DedupCheck> {
+        let hash = hash_file(path)?;
+
+        let existing = match self.index.find(&hash) {
+            Some(existing) => existing,
+            None => return Ok(DedupCheck::Unique(hash)),
+        };
+
+        if self.trash_duplicates {
+            trash::delete(path).context(format!("Failed to trash duplicate {:?}", path))?;
+            log::info!("Trashed {:?}, identical to {:?}", path, existing);
+        } else {
+            log::info!("Leaving {:?} in place, identical to {:?}", path, existing);
+        }
+        Ok(DedupCheck::Duplicate(existing))
+    }
+
+    /// Record the hash of a file that was just organized to `path`
+    pub fn record(&self, hash: String, path: &Path) {
+        if let Err(e) = self.index.insert(hash, path.to_path_buf()) {
+            log::warn!("Failed to update dedup index: {}", e);
+        }
+    }
+}
+
+/// SHA-256 of a file's contents as lowercase hex, read in a streaming fashion
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).context(format!("Failed to open {:?} for hashing", path))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).context("Failed to hash file")?;
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_duplicates_are_recognized_after_a_restart() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = DedupConfig {
+            trash_duplicates: false,
+            max_entries: 100,
+            index_path: Some(temp_dir.path().join("config").join(DEDUP_INDEX_FILE)),
+        };
+        let organized = temp_dir.path().join("Documents").join("manual.pdf");
+        fs::create_dir_all(organized.parent().unwrap())?;
+        fs::write(&organized, b"the manual")?;
+
+        // A previous session organized the file
+        let previous = Deduplicator::from_config(&config);
+        previous.record(hash_file(&organized)?, &organized);
+        drop(previous);
+
+        let download = temp_dir.path().join("manual (1).pdf");
+        fs::write(&download, b"the manual")?;
+        let session = Deduplicator::from_config(&config);
+        let check = session.check(&download)?;
+        assert!(matches!(check, DedupCheck::Duplicate(path) if path == organized));
+        assert!(download.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_file_stays_bounded() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join(DEDUP_INDEX_FILE);
+        let files: Vec<PathBuf> = (0..7).map(|n| temp_dir.path().join(n.to_string())).collect();
+        for file in &files {
+            fs::write(file, file.to_string_lossy().as_bytes())?;
+        }
+
+        let index = DedupIndex::load(path.clone(), 2);
+        for (n, file) in files.iter().enumerate() {
+            index.insert(format!("hash{}", n), file.clone())?;
+        }
+        assert!(fs::read_to_string(&path)?.lines().count() <= 4);
+
+        let reloaded = DedupIndex::load(path, 2);
+        assert_eq!(reloaded.find("hash6"), Some(files[6].clone()));
+        assert_eq!(reloaded.find("hash5"), Some(files[5].clone()));
+        assert_eq!(reloaded.find("hash4"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_index_is_converted() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("report.txt");
+        fs::write(&file, b"numbers")?;
+        let path = temp_dir.path().join("hashes");
+        let legacy = vec![IndexEntry { hash: "abc".to_string(), path: file.clone() }];
+        fs::write(&path, serde_json::to_string(&legacy)?)?;
+
+        let index = DedupIndex::load(path.clone(), 10);
+        assert_eq!(index.find("abc"), Some(file.clone()));
+        index.insert("def".to_string(), file)?;
+        assert_eq!(fs::read_to_string(&path)?.lines().count(), 2);
+        Ok(())
+    }
+}
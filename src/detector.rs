@@ -1,25 +1,308 @@
+use crate::utils::file::{fold_extension, normalized_extension};
 use anyhow::Result;
+use content_inspector::ContentType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum FileCategory {
     Document,
     Image,
     Video,
     Audio,
     Archive,
+    /// Disk images and installers (.dmg, .iso, .img)
+    DiskImage,
     Code,
+    Font,
+    Subtitle,
+    /// BitTorrent metainfo files (.torrent)
+    Torrent,
+    /// macOS bundles like `.app`s, moved whole to the bundle destination
+    Application,
     Unknown,
+    /// A user-defined category from the `[[categories]]` config list
+    Custom(String),
+}
+
+impl FileCategory {
+    /// Config name of this category (e.g. "image"), or the custom category's name
+    pub fn name(&self) -> &str {
+        match self {
+            FileCategory::Document => "document",
+            FileCategory::Image => "image",
+            FileCategory::Video => "video",
+            FileCategory::Audio => "audio",
+            FileCategory::Archive => "archive",
+            FileCategory::DiskImage => "disk_image",
+            FileCategory::Code => "code",
+            FileCategory::Font => "font",
+            FileCategory::Subtitle => "subtitle",
+            FileCategory::Torrent => "torrent",
+            FileCategory::Application => "application",
+            FileCategory::Unknown => "unknown",
+            FileCategory::Custom(name) => name,
+        }
+    }
+}
+
+impl From<String> for FileCategory {
+    fn from(name: String) -> Self {
+        match name.to_lowercase().as_str() {
+            "document" => FileCategory::Document,
+            "image" => FileCategory::Image,
+            "video" => FileCategory::Video,
+            "audio" => FileCategory::Audio,
+            "archive" => FileCategory::Archive,
+            "disk_image" => FileCategory::DiskImage,
+            "code" => FileCategory::Code,
+            "font" => FileCategory::Font,
+            "subtitle" => FileCategory::Subtitle,
+            "torrent" => FileCategory::Torrent,
+            "application" => FileCategory::Application,
+            "unknown" => FileCategory::Unknown,
+            _ => FileCategory::Custom(name),
+        }
+    }
+}
+
+impl From<FileCategory> for String {
+    fn from(category: FileCategory) -> Self {
+        category.name().to_string()
+    }
+}
+
+/// How a file's category was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionMethod {
+    /// Recognized from the file's contents
+    MagicBytes,
+    /// Contents weren't recognized, so the extension decided
+    Extension,
+    /// The text of a `.txt` or extensionless file looked like code, prose or binary data
+    TextContent,
+}
+
+/// What the sampled contents of a file without magic bytes look like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    Code,
+    Prose,
+    Binary,
+}
+
+impl TextKind {
+    fn category(self) -> FileCategory {
+        match self {
+            TextKind::Code => FileCategory::Code,
+            TextKind::Prose => FileCategory::Document,
+            TextKind::Binary => FileCategory::Unknown,
+        }
+    }
+}
+
+/// How much of a file is sampled by `inspect_text`
+const TEXT_SAMPLE_BYTES: usize = 8 * 1024;
+
+/// Words that start a line of code in common languages
+const CODE_KEYWORDS: &[&str] = &[
+    "fn", "pub", "impl", "struct", "enum", "let", "use", "mod", "def", "class", "import",
+    "from", "return", "const", "var", "function", "package", "public", "private", "static",
+    "func", "#include", "#define", "if", "for", "while", "else", "elif", "end",
+];
+
+/// Operators that rarely appear in prose
+const CODE_OPERATORS: &[&str] = &["=>", "->", "::", "==", "!=", "&&", "||", "+=", "</", "/>"];
+
+/// Guess whether `bytes` hold source code, prose or binary data
+///
+/// `content_inspector` tells binary data from text (UTF-8, or UTF-16 and UTF-32
+/// with a byte order mark). Text starting with a shebang is code; otherwise it is
+/// code when enough of its lines look like code: ending in `;`, `{` or `}`,
+/// starting with a keyword or comment, using operators like `=>` or `::`, or
+/// indented (which counts half).
+pub fn inspect_text(bytes: &[u8]) -> TextKind {
+    let sample = &bytes[..bytes.len().min(TEXT_SAMPLE_BYTES)];
+    let text = match content_inspector::inspect(sample) {
+        ContentType::BINARY => return TextKind::Binary,
+        ContentType::UTF_8 | ContentType::UTF_8_BOM => {
+            String::from_utf8_lossy(sample).trim_start_matches('\u{feff}').to_string()
+        }
+        ContentType::UTF_16LE => decode_utf16(&sample[2..], u16::from_le_bytes),
+        ContentType::UTF_16BE => decode_utf16(&sample[2..], u16::from_be_bytes),
+        ContentType::UTF_32LE => decode_utf32(&sample[4..], u32::from_le_bytes),
+        ContentType::UTF_32BE => decode_utf32(&sample[4..], u32::from_be_bytes),
+    };
+    if text.starts_with("#!") {
+        return TextKind::Code;
+    }
+
+    let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+    let score: f32 = lines
+        .iter()
+        .map(|line| {
+            if looks_like_code(line.trim()) {
+                1.0
+            } else if line.starts_with('\t') || line.starts_with("  ") {
+                0.5
+            } else {
+                0.0
+            }
+        })
+        .sum();
+
+    if score >= 2.0 && score >= lines.len() as f32 * 0.3 {
+        TextKind::Code
+    } else {
+        TextKind::Prose
+    }
+}
+
+/// Text of UTF-16 `bytes` following the byte order mark, with `unit` reading each
+/// code unit; characters that don't decode (e.g. cut off by the end of the sample)
+/// are replaced
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Text of UTF-32 `bytes` following the byte order mark, like `decode_utf16`
+fn decode_utf32(bytes: &[u8], unit: fn([u8; 4]) -> u32) -> String {
+    bytes
+        .chunks_exact(4)
+        .map(|quad| char::from_u32(unit([quad[0], quad[1], quad[2], quad[3]])))
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn looks_like_code(line: &str) -> bool {
+    let first_word = line.split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or("");
+    line.ends_with(';')
+        || line.ends_with('{')
+        || line.starts_with('}')
+        || line.starts_with("//")
+        || line.starts_with("/*")
+        || CODE_KEYWORDS.contains(&first_word)
+        || CODE_OPERATORS.iter().any(|operator| line.contains(operator))
+}
+
+/// What content detection found out about a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionResult {
+    pub category: FileCategory,
+    /// MIME type from the file's magic bytes; `None` when only the extension was usable
+    pub mime: Option<String>,
+    pub method: DetectionMethod,
+}
+
+/// Extra extension to category mappings, consulted before the built-in table
+pub type ExtensionTable = HashMap<String, FileCategory>;
+
+/// Lowercase names of extensionless files (e.g. "dockerfile") to their category
+pub type FilenameTable = HashMap<String, FileCategory>;
+
+/// Build an extension table from config's category name -> extensions lists
+/// Fails if an extension is listed under two categories
+pub fn extension_table(extensions: &HashMap<String, Vec<String>>) -> Result<ExtensionTable> {
+    category_table(extensions, "Extension '.", "[detector.extensions]", fold_extension)
+}
+
+/// Build a filename table from config's category name -> file names lists
+/// Fails if a name is listed under two categories
+pub fn filename_table(filenames: &HashMap<String, Vec<String>>) -> Result<FilenameTable> {
+    category_table(filenames, "File name '", "[detector.filenames]", str::to_lowercase)
+}
+
+fn category_table(
+    entries: &HashMap<String, Vec<String>>,
+    label: &str,
+    section: &str,
+    fold: fn(&str) -> String,
+) -> Result<HashMap<String, FileCategory>> {
+    let mut table = HashMap::new();
+    for (category_name, category_entries) in entries {
+        let category = FileCategory::from(category_name.clone());
+        for entry in category_entries {
+            let key = fold(entry);
+            if let Some(existing) = table.insert(key.clone(), category.clone()) {
+                if existing != category {
+                    anyhow::bail!(
+                        "{}{}' is assigned to both '{}' and '{}' in {}",
+                        label,
+                        key,
+                        existing.name(),
+                        category.name(),
+                        section
+                    );
+                }
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// MIME type of BitTorrent metainfo files, which `infer` doesn't know
+const TORRENT_MIME: &str = "application/x-bittorrent";
+
+/// Whether `bytes` start like a torrent file: a bencoded dictionary whose first
+/// (alphabetically sorted) key is its tracker list or, for trackerless torrents,
+/// its info dictionary
+fn is_torrent(bytes: &[u8]) -> bool {
+    [b"d8:announce".as_slice(), b"d13:announce-list", b"d4:infod"]
+        .iter()
+        .any(|magic| bytes.starts_with(magic))
 }
 
 pub struct FileDetector;
 
 impl FileDetector {
     pub fn detect_category(path: &Path) -> Result<FileCategory> {
+        Ok(Self::detect(path)?.category)
+    }
+
+    /// Detect a file's category and MIME type, preferring magic bytes over the extension
+    pub fn detect(path: &Path) -> Result<DetectionResult> {
+        Self::detect_with(path, &ExtensionTable::new(), &FilenameTable::new(), false)
+    }
+
+    /// Like `detect`, but checks `extra_extensions` before the built-in extension table,
+    /// and `extra_filenames` before the built-in names of files without an extension
+    /// With `inspect_text`, `.txt` and unrecognized extensionless files are classified
+    /// by their contents instead (see `inspect_text`).
+    pub fn detect_with(
+        path: &Path,
+        extra_extensions: &ExtensionTable,
+        extra_filenames: &FilenameTable,
+        inspect_text: bool,
+    ) -> Result<DetectionResult> {
+        let by_extension = || Self::detect_by_extension_with(path, extra_extensions, extra_filenames);
+        let by_contents = |bytes: &[u8]| {
+            let inspectable = match path.extension() {
+                Some(_) => {
+                    normalized_extension(path) == "txt" && by_extension() == FileCategory::Document
+                }
+                None => by_extension() == FileCategory::Unknown,
+            };
+            (inspect_text && inspectable).then(|| self::inspect_text(bytes))
+        };
+
         // First try magic bytes detection
-        if let Ok(bytes) = fs::read(path) {
-            if let Some(kind) = infer::get(&bytes) {
+        let bytes = fs::read(path).ok();
+        if let Some(bytes) = &bytes {
+            if is_torrent(bytes) {
+                log::info!("MIME {} | Categorized as: {:?}", TORRENT_MIME, FileCategory::Torrent);
+                return Ok(DetectionResult {
+                    category: FileCategory::Torrent,
+                    mime: Some(TORRENT_MIME.to_string()),
+                    method: DetectionMethod::MagicBytes,
+                });
+            }
+            if let Some(kind) = infer::get(bytes) {
                 let mime_type = kind.mime_type();
                 let matcher_type = kind.matcher_type();
 
@@ -27,10 +310,15 @@ impl FileDetector {
                     infer::MatcherType::Image => FileCategory::Image,
                     infer::MatcherType::Video => FileCategory::Video,
                     infer::MatcherType::Audio => FileCategory::Audio,
+                    // Compressed disk images start like the archive format they use
+                    infer::MatcherType::Archive if by_extension() == FileCategory::DiskImage => {
+                        FileCategory::DiskImage
+                    }
                     infer::MatcherType::Archive => FileCategory::Document,
                     infer::MatcherType::Doc => FileCategory::Document,
-                    infer::MatcherType::Font => FileCategory::Document,
-                    _ => Self::detect_by_extension(path)
+                    infer::MatcherType::Font => FileCategory::Font,
+                    // Text recognized by its start, like a shell script's shebang
+                    _ => by_contents(bytes).map_or_else(by_extension, TextKind::category),
                 };
 
                 log::info!(
@@ -38,23 +326,66 @@ impl FileDetector {
                     mime_type,
                     category
                 );
-                return Ok(category);
+                return Ok(DetectionResult {
+                    category,
+                    mime: Some(mime_type.to_string()),
+                    method: DetectionMethod::MagicBytes,
+                });
             }
         }
 
+        if let Some(kind) = bytes.as_deref().and_then(by_contents) {
+            log::info!("Contents look like {:?}", kind);
+            return Ok(DetectionResult {
+                category: kind.category(),
+                mime: None,
+                method: DetectionMethod::TextContent,
+            });
+        }
+
         // Fallback to extension-based detection
         log::warn!("Could not detect file type by magic bytes, falling back to extension");
-        Ok(Self::detect_by_extension(path))
+        Ok(DetectionResult {
+            category: by_extension(),
+            mime: None,
+            method: DetectionMethod::Extension,
+        })
     }
 
-    fn detect_by_extension(path: &Path) -> FileCategory {
-        let extension = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|s| s.to_lowercase())
-            .unwrap_or_default();
+    fn detect_by_extension_with(
+        path: &Path,
+        extra_extensions: &ExtensionTable,
+        extra_filenames: &FilenameTable,
+    ) -> FileCategory {
+        if path.extension().is_none() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+            if let Some(category) = extra_filenames.get(&name) {
+                return category.clone();
+            }
+            return Self::detect_by_filename(&name);
+        }
+
+        extra_extensions
+            .get(&normalized_extension(path))
+            .cloned()
+            .unwrap_or_else(|| Self::detect_by_extension(path))
+    }
+
+    /// Well-known files without an extension, by lowercase name
+    fn detect_by_filename(name: &str) -> FileCategory {
+        match name {
+            "makefile" | "dockerfile" | "containerfile" | "jenkinsfile" | "vagrantfile"
+            | "gemfile" | "rakefile" | "procfile" | "justfile" => FileCategory::Code,
 
-        match extension.as_str() {
+            "license" | "licence" | "copying" | "readme" | "changelog" | "authors"
+            | "notice" | "todo" => FileCategory::Document,
+
+            _ => FileCategory::Unknown,
+        }
+    }
+
+    fn detect_by_extension(path: &Path) -> FileCategory {
+        match normalized_extension(path).as_str() {
             // Documents
             "pdf" | "doc" | "docx" | "txt" | "rtf" | "odt" | "xls" | "xlsx" | "ppt" | "pptx" | "csv" => {
                 FileCategory::Document
@@ -78,6 +409,17 @@ impl FileDetector {
             // Archives
             "zip" | "rar" | "7z" | "tar" | "gz" | "bz2" | "xz" | "tgz" => FileCategory::Archive,
 
+            // Disk images
+            "dmg" | "iso" | "img" => FileCategory::DiskImage,
+
+            // Fonts
+            "ttf" | "otf" | "woff" | "woff2" => FileCategory::Font,
+
+            // Subtitles
+            "srt" | "ass" | "ssa" | "vtt" | "sub" => FileCategory::Subtitle,
+
+            "torrent" => FileCategory::Torrent,
+
             // Code files
             "rs" | "py" | "js" | "ts" | "go" | "java" | "c" | "cpp" | "h" | "hpp" | "cs"
             | "rb" | "php" | "swift" | "kt" | "scala" | "r" | "m" | "sh" | "bash" | "zsh"
@@ -109,5 +451,173 @@ mod tests {
             FileDetector::detect_by_extension(&PathBuf::from("script.rs")),
             FileCategory::Code
         );
+        assert_eq!(
+            FileDetector::detect_by_extension(&PathBuf::from("Inter-Regular.TTF")),
+            FileCategory::Font
+        );
+        assert_eq!(
+            FileDetector::detect_by_extension(&PathBuf::from("Movie.en.srt")),
+            FileCategory::Subtitle
+        );
+        assert_eq!(
+            FileDetector::detect_by_extension(&PathBuf::from("episode.VTT")),
+            FileCategory::Subtitle
+        );
+        assert_eq!(
+            FileDetector::detect_by_extension(&PathBuf::from("IMG_0001.JPG")),
+            FileCategory::Image
+        );
+        assert_eq!(
+            FileDetector::detect_by_extension(&PathBuf::from("Clip.Mp4")),
+            FileCategory::Video
+        );
+        for name in ["Installer.dmg", "ubuntu-24.04.ISO", "raspios.img"] {
+            assert_eq!(
+                FileDetector::detect_by_extension(&PathBuf::from(name)),
+                FileCategory::DiskImage
+            );
+        }
+    }
+
+    #[test]
+    fn test_configured_extensions() -> Result<()> {
+        let table = extension_table(&HashMap::from([
+            ("code".to_string(), vec!["zig".to_string(), ".NIM".to_string()]),
+            ("document".to_string(), vec!["typ".to_string(), "md".to_string()]),
+        ]))?;
+
+        let by_extension = |name: &str| {
+            FileDetector::detect_by_extension_with(Path::new(name), &table, &FilenameTable::new())
+        };
+        assert_eq!(by_extension("build.zig"), FileCategory::Code);
+        assert_eq!(by_extension("tool.nim"), FileCategory::Code);
+        assert_eq!(by_extension("thesis.typ"), FileCategory::Document);
+        // Configured extensions take precedence over built-ins, which still apply otherwise
+        assert_eq!(by_extension("README.md"), FileCategory::Document);
+        assert_eq!(by_extension("main.rs"), FileCategory::Code);
+
+        let conflicting = extension_table(&HashMap::from([
+            ("code".to_string(), vec!["qmd".to_string()]),
+            ("document".to_string(), vec!["qmd".to_string()]),
+        ]));
+        assert!(conflicting.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extensionless_files() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let dockerfile = temp_dir.path().join("Dockerfile");
+        fs::write(&dockerfile, b"FROM rust:1\n")?;
+        let license = temp_dir.path().join("LICENSE");
+        fs::write(&license, b"MIT License\n")?;
+        let blob = temp_dir.path().join("data");
+        fs::write(&blob, [0x13, 0x37, 0x00, 0xfe, 0x42])?;
+
+        assert_eq!(FileDetector::detect_category(&dockerfile)?, FileCategory::Code);
+        assert_eq!(FileDetector::detect_category(&license)?, FileCategory::Document);
+        assert_eq!(FileDetector::detect_category(&blob)?, FileCategory::Unknown);
+
+        let names = filename_table(&HashMap::from([(
+            "code".to_string(),
+            vec!["BUILD".to_string(), "Data".to_string()],
+        )]))?;
+        let detection = FileDetector::detect_with(&blob, &ExtensionTable::new(), &names, false)?;
+        assert_eq!(detection.category, FileCategory::Code);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detection_method() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let png = temp_dir.path().join("pixel.png");
+        fs::write(&png, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+        let text = temp_dir.path().join("notes.txt");
+        fs::write(&text, b"plain text has no magic bytes")?;
+
+        let detection = FileDetector::detect(&png)?;
+        assert_eq!(detection.method, DetectionMethod::MagicBytes);
+        assert_eq!(detection.mime.as_deref(), Some("image/png"));
+
+        let detection = FileDetector::detect(&text)?;
+        assert_eq!(detection.method, DetectionMethod::Extension);
+        assert_eq!(detection.category, FileCategory::Document);
+        assert_eq!(detection.mime, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_inspection() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let snippet = temp_dir.path().join("snippet.txt");
+        fs::write(
+            &snippet,
+            "use std::fs;\n\nfn main() {\n    \
+             let text = fs::read_to_string(\"in.txt\").unwrap();\n    \
+             println!(\"{}\", text.len());\n}\n",
+        )?;
+        let prose = temp_dir.path().join("letter.txt");
+        fs::write(
+            &prose,
+            "Dear neighbours,\n\nThe garden party moves to Saturday because of the forecast. \
+             Bring a chair if you can; we will have food and drinks for everyone.\n\n\
+             See you there!\n",
+        )?;
+        let script = temp_dir.path().join("backup");
+        fs::write(&script, "#!/bin/sh\nrsync -a ~/Documents /Volumes/Backup\n")?;
+
+        let detect = |path: &Path| {
+            FileDetector::detect_with(path, &ExtensionTable::new(), &FilenameTable::new(), true)
+        };
+        let detection = detect(&snippet)?;
+        assert_eq!(detection.category, FileCategory::Code);
+        assert_eq!(detection.method, DetectionMethod::TextContent);
+        assert_eq!(detect(&prose)?.category, FileCategory::Document);
+        assert_eq!(detect(&script)?.category, FileCategory::Code);
+
+        // Off by default
+        assert_eq!(FileDetector::detect_category(&snippet)?, FileCategory::Document);
+        assert_eq!(inspect_text(&[0x13, 0x37, 0x00, 0xfe]), TextKind::Binary);
+
+        // UTF-16 text, as saved by some Windows editors, is inspected like UTF-8
+        let utf16 = |text: &str| -> Vec<u8> {
+            let units = text.encode_utf16().flat_map(|unit| unit.to_le_bytes());
+            [0xFF, 0xFE].into_iter().chain(units).collect()
+        };
+        let code = fs::read_to_string(&snippet)?;
+        let letter = fs::read_to_string(&prose)?;
+        assert_eq!(inspect_text(&utf16(&code)), TextKind::Code);
+        assert_eq!(inspect_text(&utf16(&letter)), TextKind::Prose);
+        Ok(())
+    }
+
+    #[test]
+    fn test_font_magic_bytes() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        // TrueType header without a telling extension
+        let font = temp_dir.path().join("download");
+        fs::write(&font, [0x00, 0x01, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x80])?;
+
+        assert_eq!(FileDetector::detect_category(&font)?, FileCategory::Font);
+        Ok(())
+    }
+
+    #[test]
+    fn test_torrent_detection() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let metainfo = b"d8:announce25:http://t.example/announce4:infod4:name8:file.isoee";
+        let by_extension = temp_dir.path().join("ubuntu.torrent");
+        fs::write(&by_extension, b"not actually bencoded")?;
+        let by_contents = temp_dir.path().join("[download]");
+        fs::write(&by_contents, metainfo)?;
+        let trackerless = temp_dir.path().join("dht.bin");
+        fs::write(&trackerless, b"d4:infod4:name8:file.isoee")?;
+
+        assert_eq!(FileDetector::detect_category(&by_extension)?, FileCategory::Torrent);
+        let detection = FileDetector::detect(&by_contents)?;
+        assert_eq!(detection.category, FileCategory::Torrent);
+        assert_eq!(detection.mime.as_deref(), Some("application/x-bittorrent"));
+        assert_eq!(FileDetector::detect_category(&trackerless)?, FileCategory::Torrent);
+        Ok(())
     }
 }
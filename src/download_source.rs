@@ -0,0 +1,158 @@
+use std::path::Path;
+
+/// Set by browsers on Linux that follow the freedesktop.org convention
+#[cfg(unix)]
+const XDG_ORIGIN_ATTR: &str = "user.xdg.origin.url";
+
+/// Set by macOS on downloads: a binary property list of the file's URL, then the page's
+#[cfg(unix)]
+const WHERE_FROMS_ATTR: &str = "com.apple.metadata:kMDItemWhereFroms";
+
+/// URLs the browser recorded for the download of the file at `path`: the file's
+/// own URL and, on macOS, the page it was linked from; empty if none were recorded
+#[cfg(unix)]
+pub fn download_urls(path: &Path) -> Vec<String> {
+    if let Ok(Some(url)) = xattr::get(path, XDG_ORIGIN_ATTR) {
+        return String::from_utf8(url).into_iter().collect();
+    }
+    match xattr::get(path, WHERE_FROMS_ATTR) {
+        Ok(Some(where_froms)) => plist_strings(&where_froms).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// URLs recorded for the download of the file at `path`; extended attributes
+/// aren't read on this platform, so always empty
+#[cfg(not(unix))]
+pub fn download_urls(_path: &Path) -> Vec<String> {
+    Vec::new()
+}
+
+/// Lowercase host name of an http(s) URL, without port or credentials
+pub fn host(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = host_port.split(':').next()?.trim_end_matches('.');
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+/// Whether `host` is `domain` or one of its subdomains
+pub fn host_in_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+/// Every string object in a binary property list ("bplist00"), in object order
+/// Only strings are decoded; `None` if the data isn't a well-formed bplist.
+pub(crate) fn plist_strings(data: &[u8]) -> Option<Vec<String>> {
+    if !data.starts_with(b"bplist00") || data.len() < 8 + 32 {
+        return None;
+    }
+    let trailer = &data[data.len() - 32..];
+    let offset_size = trailer[6] as usize;
+    let object_count = be_uint(&trailer[8..16])? as usize;
+    let table_start = be_uint(&trailer[24..32])? as usize;
+
+    let mut strings = Vec::new();
+    for i in 0..object_count {
+        let entry = table_start.checked_add(i.checked_mul(offset_size)?)?;
+        let offset = be_uint(data.get(entry..entry.checked_add(offset_size)?)?)? as usize;
+        let marker = *data.get(offset)?;
+        let (kind, short_len) = (marker >> 4, (marker & 0x0F) as usize);
+        if kind != 0x5 && kind != 0x6 {
+            continue;
+        }
+
+        // Lengths of 15 or more follow the marker as an integer object
+        let (len, start) = if short_len == 0x0F {
+            let int_marker = *data.get(offset + 1)?;
+            if int_marker >> 4 != 0x1 {
+                return None;
+            }
+            let int_size = 1usize << (int_marker & 0x0F);
+            let len = be_uint(data.get(offset + 2..offset + 2 + int_size)?)? as usize;
+            (len, offset + 2 + int_size)
+        } else {
+            (short_len, offset + 1)
+        };
+
+        if kind == 0x5 {
+            let bytes = data.get(start..start.checked_add(len)?)?;
+            strings.push(String::from_utf8_lossy(bytes).into_owned());
+        } else {
+            let bytes = data.get(start..start.checked_add(len.checked_mul(2)?)?)?;
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            strings.push(String::from_utf16_lossy(&units));
+        }
+    }
+    Some(strings)
+}
+
+/// Big-endian unsigned integer of up to 8 bytes
+fn be_uint(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |value, &byte| value << 8 | byte as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bplist holding an array of two ASCII strings, the way macOS stores WhereFroms
+    fn where_froms(file_url: &str, page_url: &str) -> Vec<u8> {
+        let mut data = b"bplist00".to_vec();
+        let mut offsets = vec![data.len()];
+        // Array of objects 1 and 2, with 1-byte object references
+        data.extend([0xA2, 1, 2]);
+        for url in [file_url, page_url] {
+            offsets.push(data.len());
+            data.extend([0x5F, 0x10, url.len() as u8]);
+            data.extend(url.as_bytes());
+        }
+        let table_start = data.len();
+        data.extend(offsets.iter().map(|&offset| offset as u8));
+
+        let mut trailer = [0u8; 32];
+        trailer[6] = 1;
+        trailer[7] = 1;
+        trailer[8..16].copy_from_slice(&(offsets.len() as u64).to_be_bytes());
+        trailer[24..32].copy_from_slice(&(table_start as u64).to_be_bytes());
+        data.extend(trailer);
+        data
+    }
+
+    #[test]
+    fn test_plist_strings() {
+        let data = where_froms(
+            "https://objects.githubusercontent.com/release.zip",
+            "https://github.com/owner/repo/releases",
+        );
+        assert_eq!(
+            plist_strings(&data),
+            Some(vec![
+                "https://objects.githubusercontent.com/release.zip".to_string(),
+                "https://github.com/owner/repo/releases".to_string(),
+            ])
+        );
+        assert_eq!(plist_strings(b"not a plist"), None);
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(host("https://GitHub.com/owner/repo").as_deref(), Some("github.com"));
+        assert_eq!(host("http://user:pw@bank.example:8443?x=1").as_deref(), Some("bank.example"));
+        assert_eq!(host("ftp://files.example/a"), None);
+        assert!(host_in_domain("api.github.com", "github.com"));
+        assert!(!host_in_domain("notgithub.com", "github.com"));
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Name of the embedding cache file in the config dir
+pub const EMBEDDING_CACHE_FILE: &str = "embeddings.json";
+
+/// Shortest time between writes of the cache file by `save_soon`
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFolder {
+    path: PathBuf,
+    /// The text that was embedded; entries from earlier versions have none and
+    /// are embedded again
+    #[serde(default)]
+    name: String,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    model: String,
+    folders: Vec<CachedFolder>,
+}
+
+/// Embeddings of folder names, keyed by folder path and valid while the embedded
+/// name is unchanged
+///
+/// When backed by a file, entries survive restarts: changes are written at most
+/// every `SAVE_INTERVAL` and when the cache is dropped. The file is tied to one
+/// model; embeddings of another model are discarded on load.
+pub struct EmbeddingCache {
+    path: Option<PathBuf>,
+    model: String,
+    entries: Mutex<HashMap<PathBuf, CachedFolder>>,
+    dirty: AtomicBool,
+    /// When the file was last written by `save_soon`; `None` until the first write
+    last_saved: Mutex<Option<Instant>>,
+}
+
+impl EmbeddingCache {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            model: String::new(),
+            entries: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+            last_saved: Mutex::new(None),
+        }
+    }
+
+    /// Load the embeddings `model` computed from `path`, starting empty if it doesn't
+    /// exist, can't be read or belongs to another model
+    /// Entries of folders that no longer exist are dropped.
+    pub fn load(path: PathBuf, model: &str) -> Self {
+        let cached = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str::<CacheFile>(&contents).unwrap_or_else(|e| {
+                log::warn!("Ignoring unreadable embedding cache {:?}: {}", path, e);
+                CacheFile { model: model.to_string(), folders: Vec::new() }
+            }),
+            Err(_) => CacheFile { model: model.to_string(), folders: Vec::new() },
+        };
+
+        let entries: HashMap<PathBuf, CachedFolder> = if cached.model == model {
+            cached
+                .folders
+                .into_iter()
+                .filter(|folder| folder.path.is_dir())
+                .map(|folder| (folder.path.clone(), folder))
+                .collect()
+        } else {
+            log::info!("Embedding cache is for model {}, starting over", cached.model);
+            HashMap::new()
+        };
+        log::debug!("Loaded {} cached folder embeddings from {:?}", entries.len(), path);
+
+        Self {
+            path: Some(path),
+            model: model.to_string(),
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+            last_saved: Mutex::new(None),
+        }
+    }
+
+    /// The cached embedding of `folder`, if it was computed from `name`
+    pub fn get(&self, folder: &Path, name: &str) -> Option<Vec<f32>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(folder)
+            .filter(|cached| cached.name == name)
+            .map(|cached| cached.embedding.clone())
+    }
+
+    /// Remember the embedding of `folder` computed from `name`
+    pub fn insert(&self, folder: &Path, name: &str, embedding: Vec<f32>) {
+        let path = folder.to_path_buf();
+        let entry = CachedFolder { path: path.clone(), name: name.to_string(), embedding };
+        self.entries.lock().unwrap().insert(path, entry);
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Write the cache to its file if it changed, unless it was written less than
+    /// `SAVE_INTERVAL` ago; changes left unwritten are saved on drop
+    pub fn save_soon(&self) -> Result<()> {
+        let mut last_saved = self.last_saved.lock().unwrap();
+        if last_saved.is_some_and(|saved| saved.elapsed() < SAVE_INTERVAL) {
+            return Ok(());
+        }
+        *last_saved = Some(Instant::now());
+        self.save()
+    }
+
+    /// Write the cache to its file if it changed since it was loaded or last saved
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let contents = {
+            let entries = self.entries.lock().unwrap();
+            let cached = CacheFile {
+                model: self.model.clone(),
+                folders: entries.values().cloned().collect(),
+            };
+            serde_json::to_string(&cached).context("Failed to serialize embedding cache")?
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create embedding cache directory")?;
+        }
+
+        // Replace the file whole, so a reader never sees it half written
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, contents).context("Failed to write embedding cache")?;
+        fs::rename(&partial, path).context("Failed to replace embedding cache")?;
+        Ok(())
+    }
+}
+
+impl Drop for EmbeddingCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save folder embeddings: {:#}", e);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Machine-readable record of what happened to one processed file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OrganizeEvent {
+    /// Path the file was picked up from
+    pub input: PathBuf,
+    /// "moved", "skipped", "deferred", "quarantined", "duplicate" or "error"
+    pub outcome: String,
+    /// Detected category name
+    pub category: Option<String>,
+    /// MIME type detected from the file's contents
+    pub mime: Option<String>,
+    /// Top-level destination chosen by the routing rules
+    pub destination: Option<PathBuf>,
+    /// Subfolder chosen by semantic matching, if any
+    pub matched_folder: Option<PathBuf>,
+    /// Similarity of the matched subfolder
+    pub similarity: Option<f32>,
+    /// Where the file ended up
+    pub final_path: Option<PathBuf>,
+    /// Error message when organizing failed
+    pub error: Option<String>,
+}
+
+impl OrganizeEvent {
+    pub fn new(input: &Path) -> Self {
+        Self {
+            input: input.to_path_buf(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Receives an event for every file the organizer processes
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: &OrganizeEvent);
+}
+
+/// Writes each event as one line of JSON
+pub struct JsonLinesSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLinesSink {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Emit events on stdout, leaving stderr for the human-readable logs
+    pub fn stdout() -> Self {
+        Self::new(Box::new(std::io::stdout()))
+    }
+
+    /// Emit events to a listening Unix socket
+    #[cfg(unix)]
+    pub fn unix_socket(path: &Path) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        let stream = std::os::unix::net::UnixStream::connect(path)
+            .with_context(|| format!("Failed to connect to event socket {:?}", path))?;
+        Ok(Self::new(Box::new(stream)))
+    }
+}
+
+impl EventSink for JsonLinesSink {
+    fn emit(&self, event: &OrganizeEvent) {
+        let line = match serde_json::to_string(event) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to serialize event: {}", e);
+                return;
+            }
+        };
+
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+            log::error!("Failed to write event: {}", e);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// What moves need to know about a file or folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileInfo {
+    pub len: u64,
+    pub modified: SystemTime,
+    pub is_dir: bool,
+}
+
+/// The filesystem operations `FileMover` performs, so moves can be tested without a disk
+///
+/// Copies go through `open` and `create` so they can be throttled and report progress.
+pub trait FileSystem: Send + Sync {
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Fails when `from` and `to` are on different filesystems
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>>;
+
+    /// Create or truncate the file at `path` for writing
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write>>;
+
+    /// Give `to` the same permissions as `from`
+    fn copy_permissions(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    fn metadata(&self, path: &Path) -> io::Result<FileInfo>;
+
+    /// Paths of the entries directly inside `path`, in no particular order
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Bytes that can still be written to the filesystem holding `path`
+    fn available_space(&self, path: &Path) -> io::Result<u64>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok_and(|info| !info.is_dir)
+    }
+}
+
+/// The actual disk
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(fs::File::create(path)?))
+    }
+
+    fn copy_permissions(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::set_permissions(to, fs::metadata(from)?.permissions())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileInfo> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileInfo {
+            len: metadata.len(),
+            modified: metadata.modified()?,
+            is_dir: metadata.is_dir(),
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        fs2::available_space(path)
+    }
+}
+
+#[cfg(test)]
+pub use memory::MemoryFileSystem;
+
+#[cfg(test)]
+mod memory {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    enum Entry {
+        File { contents: Vec<u8>, modified: SystemTime },
+        Dir,
+    }
+
+    type Entries = Arc<Mutex<BTreeMap<PathBuf, Entry>>>;
+
+    /// In-memory filesystem for tests; paths under different mount points count as
+    /// different filesystems, so renames between them fail like they would on disk
+    #[derive(Default)]
+    pub struct MemoryFileSystem {
+        entries: Entries,
+        mounts: Vec<PathBuf>,
+        /// Free bytes per mount point; unlimited for those not listed
+        available: HashMap<PathBuf, u64>,
+    }
+
+    impl MemoryFileSystem {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Treat `path` and everything below it as a separate filesystem
+        pub fn with_mount(mut self, path: impl Into<PathBuf>) -> Self {
+            let path = path.into();
+            self.entries.lock().unwrap().insert(path.clone(), Entry::Dir);
+            self.mounts.push(path);
+            self
+        }
+
+        /// Report only `bytes` free on the filesystem mounted at `mount`
+        pub fn with_available_space(mut self, mount: impl Into<PathBuf>, bytes: u64) -> Self {
+            self.available.insert(mount.into(), bytes);
+            self
+        }
+
+        /// Add a file, along with any missing parent folders
+        pub fn add_file(&self, path: impl AsRef<Path>, contents: &[u8], modified: SystemTime) {
+            let path = path.as_ref();
+            if let Some(parent) = path.parent() {
+                self.create_dir_all(parent).unwrap();
+            }
+            let file = Entry::File {
+                contents: contents.to_vec(),
+                modified,
+            };
+            self.entries.lock().unwrap().insert(path.to_path_buf(), file);
+        }
+
+        /// Contents of the file at `path`, if there is one
+        pub fn contents(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+            match self.entries.lock().unwrap().get(path.as_ref()) {
+                Some(Entry::File { contents, .. }) => Some(contents.clone()),
+                _ => None,
+            }
+        }
+
+        /// Innermost mount point containing `path`
+        fn mount_of(&self, path: &Path) -> Option<&Path> {
+            self.mounts
+                .iter()
+                .filter(|mount| path.starts_with(mount))
+                .max_by_key(|mount| mount.components().count())
+                .map(PathBuf::as_path)
+        }
+
+        fn require_parent(entries: &BTreeMap<PathBuf, Entry>, path: &Path) -> io::Result<()> {
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => match entries.get(parent) {
+                    Some(Entry::Dir) => Ok(()),
+                    _ => Err(io::ErrorKind::NotFound.into()),
+                },
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Writes straight into the shared entries, so partial writes are visible
+    struct MemoryWriter {
+        entries: Entries,
+        path: PathBuf,
+    }
+
+    impl Write for MemoryWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match self.entries.lock().unwrap().get_mut(&self.path) {
+                Some(Entry::File { contents, modified }) => {
+                    contents.extend_from_slice(buf);
+                    *modified = SystemTime::now();
+                    Ok(buf.len())
+                }
+                _ => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl FileSystem for MemoryFileSystem {
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            let mut entries = self.entries.lock().unwrap();
+            for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+                match entries.get(dir) {
+                    Some(Entry::File { .. }) => return Err(io::ErrorKind::AlreadyExists.into()),
+                    Some(Entry::Dir) => {}
+                    None => {
+                        entries.insert(dir.to_path_buf(), Entry::Dir);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            if self.mount_of(from) != self.mount_of(to) {
+                return Err(io::ErrorKind::CrossesDevices.into());
+            }
+            let mut entries = self.entries.lock().unwrap();
+            Self::require_parent(&entries, to)?;
+            match entries.get(from) {
+                Some(Entry::File { .. }) => {}
+                Some(Entry::Dir) => return Err(io::ErrorKind::IsADirectory.into()),
+                None => return Err(io::ErrorKind::NotFound.into()),
+            }
+            let file = entries.remove(from).unwrap();
+            entries.insert(to.to_path_buf(), file);
+            Ok(())
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+            let contents = self.contents(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(Box::new(io::Cursor::new(contents)))
+        }
+
+        fn create(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+            let mut entries = self.entries.lock().unwrap();
+            Self::require_parent(&entries, path)?;
+            if let Some(Entry::Dir) = entries.get(path) {
+                return Err(io::ErrorKind::IsADirectory.into());
+            }
+            let file = Entry::File {
+                contents: Vec::new(),
+                modified: SystemTime::now(),
+            };
+            entries.insert(path.to_path_buf(), file);
+            Ok(Box::new(MemoryWriter {
+                entries: Arc::clone(&self.entries),
+                path: path.to_path_buf(),
+            }))
+        }
+
+        fn copy_permissions(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.metadata(from)?;
+            self.metadata(to)?;
+            Ok(())
+        }
+
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.get(path) {
+                Some(Entry::File { .. }) => {
+                    entries.remove(path);
+                    Ok(())
+                }
+                Some(Entry::Dir) => Err(io::ErrorKind::IsADirectory.into()),
+                None => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<FileInfo> {
+            match self.entries.lock().unwrap().get(path) {
+                Some(Entry::File { contents, modified }) => Ok(FileInfo {
+                    len: contents.len() as u64,
+                    modified: *modified,
+                    is_dir: false,
+                }),
+                Some(Entry::Dir) => Ok(FileInfo {
+                    len: 0,
+                    modified: SystemTime::UNIX_EPOCH,
+                    is_dir: true,
+                }),
+                None => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            let entries = self.entries.lock().unwrap();
+            match entries.get(path) {
+                Some(Entry::Dir) => Ok(entries
+                    .keys()
+                    .filter(|entry| entry.parent() == Some(path))
+                    .cloned()
+                    .collect()),
+                Some(Entry::File { .. }) => Err(io::ErrorKind::NotADirectory.into()),
+                None => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+
+        fn available_space(&self, path: &Path) -> io::Result<u64> {
+            let mount = self.mount_of(path).map(Path::to_path_buf).unwrap_or_default();
+            Ok(self.available.get(&mount).copied().unwrap_or(u64::MAX))
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the move history in the config directory
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// Size past which the history is rotated to a `.1` file, replacing the previous
+/// one; keeps it from growing without bound and `recent` from reading all of it
+const MAX_HISTORY_BYTES: u64 = 1024 * 1024;
+
+/// Name of the manifest of files placed in a destination folder, kept in that folder
+pub const MANIFEST_FILE: &str = ".autofile-manifest.jsonl";
+
+/// One organized file, as recorded in the move history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Seconds since the Unix epoch
+    pub moved_at: u64,
+    /// Where the file was picked up from
+    pub source: PathBuf,
+    /// Where it ended up
+    pub destination: PathBuf,
+}
+
+/// Append-only log of the moves the organizer made, one JSON object per line
+///
+/// Once the file reaches `max_bytes` it is renamed with a `.1` suffix and a new
+/// one is started, so at most the last two files' worth of moves is kept.
+pub struct MoveHistory {
+    path: PathBuf,
+    max_bytes: u64,
+    /// Keeps lines written from different threads whole
+    write_lock: Mutex<()>,
+    /// Set once a write failed, e.g. in a read-only config directory
+    unwritable: AtomicBool,
+}
+
+impl MoveHistory {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            max_bytes: MAX_HISTORY_BYTES,
+            write_lock: Mutex::new(()),
+            unwritable: AtomicBool::new(false),
+        }
+    }
+
+    /// Add a move of `source` to `destination`, made just now
+    /// After a failed write no further moves are recorded, so the failure is only
+    /// reported once.
+    pub fn record(&self, source: &Path, destination: &Path) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        if self.unwritable.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let write = || -> Result<()> {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent).context("Failed to create history directory")?;
+            }
+            if fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() >= self.max_bytes) {
+                fs::rename(&self.path, self.rotated_path())
+                    .context("Failed to rotate move history")?;
+            }
+            append_entry(&self.path, &HistoryEntry::now(source, destination))
+        };
+        let written = write();
+        if written.is_err() {
+            self.unwritable.store(true, Ordering::SeqCst);
+        }
+        written.context(format!(
+            "Failed to write move history {:?}, not recording moves this run",
+            self.path
+        ))
+    }
+
+    /// The last `limit` moves, most recent first; empty if none were recorded
+    pub fn recent(&self, limit: usize) -> Result<Vec<HistoryEntry>> {
+        let mut entries = read_entries_newest_first(&self.path, limit)?;
+        if entries.len() < limit {
+            let older = read_entries_newest_first(&self.rotated_path(), limit - entries.len())?;
+            entries.extend(older);
+        }
+        Ok(entries)
+    }
+
+    /// Where the history is moved to when rotated
+    fn rotated_path(&self) -> PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        PathBuf::from(rotated)
+    }
+}
+
+/// Up to `limit` entries of the history file at `path`, last line first; empty if
+/// the file doesn't exist
+fn read_entries_newest_first(path: &Path, limit: usize) -> Result<Vec<HistoryEntry>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Failed to read move history {:?}", path)),
+    };
+    // A line cut short by a crash is skipped rather than failing the whole read
+    Ok(contents
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .take(limit)
+        .collect())
+}
+
+impl HistoryEntry {
+    fn now(source: &Path, destination: &Path) -> Self {
+        Self {
+            moved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        }
+    }
+}
+
+/// Add the move of `source` to `destination` to the manifest in the folder it
+/// landed in, which lists every file placed there
+pub fn record_in_manifest(source: &Path, destination: &Path) -> Result<()> {
+    let manifest = destination.with_file_name(MANIFEST_FILE);
+    append_entry(&manifest, &HistoryEntry::now(source, destination))
+        .context(format!("Failed to write manifest {:?}", manifest))
+}
+
+/// Append `entry` as one line, holding an exclusive lock on the file (released
+/// when it is closed) so lines written by other threads or AutoFile instances stay whole
+pub(crate) fn append_entry(path: &Path, entry: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.lock_exclusive()?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Seconds since the Unix epoch as "2024-01-31 13:45:00 UTC"
+pub fn format_timestamp(secs: u64) -> String {
+    let (days, time) = (secs / 86400, secs % 86400);
+
+    // Days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwritable_history_fails_once() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let blocker = temp_dir.path().join("autofile");
+        fs::write(&blocker, b"")?;
+        let history = MoveHistory::new(blocker.join(HISTORY_FILE));
+
+        let (source, destination) = (Path::new("/tmp/a.pdf"), Path::new("/tmp/docs/a.pdf"));
+        assert!(history.record(source, destination).is_err());
+        // Later moves aren't recorded, and don't report the same failure again
+        assert!(history.record(source, destination).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_is_rotated_and_still_listed() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join(HISTORY_FILE);
+        let history = MoveHistory {
+            max_bytes: 300,
+            ..MoveHistory::new(path.clone())
+        };
+
+        for n in 0..10 {
+            let name = format!("{}.pdf", n);
+            history.record(&Path::new("/tmp").join(&name), &Path::new("/tmp/docs").join(&name))?;
+        }
+        // Only the current and the rotated file are kept, each about max_bytes
+        assert!(fs::metadata(&path)?.len() <= 300 + 100);
+        assert!(fs::metadata(history.rotated_path())?.len() <= 300 + 100);
+
+        let recent = history.recent(4)?;
+        let names: Vec<_> = recent.iter().map(|entry| entry.source.clone()).collect();
+        let expected: Vec<_> = (6..10)
+            .rev()
+            .map(|n| PathBuf::from(format!("/tmp/{}.pdf", n)))
+            .collect();
+        assert_eq!(names, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_706_708_700), "2024-01-31 13:45:00 UTC");
+    }
+}
@@ -0,0 +1,293 @@
+use crate::config::HooksConfig;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a running hook is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Most folders one file's folder hooks may pass it through
+const MAX_FOLDER_CHAIN: usize = 8;
+
+/// A file that was just organized, as described to hooks
+#[derive(Debug, Clone)]
+pub struct MovedFile {
+    pub source: PathBuf,
+    pub final_path: PathBuf,
+    pub category: String,
+}
+
+impl MovedFile {
+    pub fn new(source: &Path, final_path: &Path, category: &str) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            final_path: final_path.to_path_buf(),
+            category: category.to_string(),
+        }
+    }
+}
+
+/// Folders with the commands run for files landing in them, deepest first
+#[derive(Clone, Default)]
+struct FolderHooks(Arc<Vec<(PathBuf, Vec<Vec<String>>)>>);
+
+/// Runs the configured `post_move` and folder commands in the background
+pub struct PostMoveHooks {
+    commands: Vec<Vec<String>>,
+    folders: FolderHooks,
+    timeout: Duration,
+}
+
+impl PostMoveHooks {
+    pub fn from_config(config: &HooksConfig) -> Self {
+        let non_empty = |commands: &Vec<Vec<String>>| -> Vec<Vec<String>> {
+            commands.iter().filter(|command| !command.is_empty()).cloned().collect()
+        };
+        let mut folders: Vec<(PathBuf, Vec<Vec<String>>)> = config
+            .folders
+            .iter()
+            .map(|(folder, commands)| (folder.clone(), non_empty(commands)))
+            .collect();
+        folders.sort_by_key(|(folder, _)| std::cmp::Reverse(folder.components().count()));
+
+        Self {
+            commands: non_empty(&config.post_move),
+            folders: FolderHooks(Arc::new(folders)),
+            timeout: Duration::from_secs(config.timeout_secs),
+        }
+    }
+
+    /// Start every hook for `moved` without waiting for them
+    /// Failures are logged and never affect the organize result
+    pub fn run(&self, moved: &MovedFile) {
+        if self.folders.commands_for(&moved.final_path).is_some() {
+            let (folders, moved, timeout) = (self.folders.clone(), moved.clone(), self.timeout);
+            std::thread::spawn(move || folders.run_chain(moved, timeout));
+        }
+
+        for command in &self.commands {
+            let command = command.clone();
+            let moved = moved.clone();
+            let timeout = self.timeout;
+            std::thread::spawn(move || match run_hook(&command, &moved, timeout) {
+                Ok(status) if status.success() => {
+                    log::debug!("Hook {:?} finished for {:?}", command[0], moved.final_path)
+                }
+                Ok(status) => log::warn!(
+                    "Hook {:?} exited with {} for {:?}",
+                    command[0],
+                    status,
+                    moved.final_path
+                ),
+                Err(e) => log::warn!("Hook {:?} failed for {:?}: {}", command[0], moved.final_path, e),
+            });
+        }
+    }
+}
+
+impl FolderHooks {
+    /// The closest configured folder containing `path`, with its commands
+    fn commands_for(&self, path: &Path) -> Option<&(PathBuf, Vec<Vec<String>>)> {
+        self.0.iter().find(|(folder, _)| path.starts_with(folder))
+    }
+
+    /// Run the commands of the folder `moved` landed in, one after another, then
+    /// those of the folder a command reports having moved it to, and so on
+    /// A folder the file already passed through ends the chain, so hooks moving
+    /// files back and forth can't run forever.
+    fn run_chain(&self, mut moved: MovedFile, timeout: Duration) {
+        let mut visited = HashSet::new();
+        while let Some((folder, commands)) = self.commands_for(&moved.final_path) {
+            if !visited.insert(folder) {
+                let path = &moved.final_path;
+                log::warn!("{:?} is back in {:?}, not running its hooks again", path, folder);
+                return;
+            }
+            if visited.len() > MAX_FOLDER_CHAIN {
+                let path = &moved.final_path;
+                let limit = MAX_FOLDER_CHAIN;
+                log::warn!("Stopping folder hooks for {:?} after {} folders", path, limit);
+                return;
+            }
+
+            let mut moved_to = None;
+            for command in commands {
+                match run_folder_hook(command, &moved, timeout) {
+                    Ok((status, _)) if !status.success() => log::warn!(
+                        "Folder hook {:?} exited with {} for {:?}",
+                        command[0],
+                        status,
+                        moved.final_path
+                    ),
+                    Ok((_, Some(path))) => moved_to = Some(path),
+                    Ok((_, None)) => {}
+                    Err(e) => log::warn!(
+                        "Folder hook {:?} failed for {:?}: {}",
+                        command[0],
+                        moved.final_path,
+                        e
+                    ),
+                }
+                if moved_to.is_some() {
+                    break;
+                }
+            }
+
+            let Some(path) = moved_to else {
+                return;
+            };
+            log::info!("Folder hook moved {:?} to {:?}", moved.final_path, path);
+            moved.final_path = path;
+        }
+    }
+}
+
+/// Run one folder hook like `run_hook`, also returning the path it printed if it
+/// moved the file, i.e. if its output names an existing file other than the one given
+fn run_folder_hook(
+    command: &[String],
+    moved: &MovedFile,
+    timeout: Duration,
+) -> Result<(ExitStatus, Option<PathBuf>)> {
+    let mut child = hook_command(command, moved)?
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start hook {:?}", command[0]))?;
+
+    // Read concurrently, so a chatty hook can't fill the pipe and stall
+    let mut stdout = child.stdout.take().context("Hook output unavailable")?;
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+    let status = wait_for_hook(&mut child, timeout)?;
+    let output = reader.join().unwrap_or_default();
+
+    let reported = output.lines().rev().find(|line| !line.trim().is_empty());
+    let moved_to = reported
+        .map(|line| PathBuf::from(line.trim()))
+        .filter(|path| *path != moved.final_path && path.is_file());
+    Ok((status, moved_to))
+}
+
+/// The program of a hook, with the final path appended as its last argument and
+/// the details also available as `AUTOFILE_*` env vars
+fn hook_command(command: &[String], moved: &MovedFile) -> Result<Command> {
+    let (program, args) = command.split_first().context("Empty hook command")?;
+    let mut hook = Command::new(program);
+    hook.args(args)
+        .arg(&moved.final_path)
+        .env("AUTOFILE_PATH", &moved.final_path)
+        .env("AUTOFILE_SOURCE", &moved.source)
+        .env("AUTOFILE_CATEGORY", &moved.category)
+        .stdin(Stdio::null());
+    Ok(hook)
+}
+
+/// Run one hook to completion, killing it after `timeout`
+///
+/// The program is spawned directly (no shell), with the final path appended as
+/// its last argument and the details also available as `AUTOFILE_*` env vars.
+fn run_hook(command: &[String], moved: &MovedFile, timeout: Duration) -> Result<ExitStatus> {
+    let mut child = hook_command(command, moved)?
+        .spawn()
+        .with_context(|| format!("Failed to start hook {:?}", command[0]))?;
+    wait_for_hook(&mut child, timeout)
+}
+
+/// Wait for a hook to exit, killing it after `timeout`
+fn wait_for_hook(child: &mut Child, timeout: Duration) -> Result<ExitStatus> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("timed out after {:?}", timeout);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hook_receives_path_as_argument() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output = temp_dir.path().join("received.txt");
+        let final_path = temp_dir.path().join("Documents").join("it's $(not) a shell.txt");
+        let moved = MovedFile::new(Path::new("/downloads/report.txt"), &final_path, "document");
+
+        // The script writes its first argument and the category env var
+        let script = format!(
+            "printf '%s|%s' \"$1\" \"$AUTOFILE_CATEGORY\" > '{}'",
+            output.display()
+        );
+        let command = vec!["sh".to_string(), "-c".to_string(), script, "hook".to_string()];
+
+        let status = run_hook(&command, &moved, Duration::from_secs(5))?;
+        assert!(status.success());
+        assert_eq!(
+            fs::read_to_string(&output)?,
+            format!("{}|document", final_path.display())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_folder_hooks_follow_file_and_stop_on_loops() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let (invoices, paid) = (temp_dir.path().join("Invoices"), temp_dir.path().join("Paid"));
+        fs::create_dir_all(&invoices)?;
+        fs::create_dir_all(&paid)?;
+        let log = temp_dir.path().join("log.txt");
+        let file = invoices.join("acme.pdf");
+        fs::write(&file, b"content")?;
+
+        // Each folder's hook logs the path, moves the file to the other folder and
+        // prints where it went
+        let mover = |to: &Path| {
+            let script = format!(
+                "echo \"$1\" >> '{}'; mv \"$1\" '{}'; echo '{}'",
+                log.display(),
+                to.display(),
+                to.join("acme.pdf").display()
+            );
+            vec![vec!["sh".to_string(), "-c".to_string(), script, "hook".to_string()]]
+        };
+        let config = HooksConfig {
+            folders: [(invoices.clone(), mover(&paid)), (paid.clone(), mover(&invoices))].into(),
+            ..Default::default()
+        };
+        let hooks = PostMoveHooks::from_config(&config);
+
+        let moved = MovedFile::new(Path::new("/downloads/acme.pdf"), &file, "document");
+        hooks.folders.run_chain(moved, Duration::from_secs(5));
+
+        // Invoices, then Paid; back in Invoices the chain stops
+        let expected = format!("{}\n{}\n", file.display(), paid.join("acme.pdf").display());
+        assert_eq!(fs::read_to_string(&log)?, expected);
+        assert!(file.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_hook_is_killed() {
+        let moved = MovedFile::new(Path::new("/a"), Path::new("/b"), "document");
+        // The appended path becomes $0 of the inline script
+        let command = vec!["sh".to_string(), "-c".to_string(), "sleep 5".to_string()];
+
+        assert!(run_hook(&command, &moved, Duration::from_millis(100)).is_err());
+    }
+}
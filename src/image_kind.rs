@@ -0,0 +1,191 @@
+use crate::config::ImageKindsConfig;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Allowed relative difference between an image's aspect ratio and a screen's
+const ASPECT_RATIO_TOLERANCE: f32 = 0.01;
+
+/// What an image looks like it is, judging by its name, size and metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Photo,
+    Screenshot,
+    Icon,
+}
+
+/// Sorts images into photos, screenshots and icons, each with its own folder
+pub struct ImageClassifier {
+    config: ImageKindsConfig,
+    screenshot_globs: Vec<glob::Pattern>,
+    /// Long side over short side of common screens
+    screen_ratios: Vec<f32>,
+}
+
+impl ImageClassifier {
+    /// Invalid patterns and ratios are logged and ignored
+    pub fn from_config(config: &ImageKindsConfig) -> Self {
+        let screenshot_globs = config
+            .screenshot_globs
+            .iter()
+            .filter_map(|pattern| match glob::Pattern::new(pattern) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    log::warn!("Invalid screenshot glob pattern {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        let screen_ratios = config
+            .screenshot_aspect_ratios
+            .iter()
+            .filter_map(|ratio| {
+                let parsed = parse_ratio(ratio);
+                if parsed.is_none() {
+                    log::warn!("Invalid screenshot aspect ratio {:?}, expected e.g. \"16:9\"", ratio);
+                }
+                parsed
+            })
+            .collect();
+
+        Self {
+            config: config.clone(),
+            screenshot_globs,
+            screen_ratios,
+        }
+    }
+
+    /// Kind of the image at `path`, or `None` if it doesn't clearly fit one
+    ///
+    /// Checked in order: a screenshot-like name, small square dimensions (icon),
+    /// camera EXIF tags (photo), then a screen's aspect ratio (screenshot).
+    pub fn classify(&self, path: &Path) -> Option<ImageKind> {
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        let options = glob::MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        if self.screenshot_globs.iter().any(|glob| glob.matches_with(file_name, options)) {
+            return Some(ImageKind::Screenshot);
+        }
+
+        let (width, height) = image::ImageReader::open(path)
+            .ok()?
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?;
+        if width == height && width <= self.config.max_icon_size {
+            return Some(ImageKind::Icon);
+        }
+        if has_camera_tags(path) {
+            return Some(ImageKind::Photo);
+        }
+
+        let ratio = width.max(height) as f32 / width.min(height).max(1) as f32;
+        let is_screen = |screen: &f32| (ratio - screen).abs() / screen <= ASPECT_RATIO_TOLERANCE;
+        self.screen_ratios.iter().any(is_screen).then_some(ImageKind::Screenshot)
+    }
+
+    /// Folder within the images destination for `kind`
+    pub fn folder(&self, kind: ImageKind, images: &Path) -> PathBuf {
+        images.join(match kind {
+            ImageKind::Photo => &self.config.photos_folder,
+            ImageKind::Screenshot => &self.config.screenshots_folder,
+            ImageKind::Icon => &self.config.icons_folder,
+        })
+    }
+}
+
+/// Whether the image's EXIF metadata names the camera that took it
+fn has_camera_tags(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut BufReader::new(file)) else {
+        return false;
+    };
+    [exif::Tag::Make, exif::Tag::Model]
+        .into_iter()
+        .any(|tag| exif.get_field(tag, exif::In::PRIMARY).is_some())
+}
+
+/// "16:9" or "19.5:9" as the long side over the short side
+fn parse_ratio(ratio: &str) -> Option<f32> {
+    let (a, b) = ratio.split_once(':')?;
+    let (a, b): (f32, f32) = (a.trim().parse().ok()?, b.trim().parse().ok()?);
+    (a > 0.0 && b > 0.0).then(|| a.max(b) / a.min(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{RgbImage, RgbaImage};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A JPEG with a minimal EXIF block naming the camera maker
+    fn camera_photo(path: &Path) -> anyhow::Result<()> {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new(&mut jpeg).encode_image(&RgbImage::new(40, 30))?;
+
+        // Little-endian TIFF header, then one IFD entry: Make (0x010F), ASCII, "Canon"
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend(0x010Fu16.to_le_bytes());
+        tiff.extend(2u16.to_le_bytes());
+        tiff.extend(6u32.to_le_bytes());
+        tiff.extend(26u32.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(b"Canon\0");
+
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff);
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend((app1.len() as u16 + 2).to_be_bytes());
+        segment.extend(app1);
+
+        // Right after the start-of-image marker
+        jpeg.splice(2..2, segment);
+        fs::write(path, jpeg)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_classifies_photos_icons_and_screenshots() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let classifier = ImageClassifier::from_config(&ImageKindsConfig::default());
+
+        let photo = temp_dir.path().join("holiday.jpg");
+        camera_photo(&photo)?;
+        assert_eq!(classifier.classify(&photo), Some(ImageKind::Photo));
+
+        let icon = temp_dir.path().join("favicon.png");
+        RgbaImage::new(32, 32).save(&icon)?;
+        assert_eq!(classifier.classify(&icon), Some(ImageKind::Icon));
+
+        let screen = temp_dir.path().join("capture.png");
+        RgbaImage::new(1280, 800).save(&screen)?;
+        assert_eq!(classifier.classify(&screen), Some(ImageKind::Screenshot));
+
+        let named = temp_dir.path().join("Screenshot 2024-01-02 at 10.11.12.png");
+        RgbaImage::new(40, 30).save(&named)?;
+        assert_eq!(classifier.classify(&named), Some(ImageKind::Screenshot));
+
+        // 4:3 without camera metadata could be anything
+        let drawing = temp_dir.path().join("diagram.png");
+        RgbaImage::new(40, 30).save(&drawing)?;
+        assert_eq!(classifier.classify(&drawing), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_ratio("16:10"), Some(1.6));
+        assert_eq!(parse_ratio("9:16"), Some(16.0 / 9.0));
+        assert_eq!(parse_ratio("16x9"), None);
+        assert_eq!(parse_ratio("0:9"), None);
+    }
+}
@@ -0,0 +1,66 @@
+//! AutoFile - Smart File Organizer
+//!
+//! Detects new files in a watched directory, categorizes them, finds the best
+//! matching subfolder in the category's destination, and moves them there.
+//! The `autofile` binary is a thin wrapper around this library.
+
+mod categorizer;
+mod cleanup;
+mod config;
+mod config_env;
+mod config_template;
+mod dedup;
+mod embedding_cache;
+mod detector;
+mod download_source;
+mod events;
+mod filesystem;
+mod history;
+mod hooks;
+mod image_kind;
+mod lock;
+mod matcher;
+mod metrics;
+mod mover;
+mod organizer;
+mod preprocessor;
+mod prompt;
+mod quarantine;
+mod queue;
+mod recent;
+mod report;
+mod status;
+mod tags;
+mod utils;
+mod watcher;
+
+pub use categorizer::{Categorizer, CategoryRule, CategorySource, DestinationSource, Route};
+pub use cleanup::sweep_old_files;
+pub use config::{
+    CategoryMatch, CleanupRule, CompressConfig, Config, CustomCategory, DedupConfig, DetectorConfig,
+    HooksConfig, ImageKindsConfig, ImageRenamerConfig, MatcherConfig, MetricsConfig,
+    NormalizeFilenamesConfig, QuarantineConfig, SizeRule,
+};
+pub use detector::{
+    extension_table, filename_table, inspect_text, DetectionMethod, DetectionResult, ExtensionTable,
+    FileCategory, FileDetector, FilenameTable, TextKind,
+};
+pub use events::{EventSink, JsonLinesSink, OrganizeEvent};
+pub use filesystem::{FileInfo, FileSystem, RealFileSystem};
+pub use history::{format_timestamp, HistoryEntry, MoveHistory, HISTORY_FILE};
+pub use image_kind::{ImageClassifier, ImageKind};
+pub use lock::{acquire_lock, acquire_lock_in, InstanceLock};
+pub use organizer::{Explanation, FileOrganizer, OrganizeOutcome};
+pub use matcher::{
+    Embedder, FolderCase, SimilarityMetric, SubfolderMatch, SubfolderMatcher, TokenMatching,
+};
+pub use metrics::{serve as serve_metrics, Metrics};
+pub use mover::{ConflictResolution, ConflictStrategy, ConflictSuffix};
+pub use preprocessor::{PreprocessPhase, Preprocessor, PreprocessorPipeline};
+pub use prompt::{MatchDecision, MatchPrompt, StdinPrompt};
+pub use queue::{file_queue, QueueReceiver, QueueSender};
+pub use recent::RecentMoves;
+pub use report::{plan_files, write_report, PlannedAction};
+pub use status::{read_statuses, SessionCounts, Status, StatusReporter, STATUS_INTERVAL};
+pub use utils::file::{is_bundle, list_files, DateSource};
+pub use watcher::{wait_for_directory, DroppedFolders, FileWatcher};
@@ -0,0 +1,109 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Advisory lock held while an instance watches a folder
+///
+/// Two instances on the same folder would race to move the same files. The lock
+/// is released when this is dropped, or by the OS if the process dies.
+pub struct InstanceLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Lock file guarding `watch_dir`
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Take the single-instance lock for `watch_dir`, kept under the config directory
+///
+/// Returns `None`, with a warning, when the lock file can't be created (e.g. in a
+/// read-only or missing config directory); only another instance holding the lock is
+/// an error.
+pub fn acquire_lock(watch_dir: &Path) -> Result<Option<InstanceLock>> {
+    let config_dir = match Config::config_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            log::warn!("{:#}; running without the single-instance lock", e);
+            return Ok(None);
+        }
+    };
+    match acquire_lock_in(&config_dir.join("locks"), watch_dir) {
+        Err(e) if e.downcast_ref::<io::Error>().is_some() => {
+            log::warn!("{:#}; running without the single-instance lock", e);
+            Ok(None)
+        }
+        locked => locked.map(Some),
+    }
+}
+
+/// Take the lock for `watch_dir` with its lock file in `lock_dir`
+/// Fails if another instance (or another handle in this one) holds it
+pub fn acquire_lock_in(lock_dir: &Path, watch_dir: &Path) -> Result<InstanceLock> {
+    fs::create_dir_all(lock_dir).context(format!("Failed to create {:?}", lock_dir))?;
+
+    // Symlinks and relative paths to the same folder share one lock
+    let watch_dir = fs::canonicalize(watch_dir).unwrap_or_else(|_| watch_dir.to_path_buf());
+    let digest = Sha256::digest(watch_dir.to_string_lossy().as_bytes());
+    let key: String = digest.iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+    let path = lock_dir.join(format!("{}.lock", key));
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .context(format!("Failed to open lock file {:?}", path))?;
+
+    if FileExt::try_lock_exclusive(&file).is_err() {
+        let mut holder = String::new();
+        let _ = file.read_to_string(&mut holder);
+        anyhow::bail!(
+            "Another AutoFile instance (pid {}) is already watching {:?}",
+            holder.trim(),
+            watch_dir
+        );
+    }
+
+    // Record who holds the lock, for the message above
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", std::process::id())?;
+
+    Ok(InstanceLock { _file: file, path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_lock_fails_while_first_is_held() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let locks = temp_dir.path().join("locks");
+        let watched = temp_dir.path().join("Downloads");
+        let other = temp_dir.path().join("Desktop");
+        fs::create_dir(&watched)?;
+        fs::create_dir(&other)?;
+
+        let first = acquire_lock_in(&locks, &watched)?;
+        assert!(acquire_lock_in(&locks, &watched).is_err());
+        // The same folder spelled differently is still locked
+        assert!(acquire_lock_in(&locks, &watched.join("..").join("Downloads")).is_err());
+        // Other folders are independent
+        let _other = acquire_lock_in(&locks, &other)?;
+
+        drop(first);
+        assert!(acquire_lock_in(&locks, &watched).is_ok());
+        Ok(())
+    }
+}
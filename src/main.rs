@@ -1,70 +1,681 @@
-mod categorizer;
-mod config;
-mod detector;
-mod matcher;
-mod mover;
-mod organizer;
-mod preprocessor;
-mod watcher;
-mod utils;
-
 use anyhow::{Context, Result};
-use organizer::FileOrganizer;
-use std::env;
-use std::path::PathBuf;
-use std::sync::mpsc;
-use watcher::FileWatcher;
+use autofile::{
+    acquire_lock, file_queue, format_timestamp, is_bundle, list_files, plan_files, read_statuses,
+    serve_metrics, sweep_old_files, wait_for_directory, write_report, Categorizer, Config,
+    FileOrganizer, FileWatcher, HistoryEntry, JsonLinesSink, Metrics, MoveHistory,
+    OrganizeOutcome, Status, StatusReporter, StdinPrompt, HISTORY_FILE, STATUS_INTERVAL,
+};
+use clap::{Parser, Subcommand};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "autofile", about = "Smart file organizer", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Directory to watch (defaults to ~/Downloads)
+    watch_dir: Option<PathBuf>,
+
+    /// Log which rule decided each file's destination
+    #[arg(long)]
+    explain: bool,
+
+    /// Ask before filing into a low-confidence subfolder match (organize and run only)
+    #[arg(short, long)]
+    interactive: bool,
+
+    /// Organize files already in the watched folder at startup, then keep watching
+    #[arg(long)]
+    watch_existing: bool,
+
+    /// Print one JSON object per processed file on stdout
+    #[arg(long)]
+    json_events: bool,
+
+    /// Send JSON events to a listening Unix socket, also printing them with --json-events
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH")]
+    events_socket: Option<PathBuf>,
+
+    /// Log more detail (-v for debug, -vv for trace); RUST_LOG takes precedence
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only log warnings and errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Stop after organizing this many files, as a safety limit against runaway moves
+    #[arg(long, global = true, value_name = "N")]
+    max_files: Option<usize>,
+
+    /// Config profile to use (defaults to $AUTOFILE_PROFILE, then "default")
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Sort files into top-level destinations only, without loading the matching model
+    #[arg(long, global = true)]
+    no_subfolder_match: bool,
+
+    /// Words embedded with every file name for this run (e.g. "German"), overriding
+    /// the configured `language_hint`
+    #[arg(long, global = true, value_name = "WORDS")]
+    language_hint: Option<String>,
+}
+
+/// Command line options that override the loaded config
+struct ConfigOverrides {
+    no_subfolder_match: bool,
+    language_hint: Option<String>,
+}
+
+/// Command line options for organizing files once with `organize` or `run`
+struct RunOptions {
+    max_files: Option<usize>,
+    /// Confirm uncertain matches on the terminal
+    interactive: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a default config with every key and a comment describing it
+    Init {
+        /// Replace the profile's existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move files older than the configured `[[cleanup]]` ages to the trash, then exit
+    Cleanup {
+        /// List the files that would be trashed without touching them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Organize the files already in a folder once, then exit
+    Organize {
+        /// Folder whose files to organize
+        dir: PathBuf,
+        /// Show where each file would go without moving anything
+        #[arg(long)]
+        dry_run: bool,
+        /// With --dry-run, write the planned actions to this JSON file
+        #[arg(long, value_name = "FILE", requires = "dry_run")]
+        report: Option<PathBuf>,
+    },
+    /// Organize one file through the full pipeline now and print where it went
+    Run {
+        /// File to organize
+        file: PathBuf,
+        /// Show where the file would go without moving it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show where a file would be organized and why, without moving it
+    Explain {
+        /// File to explain
+        file: PathBuf,
+    },
+    /// List categories with their rule names and destinations, applying the config
+    ListCategories,
+    /// Show running instances with their queue length and files organized so far
+    Status,
+    /// Show the most recent moves, newest first
+    Recent {
+        /// How many moves to show
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Manage config profiles, stored as `profiles/<name>.toml` in the config folder
+    Profiles {
+        #[command(subcommand)]
+        command: ProfilesCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfilesCommand {
+    /// List existing profiles, marking the active one
+    List,
+}
 
 fn main() {
-    // Initialize logger
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let cli = Cli::parse();
+
+    // Initialize logger; RUST_LOG still overrides the level chosen by flags
+    let log_level = default_log_level(cli.verbose, cli.quiet);
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level)).init();
+
+    let profile = Config::active_profile(cli.profile.as_deref());
+    let overrides = ConfigOverrides {
+        no_subfolder_match: cli.no_subfolder_match,
+        language_hint: cli.language_hint.clone(),
+    };
+    let options = RunOptions {
+        max_files: cli.max_files,
+        interactive: cli.interactive,
+    };
+
+    match cli.command {
+        Some(Command::Init { force }) => {
+            match Config::init_profile(&profile, force) {
+                Ok(path) => println!("Wrote {}", path.display()),
+                Err(e) => {
+                    log::error!("Could not write config: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Cleanup { dry_run }) => {
+            if let Err(e) = run_cleanup(&profile, dry_run) {
+                log::error!("Cleanup failed: {:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Organize { dir, dry_run, report }) => {
+            let report = report.as_deref();
+            if let Err(e) = run_organize(&profile, &dir, dry_run, report, &options, &overrides) {
+                log::error!("Could not organize {:?}: {:#}", dir, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Run { file, dry_run }) => {
+            if let Err(e) = run_file(&profile, &file, dry_run, &options, &overrides) {
+                log::error!("Could not organize {:?}: {:#}", file, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Explain { file }) => {
+            if let Err(e) = run_explain(&profile, &file, &overrides) {
+                log::error!("Could not explain {:?}: {:#}", file, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::ListCategories) => {
+            if let Err(e) = run_list_categories(&profile) {
+                log::error!("Could not list categories: {:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Status) => {
+            match run_status() {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    log::error!("Could not read status: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Recent { limit }) => {
+            if let Err(e) = run_recent(limit) {
+                log::error!("Could not read move history: {:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Profiles { command: ProfilesCommand::List }) => {
+            if let Err(e) = list_profiles(&profile) {
+                log::error!("Could not list profiles: {:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
 
     log::info!("Starting AutoFile - Smart File Organizer");
 
     // Get watch directory from args or use Downloads
-    let watch_dir = get_watch_directory().unwrap();
+    let watch_dir = get_watch_directory(cli.watch_dir).unwrap();
 
     log::info!("Monitoring directory: {:?}", watch_dir);
 
-    // Validate watch directory exists
-    if !watch_dir.exists() {
-        log::error!("Watch directory doesn't exist");
-        std::process::exit(1);
+    log::info!("Using config profile '{}'", profile);
+    let config = load_config(&profile, &overrides)
+        .context("Failed to load config")
+        .unwrap();
+    let process_existing = cli.watch_existing || config.process_existing;
+
+    // A removable drive may not be mounted yet
+    if !watch_dir.is_dir() {
+        let wait = Duration::from_secs(config.watch_dir_wait_secs);
+        log::warn!("Watch directory doesn't exist yet, waiting up to {}s", wait.as_secs());
+        if !wait_for_directory(&watch_dir, wait) {
+            log::error!("Watch directory doesn't exist");
+            std::process::exit(1);
+        }
+        log::info!("Watch directory appeared (drive mounted?)");
     }
 
+    // Held until the process exits, so a second instance can't race this one
+    let _instance_lock = match acquire_lock(&watch_dir) {
+        Ok(lock) => {
+            if let Some(lock) = &lock {
+                log::debug!("Holding instance lock {:?}", lock.path());
+            }
+            lock
+        }
+        Err(e) => {
+            log::error!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Without a config directory there is nowhere to keep the status file and
+    // move history, so run without them like when it isn't writable
+    let config_dir = match Config::config_dir() {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            log::warn!("{:#}; running without a status file or move history", e);
+            None
+        }
+    };
+    // Reported by `autofile status`; left-behind files go stale and are ignored
+    let status = config_dir
+        .as_ref()
+        .map(|dir| Arc::new(StatusReporter::new(&dir.join("status"), &watch_dir, &profile)));
+
     // Create file organizer
-    let organizer = FileOrganizer::new().context("Failed to create file organizer").unwrap();
+    let mut organizer = FileOrganizer::from_config(&config)
+        .context("Failed to create file organizer")
+        .unwrap()
+        .with_explain(cli.explain)
+        .with_watch_root(watch_dir.clone());
+    if let Some(status) = &status {
+        organizer = organizer.with_status_reporter(status.clone());
+    }
+    if let Some(dir) = &config_dir {
+        organizer = organizer.with_history(MoveHistory::new(dir.join(HISTORY_FILE)));
+    }
+
+    // Nobody is around to answer a background watcher's prompts
+    if cli.interactive {
+        log::warn!("--interactive only applies to the organize and run commands, ignoring");
+    }
+    if let Some(metrics_config) = &config.metrics {
+        let metrics = Arc::new(Metrics::new());
+        match serve_metrics(&metrics_config.listen, metrics.clone()) {
+            Ok(address) => log::info!("Serving metrics at http://{}/metrics", address),
+            Err(e) => {
+                log::error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        organizer = organizer.with_metrics(metrics);
+    }
+    let recent_moves = organizer.recent_moves();
+    // Destinations inside the watched folder (e.g. when watching the home folder)
+    // hold files that are already organized
+    let mut ignored_dirs = organizer.destination_roots();
+    ignored_dirs.extend(config.ignore_dirs.iter().cloned());
 
-    // Create channel for file events
-    let (tx, rx) = mpsc::channel::<PathBuf>();
+    #[cfg(unix)]
+    if let Some(socket) = &cli.events_socket {
+        let sink = JsonLinesSink::unix_socket(socket).unwrap();
+        organizer = organizer.with_event_sink(Box::new(sink));
+    }
+    if cli.json_events {
+        organizer = organizer.with_event_sink(Box::new(JsonLinesSink::stdout()));
+    }
 
-    // Spawn organizer thread
-    std::thread::spawn(move || {
-        for file_path in rx {
-            if let Err(e) = organizer.organize_file(&file_path) {
-                log::error!("Error organizing file {:?}: {}", file_path, e);
+    // Create queue for file events; a path waiting to be processed isn't queued twice
+    let (tx, mut rx) = file_queue();
+
+    // Refresh the status file so `autofile status` can tell this instance is alive;
+    // holding it weakly lets the file be removed when `main` returns
+    if let Some(status) = status {
+        let status_tx = tx.clone();
+        let weak_status = Arc::downgrade(&status);
+        drop(status);
+        std::thread::spawn(move || {
+            let mut warned = false;
+            while let Some(status) = weak_status.upgrade() {
+                status.set_queue_length(status_tx.pending_len());
+                match status.write() {
+                    Ok(()) => warned = false,
+                    // Likely a read-only config directory; say so once rather than every time
+                    Err(e) if warned => log::debug!("Failed to update status file: {:#}", e),
+                    Err(e) => {
+                        log::warn!("Failed to update status file: {:#}", e);
+                        warned = true;
+                    }
+                }
+                drop(status);
+                std::thread::sleep(STATUS_INTERVAL);
             }
+        });
+    }
+
+    // Watch until the process exits, surviving the folder being unmounted
+    let watcher = FileWatcher::new(watch_dir, recent_moves)
+        .with_process_existing(process_existing)
+        .with_recursive(config.recursive)
+        .with_ignored_dirs(ignored_dirs)
+        .with_dropped_folders(
+            config.dropped_folders,
+            config.project_markers.clone(),
+            Duration::from_secs(config.dropped_folder_settle_secs),
+        );
+    let watch_tx = tx.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = watcher.run(watch_tx) {
+            log::error!("File watcher failed: {:#}", e);
+            std::process::exit(1);
         }
     });
 
-    // Start file watcher and keep it alive
-    let watcher = FileWatcher::new(watch_dir);
-    let _debouncer = watcher.start(tx).unwrap();
+    // Organize queued files; returning from `main` releases the lock and status file
+    let retry_tx = tx;
+    let mut moved = 0;
+    // Checked before taking the next file, so `--max-files 0` moves nothing
+    while cli.max_files.is_none_or(|max| moved < max) {
+        let Some(file_path) = rx.next() else {
+            return;
+        };
+        match organizer.organize_file(&file_path) {
+            Ok(OrganizeOutcome::Moved(_)) => moved += 1,
+            Ok(OrganizeOutcome::Deferred(delay)) => {
+                // Re-queue the file once it has had time to settle
+                let retry_tx = retry_tx.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(delay);
+                    let _ = retry_tx.send(file_path);
+                });
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Error organizing file {:?}: {}", file_path, e),
+        }
+    }
+    log::warn!("Organized {} file(s), the --max-files limit; exiting", moved);
+}
+
+fn run_cleanup(profile: &str, dry_run: bool) -> Result<()> {
+    let config = Config::load_profile(profile)?;
+    if config.cleanup.is_empty() {
+        log::warn!("No [[cleanup]] folders configured, nothing to do");
+        return Ok(());
+    }
+    // Safe mode never deletes anything, so only list what would be trashed
+    if config.safe_mode && !dry_run {
+        log::warn!("Safe mode: listing the files cleanup would trash instead of trashing them");
+    }
+    let dry_run = dry_run || config.safe_mode;
+
+    let swept = sweep_old_files(&config.cleanup, config.date_source, |path| {
+        if dry_run {
+            println!("{}", path.display());
+            Ok(())
+        } else {
+            trash::delete(path).context("Failed to move file to the trash")
+        }
+    })?;
 
-    // Keep the main thread alive indefinitely
-    std::thread::park();
+    let verb = if dry_run { "Would trash" } else { "Trashed" };
+    log::info!("{} {} file(s)", verb, swept.len());
+    Ok(())
 }
 
-fn get_watch_directory() -> Result<PathBuf> {
+fn run_organize(
+    profile: &str,
+    dir: &Path,
+    dry_run: bool,
+    report: Option<&Path>,
+    options: &RunOptions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    if !dir.is_dir() {
+        anyhow::bail!("Not a directory");
+    }
+    let config = load_config(profile, overrides)?;
+    let files = list_files(dir, config.recursive)?;
+
+    if dry_run {
+        // Nothing is created or moved, including destination folders
+        let organizer = FileOrganizer::for_explaining(&config)?.with_watch_root(dir.to_path_buf());
+        let actions = plan_files(&organizer, &files);
+        match report {
+            Some(report) => {
+                write_report(report, &actions)?;
+                log::info!("Wrote the plan for {} file(s) to {:?}", actions.len(), report);
+            }
+            None => actions.iter().for_each(|action| println!("{}", action)),
+        }
+        return Ok(());
+    }
+
+    let organizer = FileOrganizer::from_config(&config)?
+        .with_watch_root(dir.to_path_buf())
+        .with_history(MoveHistory::new(Config::config_dir()?.join(HISTORY_FILE)));
+    let organizer = with_terminal_prompt(organizer, options.interactive);
+    let (moved, limit_reached) = organizer.organize_files(&files, options.max_files);
+    log::info!("Organized {} of {} file(s)", moved, files.len());
+    if limit_reached {
+        println!("Stopped after organizing {} file(s) (--max-files)", moved);
+    }
+    Ok(())
+}
+
+fn run_file(
+    profile: &str,
+    file: &Path,
+    dry_run: bool,
+    options: &RunOptions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    // macOS bundles are folders organized as one file
+    if !file.is_file() && !is_bundle(file) {
+        anyhow::bail!("Not a file");
+    }
+    let config = load_config(profile, overrides)?;
+    let folder = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+
+    if dry_run {
+        let organizer = FileOrganizer::for_explaining(&config)?.with_watch_root(folder);
+        plan_files(&organizer, &[file.to_path_buf()])
+            .iter()
+            .for_each(|action| println!("{}", action));
+        return Ok(());
+    }
+
+    let organizer = FileOrganizer::from_config(&config)?
+        .with_watch_root(folder)
+        .with_history(MoveHistory::new(Config::config_dir()?.join(HISTORY_FILE)));
+    let organizer = with_terminal_prompt(organizer, options.interactive);
+    println!("{}", organize_one(&organizer, file)?);
+    Ok(())
+}
+
+/// `organizer` confirming uncertain matches on the terminal if `interactive`
+/// Prompting only makes sense when someone is at the terminal.
+fn with_terminal_prompt(organizer: FileOrganizer, interactive: bool) -> FileOrganizer {
+    if !interactive {
+        return organizer;
+    }
+    if std::io::stdin().is_terminal() {
+        organizer.with_prompt(Box::new(StdinPrompt))
+    } else {
+        log::warn!("--interactive requires a terminal on stdin, ignoring");
+        organizer
+    }
+}
+
+/// Organize `file` right away, describing the outcome like a planned action
+fn organize_one(organizer: &FileOrganizer, file: &Path) -> Result<String> {
+    let result = match organizer.organize_file(file)? {
+        OrganizeOutcome::Moved(path) => path.display().to_string(),
+        OrganizeOutcome::Skipped => "left in place".to_string(),
+        OrganizeOutcome::Deferred(delay) => {
+            format!("left in place (not ready, retry in {}s)", delay.as_secs().max(1))
+        }
+        OrganizeOutcome::Quarantined(path) => format!("quarantined at {}", path.display()),
+        OrganizeOutcome::Duplicate(path) => {
+            format!("left in place (duplicate of {})", path.display())
+        }
+    };
+    Ok(format!("{} -> {}", file.display(), result))
+}
+
+/// The config of `profile`, with semantic matching off if `--no-subfolder-match` was
+/// given and the language hint of `--language-hint`
+fn load_config(profile: &str, overrides: &ConfigOverrides) -> Result<Config> {
+    let mut config = Config::load_profile(profile)?;
+    if overrides.no_subfolder_match {
+        config.semantic_matching = false;
+    }
+    if let Some(hint) = &overrides.language_hint {
+        config.matcher.language_hint = Some(hint.clone());
+    }
+    Ok(config)
+}
+
+fn run_explain(profile: &str, file: &Path, overrides: &ConfigOverrides) -> Result<()> {
+    // macOS bundles are folders organized as one file
+    if !file.is_file() && !is_bundle(file) {
+        anyhow::bail!("Not a file");
+    }
+    let config = load_config(profile, overrides)?;
+    let organizer = FileOrganizer::for_explaining(&config)?;
+    println!("{}", organizer.explain_file(file)?);
+    Ok(())
+}
+
+fn run_list_categories(profile: &str) -> Result<()> {
+    let categorizer = Categorizer::from_config(&Config::load_profile(profile)?)?;
+    print!("{}", category_listing(&categorizer));
+    Ok(())
+}
+
+/// One line per category: its name, rule name and destination, noting missing folders
+fn category_listing(categorizer: &Categorizer) -> String {
+    let rules = categorizer.category_rules();
+    let category_width = rules.iter().map(|(c, _)| c.name().len()).max().unwrap_or(0);
+    let rule_width = rules.iter().map(|(_, rule)| rule.name.len()).max().unwrap_or(0);
+
+    let mut listing = String::new();
+    for (category, rule) in rules {
+        let missing = if rule.destination.is_dir() { "" } else { "  (missing)" };
+        listing += &format!(
+            "{:<cw$}  {:<rw$}  {}{}\n",
+            category.name(),
+            rule.name,
+            rule.destination.display(),
+            missing,
+            cw = category_width,
+            rw = rule_width
+        );
+    }
+    listing
+}
+
+fn run_recent(limit: usize) -> Result<()> {
+    let history = MoveHistory::new(Config::config_dir()?.join(HISTORY_FILE));
+    let entries = history.recent(limit)?;
+    if entries.is_empty() {
+        println!("No files have been organized yet");
+    }
+    print!("{}", recent_listing(&entries));
+    Ok(())
+}
+
+/// One line per move: when, the original file name and where it went
+fn recent_listing(entries: &[HistoryEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            let name = entry.source.file_name().unwrap_or_default().to_string_lossy();
+            format!(
+                "{}  {} -> {}\n",
+                format_timestamp(entry.moved_at),
+                name,
+                entry.destination.display()
+            )
+        })
+        .collect()
+}
+
+/// Print every running instance; returns false when none are running
+fn run_status() -> Result<bool> {
+    let statuses = read_statuses(&Config::config_dir()?.join("status"))?;
+    if statuses.is_empty() {
+        println!("AutoFile is not running");
+        return Ok(false);
+    }
+
+    for (i, status) in statuses.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        print_status(status);
+    }
+    Ok(true)
+}
+
+fn print_status(status: &Status) {
+    let counts = &status.counts;
+    println!("Watching:  {}", status.watch_dir.display());
+    println!("Profile:   {}", status.profile);
+    println!("PID:       {}", status.pid);
+    println!("Uptime:    {}", format_uptime(status.uptime()));
+    println!("Queued:    {}", status.queue_length);
+    println!(
+        "Organized: {} moved, {} skipped, {} deferred, {} duplicates, {} quarantined, {} failed",
+        counts.moved,
+        counts.skipped,
+        counts.deferred,
+        counts.duplicates,
+        counts.quarantined,
+        counts.failed
+    );
+}
+
+/// Uptime as e.g. "2d 3h 4m" or "42s"
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, _) => format!("{}m {}s", mins, secs % 60),
+        (0, _, _) => format!("{}h {}m", hours, mins),
+        _ => format!("{}d {}h {}m", days, hours, mins),
+    }
+}
+
+fn list_profiles(active: &str) -> Result<()> {
+    for name in Config::list_profiles()? {
+        let marker = if name == active { "*" } else { " " };
+        println!("{} {}", marker, name);
+    }
+    Ok(())
+}
+
+/// Log level for the -v/-q flags
+fn default_log_level(verbose: u8, quiet: bool) -> &'static str {
+    match (quiet, verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "debug",
+        (false, _) => "trace",
+    }
+}
+
+fn get_watch_directory(arg: Option<PathBuf>) -> Result<PathBuf> {
     // Check for command line argument first
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        let path = PathBuf::from(&args[1]);
-        if path.exists() && path.is_dir() {
-            return Ok(path);
-        } else {
+    // A missing path is allowed, since it may be a drive that isn't mounted yet
+    if let Some(path) = arg {
+        if path.exists() && !path.is_dir() {
             anyhow::bail!("Provided path is not a valid directory: {:?}", path);
         }
+        return Ok(path);
     }
 
     // Default to Downloads directory
@@ -79,3 +690,92 @@ fn get_watch_directory() -> Result<PathBuf> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_log_level() {
+        assert_eq!(default_log_level(0, false), "info");
+        assert_eq!(default_log_level(1, false), "debug");
+        assert_eq!(default_log_level(3, false), "trace");
+        assert_eq!(default_log_level(0, true), "warn");
+    }
+
+    #[test]
+    fn test_category_listing() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        std::fs::create_dir(&documents)?;
+        let config = Config {
+            unknown_destination: Some(temp_dir.path().join("Misc")),
+            categories: vec![autofile::CustomCategory {
+                name: "Invoices".to_string(),
+                destination: documents.clone(),
+                matcher: Default::default(),
+            }],
+            ..Default::default()
+        };
+        let categorizer = Categorizer::from_config(&config)?;
+
+        let listing = category_listing(&categorizer);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert!(lines[0].starts_with("Invoices"));
+        assert!(lines[0].ends_with(&documents.display().to_string()));
+        assert!(lines.last().unwrap().ends_with("Misc  (missing)"));
+        for name in ["document", "image", "video", "audio", "archive", "code", "font"] {
+            let destination = categorizer.get_destination(&name.to_string().into()).unwrap();
+            let line = lines.iter().find(|line| line.starts_with(name)).unwrap();
+            assert!(line.contains(&destination.display().to_string()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_moves_one_file_and_reports_where() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let pictures = temp_dir.path().join("Pictures");
+        std::fs::create_dir(&downloads)?;
+        let photo = downloads.join("holiday.png");
+        image::RgbImage::new(4, 3).save(&photo)?;
+        let untouched = downloads.join("notes.txt");
+        std::fs::write(&untouched, b"stays")?;
+
+        let config = Config {
+            semantic_matching: false,
+            mime_destinations: [("image/".to_string(), pictures.clone())].into(),
+            ..Default::default()
+        };
+        let organizer = FileOrganizer::from_config(&config)?.with_watch_root(downloads);
+        let report = organize_one(&organizer, &photo)?;
+
+        let moved = pictures.join("holiday.png");
+        assert_eq!(report, format!("{} -> {}", photo.display(), moved.display()));
+        assert!(moved.is_file() && !photo.exists());
+        assert!(untouched.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_recent_listing() {
+        let entries = [HistoryEntry {
+            moved_at: 1_706_708_700,
+            source: PathBuf::from("/home/me/Downloads/invoice.pdf"),
+            destination: PathBuf::from("/home/me/Documents/invoice (1).pdf"),
+        }];
+        assert_eq!(
+            recent_listing(&entries),
+            "2024-01-31 13:45:00 UTC  invoice.pdf -> /home/me/Documents/invoice (1).pdf\n"
+        );
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(Duration::from_secs(42)), "42s");
+        assert_eq!(format_uptime(Duration::from_secs(125)), "2m 5s");
+        assert_eq!(format_uptime(Duration::from_secs(3 * 3600 + 60)), "3h 1m");
+        assert_eq!(format_uptime(Duration::from_secs(2 * 86400 + 3 * 3600 + 240)), "2d 3h 4m");
+    }
+}
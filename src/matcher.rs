@@ -1,72 +1,437 @@
+use crate::config::MatcherConfig;
+use crate::detector::FileCategory;
+use crate::embedding_cache::EmbeddingCache;
+use crate::prompt::{MatchDecision, MatchPrompt};
+use crate::utils::file;
 use anyhow::Result;
-use fastembed::TextEmbedding;
-use std::collections::HashSet;
+use fastembed::{EmbeddingModel, TextEmbedding, TextInitOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
-/// Minimum cosine similarity threshold for a match (0.0 to 1.0)
-const SIMILARITY_THRESHOLD: f32 = 0.7;
+/// Folders scoring within this much of the best are considered tied
+const TIE_EPSILON: f32 = 0.01;
+
+/// How long a directory listing is reused before the folder is read again
+const LISTING_TTL: Duration = Duration::from_secs(10);
+
+/// Produces embedding vectors for text
+pub trait Embedder: Send {
+    fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>>;
+
+    /// Name of the model, telling apart embeddings persisted by different models
+    fn model_name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+impl Embedder for TextEmbedding {
+    fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        TextEmbedding::embed(self, texts, None)
+    }
+}
+
+/// A fastembed model together with its name, which `TextEmbedding` doesn't keep
+struct FastEmbedModel {
+    embedding: TextEmbedding,
+    model: EmbeddingModel,
+}
+
+impl Embedder for FastEmbedModel {
+    fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embedding.embed(texts, None)
+    }
+
+    fn model_name(&self) -> String {
+        self.model.to_string()
+    }
+}
+
+/// How the words of a file name are scored against folders, besides the name as a whole
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenMatching {
+    /// Only the whole name is embedded
+    #[default]
+    Off,
+    /// A folder scores as well as the whole name or its best-matching word
+    Max,
+    /// A folder scores the mean of the whole name's and the best word's similarity
+    Mean,
+}
+
+/// How the embeddings of a file name and a folder name are compared
+///
+/// A folder matches when its similarity reaches the configured threshold, 0.7 by
+/// default, which means something different under each metric.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimilarityMetric {
+    /// Cosine of the angle between the vectors, from -1 to 1 whatever their length;
+    /// 0.7 is an angle of about 45 degrees
+    #[default]
+    Cosine,
+    /// Dot product, the same as cosine for models producing unit-length vectors
+    /// (like the default one) and unbounded otherwise, so 0.7 favors long vectors
+    Dot,
+    /// `1 / (1 + distance)` from the Euclidean distance, from 0 (far) to 1 (equal);
+    /// 0.7 is a distance of about 0.43
+    Euclidean,
+}
+
+/// A similarity function over two embeddings
+type SimilarityFn = fn(&[f32], &[f32]) -> f32;
+
+impl SimilarityMetric {
+    fn function(self) -> SimilarityFn {
+        match self {
+            SimilarityMetric::Cosine => cosine_similarity,
+            SimilarityMetric::Dot => dot_similarity,
+            SimilarityMetric::Euclidean => euclidean_similarity,
+        }
+    }
+}
+
+/// Whether folder names differing only in case are the same folder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderCase {
+    /// Probe each destination's filesystem
+    #[default]
+    Auto,
+    /// "Invoices" and "invoices" are different folders
+    Sensitive,
+    /// "Invoices" and "invoices" are one folder, under the casing already on disk
+    Insensitive,
+}
+
+/// Embeddings of a file name as a whole and of each of its words
+struct FileEmbedding {
+    whole: Vec<f32>,
+    tokens: Vec<Vec<f32>>,
+}
+
+/// Result of a subfolder search
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubfolderMatch {
+    /// The chosen folder, or the top-level destination if nothing matched
+    pub path: PathBuf,
+    /// Similarity of the chosen folder; `None` when no subfolder matched
+    pub similarity: Option<f32>,
+    /// Every folder scored on the way to the chosen one, level by level
+    pub candidates: Vec<(PathBuf, f32)>,
+}
+
+impl SubfolderMatch {
+    fn unmatched(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            similarity: None,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+/// A folder's path and name
+type Subfolder = (PathBuf, String);
+
+/// Short-lived cache of the visible subfolders of each directory, so a burst of
+/// files organized into the same tree doesn't re-read it for every file
+#[derive(Default)]
+struct FolderCache {
+    listings: Mutex<HashMap<PathBuf, (Instant, Vec<Subfolder>)>>,
+}
+
+impl FolderCache {
+    /// Subfolders of `dir`, using `read` when the listing is missing or stale
+    fn subfolders(
+        &self,
+        dir: &Path,
+        read: impl FnOnce(&Path) -> Vec<Subfolder>,
+    ) -> Vec<Subfolder> {
+        let mut listings = self.listings.lock().unwrap();
+        if let Some((read_at, folders)) = listings.get(dir) {
+            if read_at.elapsed() < LISTING_TTL {
+                return folders.clone();
+            }
+        }
+
+        let folders = read(dir);
+        listings.retain(|_, (read_at, _)| read_at.elapsed() < LISTING_TTL);
+        listings.insert(dir.to_path_buf(), (Instant::now(), folders.clone()));
+        folders
+    }
+
+    fn invalidate(&self, dir: &Path) {
+        self.listings.lock().unwrap().remove(dir);
+    }
+}
+
+/// Non-hidden subfolders of `dir`
+fn read_subfolders(dir: &Path) -> Vec<Subfolder> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut folders = Vec::new();
+    for entry in entries.flatten() {
+        if let Ok(metadata) = entry.metadata() {
+            if metadata.is_dir() {
+                if let Some(folder_name) = entry.file_name().to_str() {
+                    // Skip hidden folders (those starting with a dot)
+                    if folder_name.starts_with('.') {
+                        continue;
+                    }
+
+                    folders.push((entry.path(), folder_name.to_string()));
+                }
+            }
+        }
+    }
+    folders
+}
 
 pub struct SubfolderMatcher {
-    model: Arc<Mutex<TextEmbedding>>,
+    model: Arc<Mutex<Box<dyn Embedder>>>,
     excluded_folders: HashSet<String>,
+    boilerplate_words: HashSet<String>,
+    /// Minimum similarity for a match, under whichever `SimilarityMetric` is configured
+    similarity_threshold: f32,
+    uncertain_band: f32,
+    max_match_depth: Option<usize>,
+    min_folders_to_match: usize,
+    /// How much better than its parent a subfolder must score to descend into it
+    recursion_margin: Option<f32>,
+    token_matching: TokenMatching,
+    /// The configured `SimilarityMetric`'s function
+    similarity: SimilarityFn,
+    /// Lowercase category name to the words embedded alongside its file names
+    category_hints: HashMap<String, String>,
+    /// Words embedded alongside every file name, naming its language
+    language_hint: Option<String>,
+    prompt: Option<Box<dyn MatchPrompt>>,
+    remember_answers: bool,
+    /// Answers given for uncertain matches, keyed by stem and candidate folder
+    remembered: Mutex<HashMap<(String, PathBuf), MatchDecision>>,
+    folder_cache: FolderCache,
+    /// Embeddings of folder names, optionally persisted across restarts
+    embedding_cache: EmbeddingCache,
+    folder_case: FolderCase,
+    /// Alias folder name to the name of the folder it stands for
+    folder_aliases: HashMap<String, String>,
+    /// Probed case-insensitivity of each destination, for `FolderCase::Auto`
+    case_probes: Mutex<HashMap<PathBuf, bool>>,
 }
 
 impl SubfolderMatcher {
-    pub fn new(excluded_folders: Vec<String>) -> Result<Self> {
-        // Initialize the embedding model (using a small, fast model)
-        let model = TextEmbedding::try_new(
-            Default::default()
-        )?;
+    pub fn new(config: &MatcherConfig) -> Result<Self> {
+        let model: EmbeddingModel = config.model.parse().map_err(anyhow::Error::msg)?;
+        log::info!("Loading embedding model {}", model);
+        let embedding = TextEmbedding::try_new(TextInitOptions::new(model.clone()))?;
+
+        Ok(Self::with_embedder(Box::new(FastEmbedModel { embedding, model }), config))
+    }
 
-        let excluded_set: HashSet<String> = excluded_folders.into_iter().collect();
+    /// Create a matcher backed by a specific embedder
+    pub fn with_embedder(embedder: Box<dyn Embedder>, config: &MatcherConfig) -> Self {
+        let excluded_set: HashSet<String> = config.excluded_folders.iter().cloned().collect();
 
         if !excluded_set.is_empty() {
             log::info!("Excluding folders from matching: {:?}", excluded_set);
         }
 
-        Ok(Self {
-            model: Arc::new(Mutex::new(model)),
+        Self {
+            model: Arc::new(Mutex::new(embedder)),
             excluded_folders: excluded_set,
-        })
+            boilerplate_words: config
+                .boilerplate_words
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect(),
+            similarity_threshold: config.similarity_threshold,
+            uncertain_band: config.uncertain_band,
+            max_match_depth: config.max_match_depth,
+            min_folders_to_match: config.min_folders_to_match,
+            recursion_margin: config.recursion_margin,
+            token_matching: config.token_matching,
+            similarity: config.similarity_metric.function(),
+            category_hints: config
+                .category_hints
+                .iter()
+                .map(|(category, hint)| (category.to_lowercase(), hint.clone()))
+                .collect(),
+            language_hint: config.language_hint.clone().filter(|hint| !hint.trim().is_empty()),
+            prompt: None,
+            remember_answers: config.remember_answers,
+            remembered: Mutex::new(HashMap::new()),
+            folder_cache: FolderCache::default(),
+            embedding_cache: EmbeddingCache::in_memory(),
+            folder_case: config.folder_case,
+            folder_aliases: config.folder_aliases.clone(),
+            case_probes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Ask for confirmation when the best match is only marginally above the threshold
+    pub fn with_prompt(mut self, prompt: Box<dyn MatchPrompt>) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+
+    /// Keep folder name embeddings in the file at `path`, reusing those it already
+    /// holds for the current model
+    pub fn with_embedding_cache(mut self, path: PathBuf) -> Self {
+        let model = self.lock_model().model_name();
+        self.embedding_cache = EmbeddingCache::load(path, &model);
+        self
+    }
+
+    /// Drop the cached listing of `dir`, e.g. after creating a folder inside it
+    pub fn invalidate_folder(&self, dir: &Path) {
+        self.folder_cache.invalidate(dir);
     }
 
     /// Finds a matching subfolder in the destination directory based on semantic similarity
     /// Returns the matched subfolder path, or the original destination if no match found
-    /// Uses a greedy approach: at each depth, finds the best match and recurses only into that folder
     pub fn find_matching_subfolder(
         &self,
         file_path: &Path,
         destination_dir: &Path,
     ) -> Result<PathBuf> {
+        Ok(self.find_match(file_path, destination_dir)?.path)
+    }
+
+    /// Like `find_matching_subfolder`, but also reports the similarity of the chosen folder
+    pub fn find_match(&self, file_path: &Path, destination_dir: &Path) -> Result<SubfolderMatch> {
+        self.find_match_with_hint(file_path, destination_dir, None, None)
+    }
+
+    /// Like `find_match`, but embeds the file name together with the hint
+    /// configured for its category, if any
+    pub fn find_category_match(
+        &self,
+        file_path: &Path,
+        destination_dir: &Path,
+        category: &FileCategory,
+    ) -> Result<SubfolderMatch> {
+        self.find_contextual_match(file_path, destination_dir, category, None)
+    }
+
+    /// Like `find_category_match`, also embedding `source_context` (e.g. the names of
+    /// the folders the file was found in)
+    /// With context, files with opaque names are matched by the context alone.
+    pub fn find_contextual_match(
+        &self,
+        file_path: &Path,
+        destination_dir: &Path,
+        category: &FileCategory,
+        source_context: Option<&str>,
+    ) -> Result<SubfolderMatch> {
+        let hint = self.category_hints.get(&category.name().to_lowercase());
+        self.find_match_with_hint(
+            file_path,
+            destination_dir,
+            hint.map(String::as_str),
+            source_context,
+        )
+    }
+
+    /// Uses a greedy approach: at each depth, finds the best match and recurses only into that folder
+    fn find_match_with_hint(
+        &self,
+        file_path: &Path,
+        destination_dir: &Path,
+        hint: Option<&str>,
+        source_context: Option<&str>,
+    ) -> Result<SubfolderMatch> {
         let file_stem = file_path
             .file_stem()
             .and_then(|s| s.to_str())
-            .unwrap_or("");
+            .unwrap_or("")
+            .trim();
 
-        if file_stem.is_empty() {
-            return Ok(destination_dir.to_path_buf());
+        let file_text = normalize_name(file_stem);
+        let context_text = source_context.map(normalize_name).filter(|text| !text.is_empty());
+        if file_text.is_empty() && context_text.is_none() {
+            return Ok(SubfolderMatch::unmatched(destination_dir));
         }
 
         if !destination_dir.exists() {
-            return Ok(destination_dir.to_path_buf());
+            return Ok(SubfolderMatch::unmatched(destination_dir));
+        }
+
+        // Match under the casing on disk, so case variants resolve to one folder
+        let case_insensitive = self.is_case_insensitive(destination_dir);
+        let canonical_dir;
+        let destination_dir = if case_insensitive {
+            canonical_dir = existing_casing(destination_dir);
+            canonical_dir.as_path()
+        } else {
+            destination_dir
+        };
+
+        // Names like "IMG_4821" or "a3f9c2" would only produce spurious matches
+        let opaque = is_opaque_stem(file_stem, &self.boilerplate_words);
+        if opaque && context_text.is_none() {
+            log::info!("'{}' has no meaningful words, using top-level destination", file_stem);
+            return Ok(SubfolderMatch::unmatched(destination_dir));
+        }
+
+        let tokens = if opaque { Vec::new() } else { self.file_tokens(&file_text) };
+        let file_text = [
+            (!opaque).then_some(file_text),
+            context_text,
+            hint.map(str::to_string),
+            self.language_hint.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+        if file_text.trim().is_empty() {
+            return Ok(SubfolderMatch::unmatched(destination_dir));
+        }
+
+        // A top level too sparse to match isn't worth embedding the file for
+        let folders = self.candidate_folders(destination_dir, case_insensitive);
+        if folders.len() < self.min_folders_to_match {
+            log::debug!(
+                "Only {} candidate folder(s) in {:?}, not matching",
+                folders.len(),
+                destination_dir
+            );
+            return Ok(SubfolderMatch::unmatched(destination_dir));
         }
 
         // Generate embeddings for the file stem once
-        let file_embedding = {
-            let mut model = self.model.lock().unwrap();
-            let embeddings = model.embed(vec![file_stem.to_string()], None)?;
-            embeddings.into_iter().next().unwrap()
+        let Some(whole) = self.embed_one(file_text)? else {
+            log::warn!("No embedding for '{}', using top-level destination", file_stem);
+            return Ok(SubfolderMatch::unmatched(destination_dir));
+        };
+        let file_embedding = FileEmbedding {
+            whole,
+            tokens: self.embed_tokens(tokens)?,
         };
 
         // Start greedy recursive search from the destination directory
-        let final_path = self.find_best_match_greedy(
+        let best = self.find_best_match_greedy(
             destination_dir,
             &file_embedding,
             file_stem,
             0,
-        )?;
+            None,
+            case_insensitive,
+        );
+        if let Err(e) = self.embedding_cache.save_soon() {
+            log::warn!("Failed to save folder embeddings: {}", e);
+        }
+        let best = best?;
+        let final_path = &best.path;
 
         if final_path != destination_dir {
             log::info!(
@@ -81,7 +446,7 @@ impl SubfolderMatcher {
             log::info!("No semantic match found for '{}', using top-level destination", file_stem);
         }
 
-        Ok(final_path)
+        Ok(best)
     }
 
     /// Greedy recursive search: at each level, find the best matching folder
@@ -89,53 +454,53 @@ impl SubfolderMatcher {
     fn find_best_match_greedy(
         &self,
         current_dir: &Path,
-        file_embedding: &[f32],
+        file_embedding: &FileEmbedding,
         file_stem: &str,
         depth: usize,
-    ) -> Result<PathBuf> {
-        let entries = match fs::read_dir(current_dir) {
-            Ok(entries) => entries,
-            Err(_) => return Ok(current_dir.to_path_buf()),
-        };
-
-        let mut folders = Vec::new();
-        for entry in entries.flatten() {
-            if let Ok(metadata) = entry.metadata() {
-                if metadata.is_dir() {
-                    if let Some(folder_name) = entry.file_name().to_str() {
-                        // Skip hidden folders (those starting with a dot)
-                        if folder_name.starts_with('.') {
-                            continue;
-                        }
-
-                        // Skip excluded folders
-                        if self.excluded_folders.contains(folder_name) {
-                            log::debug!("Skipping excluded folder: {}", folder_name);
-                            continue;
-                        }
-
-                        folders.push((entry.path(), folder_name.to_string()));
-                    }
-                }
-            }
+        current_similarity: Option<f32>,
+        case_insensitive: bool,
+    ) -> Result<SubfolderMatch> {
+        let mut stay = SubfolderMatch {
+            path: current_dir.to_path_buf(),
+            similarity: current_similarity,
+            candidates: Vec::new(),
+        };
+
+        if self.max_match_depth.is_some_and(|max| depth >= max) {
+            log::debug!("Reached max match depth {}, stopping at {:?}", depth, current_dir);
+            return Ok(stay);
         }
 
+        let folders = self.candidate_folders(current_dir, case_insensitive);
         if folders.is_empty() {
-            return Ok(current_dir.to_path_buf());
+            return Ok(stay);
+        }
+
+        // With too few candidates, the best one tends to win regardless of fit
+        if folders.len() < self.min_folders_to_match {
+            log::debug!(
+                "Only {} candidate folder(s) in {:?}, not matching",
+                folders.len(),
+                current_dir
+            );
+            return Ok(stay);
         }
 
-        // Find the best match at this depth level
-        let mut best_match: Option<(PathBuf, String, f32)> = None;
+        // Score every folder at this depth level
+        let mut scored: Vec<(PathBuf, String, f32)> = Vec::with_capacity(folders.len());
 
         for (folder_path, folder_name) in folders {
-            // Calculate similarity for this folder
-            let folder_embedding = {
-                let mut model = self.model.lock().unwrap();
-                let embeddings = model.embed(vec![folder_name.clone()], None)?;
-                embeddings.into_iter().next().unwrap()
+            // Calculate similarity for this folder, embedding its normalized name
+            let folder_text = match normalize_name(&folder_name) {
+                text if text.is_empty() => folder_name.clone(),
+                text => text,
+            };
+            let Some(folder_embedding) = self.folder_embedding(&folder_path, folder_text)? else {
+                log::warn!("No embedding for folder '{}', skipping it", folder_name);
+                continue;
             };
 
-            let similarity = cosine_similarity(file_embedding, &folder_embedding);
+            let similarity = self.score(file_embedding, &folder_embedding);
 
             log::debug!(
                 "{}[depth {}] '{}' <-> '{}': similarity = {:.3}",
@@ -146,19 +511,69 @@ impl SubfolderMatcher {
                 similarity
             );
 
-            // Track the best match at this level
-            if let Some((_, _, best_sim)) = &best_match {
-                if similarity > *best_sim {
-                    best_match = Some((folder_path, folder_name, similarity));
-                }
-            } else {
-                best_match = Some((folder_path, folder_name, similarity));
+            let (folder_path, folder_name) =
+                self.resolve_alias(current_dir, folder_path, folder_name, case_insensitive);
+            scored.push((folder_path, folder_name, similarity));
+        }
+
+        if scored.is_empty() {
+            return Ok(stay);
+        }
+
+        // Best match first; aliases of one folder count once, with their best score
+        scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+        let mut seen = HashSet::new();
+        scored.retain(|(path, _, _)| seen.insert(path.clone()));
+        break_ties(&mut scored, file_stem);
+        stay.candidates = scored.iter().map(|(p, _, sim)| (p.clone(), *sim)).collect();
+
+        // A folder named like the file is the one, whatever the embeddings say
+        if let Some((path, name, similarity)) = self.exact_name_match(&scored, file_stem) {
+            log::info!(
+                "{}Exact name match at depth {}: '{}' (similarity: {:.3})",
+                "  ".repeat(depth),
+                depth,
+                name,
+                similarity
+            );
+            let mut deeper = self.find_best_match_greedy(
+                path,
+                file_embedding,
+                file_stem,
+                depth + 1,
+                Some(*similarity),
+                case_insensitive,
+            )?;
+            deeper.candidates.splice(0..0, stay.candidates);
+            return Ok(deeper);
+        }
+
+        // A subfolder must fit clearly better than the folder it is in
+        let (path, name, similarity) = &scored[0];
+        if let (Some(parent), Some(margin)) = (current_similarity, self.recursion_margin) {
+            if *similarity >= self.similarity_threshold && *similarity < parent + margin {
+                log::debug!(
+                    "{}'{}' ({:.3}) isn't {} better than its parent ({:.3}), staying",
+                    "  ".repeat(depth),
+                    name,
+                    similarity,
+                    margin,
+                    parent
+                );
+                return Ok(stay);
             }
         }
 
         // If we found a match above the threshold, recurse into it
-        if let Some((path, name, similarity)) = best_match {
-            if similarity >= SIMILARITY_THRESHOLD {
+        if *similarity >= self.similarity_threshold {
+            let chosen = if *similarity < self.similarity_threshold + self.uncertain_band {
+                let alternatives: Vec<PathBuf> = scored.iter().map(|(p, _, _)| p.clone()).collect();
+                self.confirm_uncertain(file_stem, path, *similarity, &alternatives)
+            } else {
+                Some(path.clone())
+            };
+
+            if let Some(chosen) = chosen {
                 log::info!(
                     "{}Greedy match at depth {}: '{}' (similarity: {:.3})",
                     "  ".repeat(depth),
@@ -166,18 +581,389 @@ impl SubfolderMatcher {
                     name,
                     similarity
                 );
+                let chosen_similarity = scored
+                    .iter()
+                    .find(|(p, _, _)| *p == chosen)
+                    .map(|(_, _, sim)| *sim);
                 // Recurse into the best match to see if there's an even better match deeper
-                return self.find_best_match_greedy(&path, file_embedding, file_stem, depth + 1);
+                let mut deeper = self.find_best_match_greedy(
+                    &chosen,
+                    file_embedding,
+                    file_stem,
+                    depth + 1,
+                    chosen_similarity,
+                    case_insensitive,
+                )?;
+                deeper.candidates.splice(0..0, stay.candidates);
+                return Ok(deeper);
             }
         }
 
         // No match above threshold at this level, return current directory
-        Ok(current_dir.to_path_buf())
+        Ok(stay)
+    }
+
+    /// Subfolders of `dir` a file may be matched to, leaving out excluded folders
+    fn candidate_folders(&self, dir: &Path, case_insensitive: bool) -> Vec<Subfolder> {
+        let mut folders: Vec<Subfolder> = self
+            .folder_cache
+            .subfolders(dir, read_subfolders)
+            .into_iter()
+            .filter(|(_, folder_name)| {
+                // Skip excluded folders
+                let excluded = if case_insensitive {
+                    self.excluded_folders.iter().any(|name| name.eq_ignore_ascii_case(folder_name))
+                } else {
+                    self.excluded_folders.contains(folder_name)
+                };
+                if excluded {
+                    log::debug!("Skipping excluded folder: {}", folder_name);
+                }
+                !excluded
+            })
+            .collect();
+        if case_insensitive {
+            dedup_case_variants(&mut folders);
+        }
+        folders
+    }
+
+    /// The folder among `scored` whose normalized name equals that of `file_stem`, or
+    /// a run of two or more words in it ("Tax Returns" for "2023_TaxReturns_final"),
+    /// preferring longer names
+    /// A one-word name must be the stem's only meaningful word ("Taxes" for
+    /// "taxes_2023"), so generic words like "Final" don't win on their own. Names of
+    /// only boilerplate words and numbers never match this way.
+    fn exact_name_match<'a>(
+        &self,
+        scored: &'a [(PathBuf, String, f32)],
+        file_stem: &str,
+    ) -> Option<&'a (PathBuf, String, f32)> {
+        let is_meaningful = |word: &&str| {
+            !self.boilerplate_words.contains(*word) && !word.chars().all(|c| c.is_numeric())
+        };
+        let stem = normalize_name(file_stem);
+        let stem_words: Vec<&str> = stem.split_whitespace().collect();
+        let meaningful_stem: Vec<&str> = stem_words.iter().copied().filter(is_meaningful).collect();
+        scored
+            .iter()
+            .filter_map(|entry| {
+                let name = normalize_name(&entry.1);
+                let words: Vec<&str> = name.split_whitespace().collect();
+                let named = match words.len() {
+                    1 => is_meaningful(&words[0]) && meaningful_stem == words,
+                    _ => {
+                        words.iter().any(is_meaningful)
+                            && stem_words.windows(words.len()).any(|run| run == words)
+                    }
+                };
+                named.then_some((words.len(), entry))
+            })
+            .min_by_key(|(length, _)| std::cmp::Reverse(*length))
+            .map(|(_, entry)| entry)
+    }
+
+    /// The folder in `dir` that `folder_name` is an alias of, or the folder itself
+    /// The aliased folder needn't exist yet; moving a file there creates it.
+    fn resolve_alias(
+        &self,
+        dir: &Path,
+        folder_path: PathBuf,
+        folder_name: String,
+        case_insensitive: bool,
+    ) -> Subfolder {
+        let canonical = self.folder_aliases.iter().find_map(|(alias, canonical)| {
+            let is_alias = if case_insensitive {
+                alias.to_lowercase() == folder_name.to_lowercase()
+            } else {
+                *alias == folder_name
+            };
+            is_alias.then_some(canonical)
+        });
+        match canonical {
+            Some(canonical) => {
+                log::debug!("Folder '{}' is an alias of '{}'", folder_name, canonical);
+                (dir.join(canonical), canonical.clone())
+            }
+            None => (folder_path, folder_name),
+        }
+    }
+
+    /// Whether folder names under `destination_dir` ignore case, probing once per
+    /// destination when set to `FolderCase::Auto`
+    fn is_case_insensitive(&self, destination_dir: &Path) -> bool {
+        match self.folder_case {
+            FolderCase::Sensitive => false,
+            FolderCase::Insensitive => true,
+            FolderCase::Auto => *self
+                .case_probes
+                .lock()
+                .unwrap()
+                .entry(destination_dir.to_path_buf())
+                .or_insert_with(|| file::is_case_insensitive(destination_dir)),
+        }
+    }
+
+    /// Distinct meaningful words of a normalized file name, for token matching
+    /// Empty when token matching is off or the name is a single word.
+    fn file_tokens(&self, file_text: &str) -> Vec<String> {
+        if self.token_matching == TokenMatching::Off {
+            return Vec::new();
+        }
+        let mut tokens: Vec<String> = Vec::new();
+        for word in file_text.split_whitespace() {
+            let meaningful = !self.boilerplate_words.contains(word)
+                && !word.chars().all(|c| c.is_numeric());
+            if meaningful && !tokens.iter().any(|token| token == word) {
+                tokens.push(word.to_string());
+            }
+        }
+        if tokens.len() < 2 {
+            tokens.clear();
+        }
+        tokens
+    }
+
+    /// Embeddings of each token, or none if the model didn't return one per token
+    fn embed_tokens(&self, tokens: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        if tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = tokens.len();
+        let embeddings = self.lock_model().embed(tokens)?;
+        if embeddings.len() != count || embeddings.iter().any(Vec::is_empty) {
+            log::warn!("Missing word embeddings, matching on the whole name only");
+            return Ok(Vec::new());
+        }
+        Ok(embeddings)
+    }
+
+    /// Similarity of a file to a folder, combining its words as configured
+    fn score(&self, file: &FileEmbedding, folder: &[f32]) -> f32 {
+        let whole = (self.similarity)(&file.whole, folder);
+        let best_token = file
+            .tokens
+            .iter()
+            .map(|token| (self.similarity)(token, folder))
+            .reduce(f32::max);
+        match (self.token_matching, best_token) {
+            (_, None) | (TokenMatching::Off, _) => whole,
+            (TokenMatching::Max, Some(token)) => whole.max(token),
+            (TokenMatching::Mean, Some(token)) => (whole + token) / 2.0,
+        }
     }
+
+    /// Embedding of a folder's name `text`, from the cache if it was embedded before
+    fn folder_embedding(&self, folder: &Path, text: String) -> Result<Option<Vec<f32>>> {
+        if let Some(embedding) = self.embedding_cache.get(folder, &text) {
+            return Ok(Some(embedding));
+        }
+        let embedding = self.embed_one(text.clone())?;
+        if let Some(embedding) = &embedding {
+            self.embedding_cache.insert(folder, &text, embedding.clone());
+        }
+        Ok(embedding)
+    }
+
+    /// Embedding of a single text; `None` if the model returned none for it
+    fn embed_one(&self, text: String) -> Result<Option<Vec<f32>>> {
+        let embeddings = self.lock_model().embed(vec![text])?;
+        Ok(embeddings.into_iter().next().filter(|embedding| !embedding.is_empty()))
+    }
+
+    /// Lock the embedding model, recovering the lock if a panic while embedding
+    /// poisoned it; the model holds no state a panic could leave half-updated
+    fn lock_model(&self) -> MutexGuard<'_, Box<dyn Embedder>> {
+        self.model.lock().unwrap_or_else(|poisoned| {
+            log::warn!("Embedding model lock was poisoned by an earlier panic, recovering");
+            self.model.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    /// Resolve a low-confidence match through the prompt, if one is configured
+    /// Returns the folder to recurse into, or `None` to stay at the current level
+    fn confirm_uncertain(
+        &self,
+        file_stem: &str,
+        candidate: &Path,
+        similarity: f32,
+        alternatives: &[PathBuf],
+    ) -> Option<PathBuf> {
+        let prompt = match &self.prompt {
+            Some(prompt) => prompt,
+            None => return Some(candidate.to_path_buf()),
+        };
+
+        let key = (answer_key(file_stem), candidate.to_path_buf());
+        let remembered = self.remembered.lock().unwrap().get(&key).cloned();
+
+        let decision = match remembered {
+            Some(decision) => {
+                log::debug!("Reusing earlier answer for '{}': {:?}", file_stem, decision);
+                decision
+            }
+            None => {
+                let decision = prompt.confirm(file_stem, candidate, similarity, alternatives);
+                if self.remember_answers {
+                    self.remembered.lock().unwrap().insert(key, decision.clone());
+                }
+                decision
+            }
+        };
+
+        match decision {
+            MatchDecision::Accept => Some(candidate.to_path_buf()),
+            MatchDecision::Reject => None,
+            MatchDecision::Choose(path) => Some(path),
+        }
+    }
+}
+
+/// Keep one folder of each set whose names differ only in case: the first by name,
+/// which puts capitalized variants like "Invoices" ahead of "invoices"
+fn dedup_case_variants(folders: &mut Vec<Subfolder>) {
+    folders.sort_by(|a, b| a.1.cmp(&b.1));
+    let mut seen = HashSet::new();
+    folders.retain(|(path, name)| {
+        let first = seen.insert(name.to_lowercase());
+        if !first {
+            log::debug!("Treating {:?} as a case variant of an earlier folder", path);
+        }
+        first
+    });
+}
+
+/// `path` with each component spelled as on disk, for components whose casing
+/// differs from an existing entry; components that don't exist are kept as given
+fn existing_casing(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    let mut components = path.components();
+    for component in components.by_ref() {
+        let std::path::Component::Normal(name) = component else {
+            resolved.push(component);
+            continue;
+        };
+        let Some(name) = name.to_str() else {
+            resolved.push(name);
+            continue;
+        };
+
+        let mut siblings: Vec<String> = fs::read_dir(&resolved)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|sibling| sibling.to_lowercase() == name.to_lowercase())
+            .collect();
+        siblings.sort();
+        let found = siblings.iter().find(|sibling| *sibling == name).or(siblings.first());
+        match found {
+            Some(sibling) => resolved.push(sibling),
+            None => {
+                resolved.push(name);
+                break;
+            }
+        }
+    }
+    resolved.extend(components);
+    resolved
+}
+
+/// Turn a file or folder name into plain lowercase words for embedding
+/// Splits snake_case, kebab-case and CamelCase, and drops leading index numbers
+/// ("01_TaxReturns" -> "tax returns"). Accents are kept, including the combining
+/// marks of decomposed names as macOS stores them
+fn normalize_name(name: &str) -> String {
+    let mut words: Vec<String> = Vec::new();
+
+    for chunk in name.split(|c: char| !c.is_alphanumeric() && !is_combining_mark(c)) {
+        let chars: Vec<char> = chunk.chars().collect();
+        let mut current = String::new();
+
+        for (i, &c) in chars.iter().enumerate() {
+            if i > 0 {
+                let prev = chars[i - 1];
+                let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+                // "taxReturns" and the "P" in "HTMLParser" start a new word
+                let camel_boundary = (prev.is_lowercase() && c.is_uppercase())
+                    || (prev.is_uppercase() && c.is_uppercase() && next_is_lower);
+                if camel_boundary && !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            current.push(c);
+        }
+
+        if !current.is_empty() {
+            words.push(current);
+        }
+    }
+
+    // Drop leading index numbers like "01" in "01 Taxes", keeping names that are only numbers
+    let first_word = words
+        .iter()
+        .position(|word| !word.chars().all(|c| c.is_numeric()))
+        .unwrap_or(0);
+
+    words[first_word..]
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Combining diacritical marks, e.g. the accent of a decomposed "é"
+fn is_combining_mark(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}')
+}
+
+/// Check whether a stem is made only of boilerplate words, numbers and hex hashes
+pub fn is_opaque_stem(file_stem: &str, boilerplate_words: &HashSet<String>) -> bool {
+    file_stem
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .all(|word| {
+            boilerplate_words.contains(&word)
+                || word.chars().all(|c| c.is_numeric())
+                || (word.chars().all(|c| c.is_ascii_hexdigit())
+                    && word.chars().any(|c| c.is_ascii_digit()))
+        })
+}
+
+/// Among folders scoring within `TIE_EPSILON` of the best, move the one whose name is
+/// closest to `file_stem` by edit distance (then alphabetically) to the front, so ties
+/// don't depend on directory listing order
+fn break_ties(scored: &mut [(PathBuf, String, f32)], file_stem: &str) {
+    let Some(best) = scored.first().map(|(_, _, similarity)| *similarity) else {
+        return;
+    };
+    let tied = scored.iter().take_while(|(_, _, sim)| best - sim <= TIE_EPSILON).count();
+    if tied < 2 {
+        return;
+    }
+
+    let stem = file_stem.to_lowercase();
+    let closest = (0..tied)
+        .min_by_key(|&i| (strsim::levenshtein(&stem, &scored[i].1.to_lowercase()), &scored[i].1))
+        .unwrap_or(0);
+    scored[..=closest].rotate_right(1);
+}
+
+/// Reduce a stem to its words so similar names (e.g. differing only by dates or
+/// counters) share remembered answers
+fn answer_key(file_stem: &str) -> String {
+    file_stem
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let dot_product = dot_similarity(a, b);
     let magnitude_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
     let magnitude_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
 
@@ -187,3 +973,745 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
         dot_product / (magnitude_a * magnitude_b)
     }
 }
+
+fn dot_similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+fn euclidean_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let distance: f32 = a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt();
+    1.0 / (1.0 + distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    /// Embedder returning fixed vectors per text, and a zero vector otherwise
+    struct FakeEmbedder {
+        vectors: HashMap<String, Vec<f32>>,
+    }
+
+    impl FakeEmbedder {
+        fn new(vectors: &[(&str, Vec<f32>)]) -> Box<Self> {
+            Box::new(Self {
+                vectors: vectors
+                    .iter()
+                    .map(|(text, vector)| (text.to_string(), vector.clone()))
+                    .collect(),
+            })
+        }
+    }
+
+    impl Embedder for FakeEmbedder {
+        fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| self.vectors.get(text).cloned().unwrap_or_else(|| vec![0.0, 0.0]))
+                .collect())
+        }
+    }
+
+    /// Embedder recording every text it is asked to embed
+    struct RecordingEmbedder {
+        inner: Box<FakeEmbedder>,
+        texts: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Embedder for RecordingEmbedder {
+        fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            self.texts.lock().unwrap().extend(texts.iter().cloned());
+            self.inner.embed(texts)
+        }
+    }
+
+    /// Embedder that returns no vectors, as models have for some inputs
+    struct EmptyEmbedder;
+
+    impl Embedder for EmptyEmbedder {
+        fn embed(&mut self, _: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            Ok(Vec::new())
+        }
+    }
+
+    struct ScriptedPrompt {
+        decision: MatchDecision,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl MatchPrompt for ScriptedPrompt {
+        fn confirm(&self, _: &str, _: &Path, _: f32, _: &[PathBuf]) -> MatchDecision {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.decision.clone()
+        }
+    }
+
+    #[test]
+    fn test_similarity_metrics_order_folders_differently() {
+        let file = [1.0, 0.0];
+        // Same direction but longer, slightly off but close, and far off but long
+        let folders = [("same", [2.0, 0.0]), ("close", [0.9, 0.1]), ("long", [3.0, 3.0])];
+
+        let ranking = |metric: SimilarityMetric| {
+            let similarity = metric.function();
+            let mut ranked: Vec<_> =
+                folders.iter().map(|(name, folder)| (*name, similarity(&file, folder))).collect();
+            ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+            ranked.into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+        assert_eq!(ranking(SimilarityMetric::Cosine), ["same", "close", "long"]);
+        assert_eq!(ranking(SimilarityMetric::Dot), ["long", "same", "close"]);
+        assert_eq!(ranking(SimilarityMetric::Euclidean), ["close", "same", "long"]);
+
+        // Unit vectors score the same under cosine and dot
+        let (a, b) = ([0.6, 0.8], [1.0, 0.0]);
+        assert!((cosine_similarity(&a, &b) - dot_similarity(&a, &b)).abs() < 1e-6);
+        assert_eq!(euclidean_similarity(&a, &a), 1.0);
+    }
+
+    #[test]
+    fn test_missing_embedding_falls_back_to_destination() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Taxes"))?;
+        let matcher =
+            SubfolderMatcher::with_embedder(Box::new(EmptyEmbedder), &MatcherConfig::default());
+
+        let file = temp_dir.path().join("tax_2023.pdf");
+        assert_eq!(matcher.find_matching_subfolder(&file, temp_dir.path())?, temp_dir.path());
+        let file = temp_dir.path().join("   .pdf");
+        assert_eq!(matcher.find_matching_subfolder(&file, temp_dir.path())?, temp_dir.path());
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_matching_finds_folder_named_after_one_word() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Acme"))?;
+        fs::create_dir(temp_dir.path().join("Recipes"))?;
+        let file = temp_dir.path().join("2023_invoice_acme_final.pdf");
+
+        // The whole name's embedding is diluted by the other words
+        let vectors = [
+            ("invoice acme final", vec![0.5, 0.866, 0.0]),
+            ("acme", vec![1.0, 0.0, 0.0]),
+            ("recipes", vec![0.0, 0.0, 1.0]),
+        ];
+        let matcher_with = |token_matching| {
+            let config = MatcherConfig {
+                token_matching,
+                ..Default::default()
+            };
+            SubfolderMatcher::with_embedder(FakeEmbedder::new(&vectors), &config)
+        };
+
+        let whole = matcher_with(TokenMatching::Off);
+        assert_eq!(whole.find_matching_subfolder(&file, temp_dir.path())?, temp_dir.path());
+
+        let max = matcher_with(TokenMatching::Max).find_match(&file, temp_dir.path())?;
+        assert_eq!(max.path, temp_dir.path().join("Acme"));
+        assert_eq!(max.similarity, Some(1.0));
+
+        // (0.5 + 1.0) / 2
+        let mean = matcher_with(TokenMatching::Mean).find_match(&file, temp_dir.path())?;
+        assert_eq!(mean.path, temp_dir.path().join("Acme"));
+        assert!((mean.similarity.unwrap() - 0.75).abs() < 1e-3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_variant_folders_resolve_to_one() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("invoices"))?;
+        fs::create_dir(temp_dir.path().join("Invoices"))?;
+        let file = temp_dir.path().join("invoices_march.pdf");
+
+        let vectors = [("invoices march", vec![1.0, 0.0]), ("invoices", vec![1.0, 0.0])];
+        let matcher_with = |folder_case| {
+            let config = MatcherConfig {
+                folder_case,
+                ..Default::default()
+            };
+            SubfolderMatcher::with_embedder(FakeEmbedder::new(&vectors), &config)
+        };
+
+        let sensitive = matcher_with(FolderCase::Sensitive).find_match(&file, temp_dir.path())?;
+        assert_eq!(sensitive.candidates.len(), 2);
+
+        let insensitive =
+            matcher_with(FolderCase::Insensitive).find_match(&file, temp_dir.path())?;
+        assert_eq!(insensitive.path, temp_dir.path().join("Invoices"));
+        assert_eq!(insensitive.candidates.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_aliased_folders_route_to_canonical_folder() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Invoice"))?;
+        fs::create_dir(temp_dir.path().join("invoices-2023"))?;
+        fs::create_dir(temp_dir.path().join("Recipes"))?;
+
+        let embedder = FakeEmbedder::new(&[
+            ("invoice march", vec![1.0, 0.0]),
+            ("invoices q1", vec![0.6, 0.8]),
+            ("invoice", vec![1.0, 0.0]),
+            ("invoices 2023", vec![0.6, 0.8]),
+            ("recipes", vec![-1.0, 0.0]),
+        ]);
+        let config = MatcherConfig {
+            folder_aliases: HashMap::from([
+                ("Invoice".to_string(), "Invoices".to_string()),
+                ("invoices-2023".to_string(), "Invoices".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let matcher = SubfolderMatcher::with_embedder(embedder, &config);
+
+        // Each file is closest to a different alias, and both go to the canonical folder
+        let canonical = temp_dir.path().join("Invoices");
+        for name in ["invoice_march.pdf", "2023_invoices_q1.pdf"] {
+            let found = matcher.find_match(&temp_dir.path().join(name), temp_dir.path())?;
+            assert_eq!(found.path, canonical);
+            assert_eq!(found.similarity, Some(1.0));
+            assert_eq!(found.candidates.len(), 2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_existing_casing() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir_all(temp_dir.path().join("Docs").join("Invoices"))?;
+
+        let given = temp_dir.path().join("docs").join("INVOICES").join("2024");
+        let expected = temp_dir.path().join("Docs").join("Invoices").join("2024");
+        assert_eq!(existing_casing(&given), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_uncertain_match_asks_prompt() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Taxes"))?;
+
+        // cos = 0.72: above the threshold but inside the default uncertain band
+        let embedder = FakeEmbedder::new(&[
+            ("tax 2023", vec![1.0, 0.0]),
+            ("taxes", vec![0.72, (1.0f32 - 0.72 * 0.72).sqrt()]),
+        ]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let matcher = SubfolderMatcher::with_embedder(embedder, &MatcherConfig::default())
+            .with_prompt(Box::new(ScriptedPrompt {
+                decision: MatchDecision::Reject,
+                calls: calls.clone(),
+            }));
+
+        let file = temp_dir.path().join("tax_2023.pdf");
+        assert_eq!(matcher.find_matching_subfolder(&file, temp_dir.path())?, temp_dir.path());
+
+        // The answer is remembered for stems differing only by numbers
+        let file = temp_dir.path().join("tax_2024.pdf");
+        matcher.find_matching_subfolder(&file, temp_dir.path())?;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_confident_match_skips_prompt() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Taxes"))?;
+
+        let embedder = FakeEmbedder::new(&[
+            ("tax 2023", vec![1.0, 0.0]),
+            ("taxes", vec![1.0, 0.0]),
+        ]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let matcher = SubfolderMatcher::with_embedder(embedder, &MatcherConfig::default())
+            .with_prompt(Box::new(ScriptedPrompt {
+                decision: MatchDecision::Reject,
+                calls: calls.clone(),
+            }));
+
+        let file = temp_dir.path().join("tax_2023.pdf");
+        assert_eq!(
+            matcher.find_matching_subfolder(&file, temp_dir.path())?,
+            temp_dir.path().join("Taxes")
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("tax_returns_2023"), "tax returns 2023");
+        assert_eq!(normalize_name("ProjectAlpha"), "project alpha");
+        assert_eq!(normalize_name("IMG-scan_invoice"), "img scan invoice");
+        assert_eq!(normalize_name("HTMLParser"), "html parser");
+        assert_eq!(normalize_name("01 - Taxes"), "taxes");
+        assert_eq!(normalize_name("2023"), "2023");
+        assert_eq!(normalize_name("Résumés"), "résumés");
+        assert_eq!(normalize_name("Übersicht_Rechnungen"), "übersicht rechnungen");
+        assert_eq!(normalize_name("ÉtatDesLieux"), "état des lieux");
+        // Decomposed, as in names from macOS
+        let decomposed = "Factures_Re\u{301}gle\u{301}es";
+        assert_eq!(normalize_name(decomposed), "factures re\u{301}gle\u{301}es");
+    }
+
+    #[test]
+    fn test_snake_case_stem_matches_spaced_folder() -> Result<()> {
+        let embedder = || {
+            FakeEmbedder::new(&[
+                ("tax returns", vec![1.0, 0.0]),
+                ("receipts", vec![0.0, 1.0]),
+            ])
+        };
+
+        for folder in ["tax_returns", "Tax Returns"] {
+            let temp_dir = TempDir::new()?;
+            fs::create_dir(temp_dir.path().join(folder))?;
+            fs::create_dir(temp_dir.path().join("Receipts"))?;
+
+            let matcher = SubfolderMatcher::with_embedder(embedder(), &MatcherConfig::default());
+            let file = temp_dir.path().join("tax_returns.pdf");
+            assert_eq!(
+                matcher.find_matching_subfolder(&file, temp_dir.path())?,
+                temp_dir.path().join(folder)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_opaque_stems_skip_matching() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Reports"))?;
+
+        // Every stem embeds identically to the folder, so only the heuristic prevents a match
+        let embedder = FakeEmbedder::new(&[
+            ("img 1234", vec![1.0, 0.0]),
+            ("download 2", vec![1.0, 0.0]),
+            ("quarterly report", vec![1.0, 0.0]),
+            ("reports", vec![1.0, 0.0]),
+        ]);
+        let matcher = SubfolderMatcher::with_embedder(embedder, &MatcherConfig::default());
+
+        for name in ["IMG_1234.jpg", "download (2).pdf"] {
+            let file = temp_dir.path().join(name);
+            assert_eq!(matcher.find_matching_subfolder(&file, temp_dir.path())?, temp_dir.path());
+        }
+
+        let file = temp_dir.path().join("quarterly_report.pdf");
+        assert_eq!(
+            matcher.find_matching_subfolder(&file, temp_dir.path())?,
+            temp_dir.path().join("Reports")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_opaque_stem() {
+        let boilerplate: HashSet<String> = MatcherConfig::default().boilerplate_words.into_iter().collect();
+
+        assert!(is_opaque_stem("Screenshot 2024-01-02 at 1", &boilerplate));
+        assert!(is_opaque_stem("a3f9c2", &boilerplate));
+        assert!(!is_opaque_stem("cafe", &boilerplate));
+        assert!(!is_opaque_stem("IMG_beach", &boilerplate));
+    }
+
+    #[test]
+    fn test_max_match_depth_stops_recursion() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir_all(temp_dir.path().join("Work").join("Clients").join("Acme"))?;
+
+        let embedder = FakeEmbedder::new(&[
+            ("acme contract", vec![1.0, 0.0]),
+            ("work", vec![0.9, 0.436]),
+            ("clients", vec![0.95, 0.312]),
+            ("acme", vec![1.0, 0.0]),
+        ]);
+        let config = MatcherConfig {
+            max_match_depth: Some(2),
+            ..Default::default()
+        };
+        let matcher = SubfolderMatcher::with_embedder(embedder, &config);
+
+        let file = temp_dir.path().join("acme_contract.pdf");
+        assert_eq!(
+            matcher.find_matching_subfolder(&file, temp_dir.path())?,
+            temp_dir.path().join("Work").join("Clients")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_recursion_margin_keeps_marginal_matches_at_parent() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir_all(temp_dir.path().join("Work").join("Reports"))?;
+        let embedder = || {
+            FakeEmbedder::new(&[
+                ("quarterly summary", vec![1.0, 0.0]),
+                ("work", vec![0.9, 0.436]),
+                ("reports", vec![0.92, 0.392]),
+            ])
+        };
+        let file = temp_dir.path().join("quarterly_summary.pdf");
+
+        // Reports (0.92) beats Work (0.9), but not by the margin
+        let config = MatcherConfig {
+            recursion_margin: Some(0.05),
+            ..Default::default()
+        };
+        let matcher = SubfolderMatcher::with_embedder(embedder(), &config);
+        let found = matcher.find_match(&file, temp_dir.path())?;
+        assert_eq!(found.path, temp_dir.path().join("Work"));
+        assert!(found.similarity.is_some_and(|similarity| (similarity - 0.9).abs() < 0.01));
+
+        let matcher = SubfolderMatcher::with_embedder(embedder(), &MatcherConfig::default());
+        assert_eq!(
+            matcher.find_matching_subfolder(&file, temp_dir.path())?,
+            temp_dir.path().join("Work").join("Reports")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_folder_names_match_below_the_threshold() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir_all(temp_dir.path().join("Taxes"))?;
+        fs::create_dir_all(temp_dir.path().join("Tax Returns"))?;
+        fs::create_dir_all(temp_dir.path().join("Photos"))?;
+        // The embeddings favor Photos, and nothing reaches the threshold
+        let matcher = SubfolderMatcher::with_embedder(
+            FakeEmbedder::new(&[
+                ("taxes", vec![1.0, 0.0]),
+                ("tax returns final", vec![1.0, 0.0]),
+                ("photos", vec![0.6, 0.8]),
+                ("tax returns", vec![0.0, 1.0]),
+            ]),
+            &MatcherConfig::default(),
+        );
+
+        let matched = |name: &str| {
+            matcher.find_matching_subfolder(&temp_dir.path().join(name), temp_dir.path())
+        };
+        assert_eq!(matched("Taxes.pdf")?, temp_dir.path().join("Taxes"));
+        assert_eq!(matched("01_taxes.pdf")?, temp_dir.path().join("Taxes"));
+        // A run of words in the name counts too
+        assert_eq!(matched("2023_TaxReturns_final.pdf")?, temp_dir.path().join("Tax Returns"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generic_folder_names_dont_match_by_name() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        for folder in ["Final", "2023", "Reports"] {
+            fs::create_dir_all(temp_dir.path().join(folder))?;
+        }
+        let matcher = SubfolderMatcher::with_embedder(
+            FakeEmbedder::new(&[
+                ("final", vec![0.0, 1.0]),
+                ("2023", vec![0.0, 1.0]),
+                ("reports", vec![1.0, 0.0]),
+                ("report final", vec![0.8, 0.6]),
+                ("report 2023", vec![0.8, 0.6]),
+                ("final 2023", vec![0.8, 0.6]),
+            ]),
+            &MatcherConfig::default(),
+        );
+
+        let matched = |name: &str| {
+            matcher.find_matching_subfolder(&temp_dir.path().join(name), temp_dir.path())
+        };
+        // One generic word of several is left to the embeddings
+        assert_eq!(matched("report_final.pdf")?, temp_dir.path().join("Reports"));
+        assert_eq!(matched("report_2023.pdf")?, temp_dir.path().join("Reports"));
+        // A file named only by that word still matches it
+        assert_eq!(matched("final_2023.pdf")?, temp_dir.path().join("Final"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_folder_embeddings_persist_across_sessions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let destination = temp_dir.path().join("Documents");
+        fs::create_dir_all(destination.join("Taxes"))?;
+        fs::create_dir_all(destination.join("Photos"))?;
+        let cache_path = temp_dir.path().join("config").join("embeddings.json");
+
+        // Each session is a fresh matcher, as after a restart
+        let session = || {
+            let texts = Arc::new(Mutex::new(Vec::new()));
+            let embedder = RecordingEmbedder {
+                inner: FakeEmbedder::new(&[
+                    ("tax 2023", vec![1.0, 0.0]),
+                    ("taxes", vec![1.0, 0.0]),
+                    ("photos", vec![0.0, 1.0]),
+                    ("receipts", vec![0.0, 1.0]),
+                ]),
+                texts: texts.clone(),
+            };
+            let config = MatcherConfig::default();
+            let matcher = SubfolderMatcher::with_embedder(Box::new(embedder), &config)
+                .with_embedding_cache(cache_path.clone());
+            (matcher, texts)
+        };
+        let file = temp_dir.path().join("tax_2023.pdf");
+        let taxes = destination.join("Taxes");
+
+        let (matcher, texts) = session();
+        assert_eq!(matcher.find_matching_subfolder(&file, &destination)?, taxes);
+        assert_eq!(texts.lock().unwrap().len(), 3);
+        // Later changes aren't written right away, but are once the matcher is dropped
+        fs::create_dir(destination.join("Receipts"))?;
+        matcher.invalidate_folder(&destination);
+        matcher.find_matching_subfolder(&file, &destination)?;
+        assert!(!fs::read_to_string(&cache_path)?.contains("receipts"));
+        drop(matcher);
+        assert!(fs::read_to_string(&cache_path)?.contains("receipts"));
+
+        // Only the file name is embedded once the folders are known, even after
+        // files were moved into them
+        fs::write(taxes.join("tax_2022.pdf"), b"")?;
+        let (matcher, texts) = session();
+        assert_eq!(matcher.find_matching_subfolder(&file, &destination)?, taxes);
+        assert_eq!(*texts.lock().unwrap(), vec!["tax 2023"]);
+
+        // A renamed folder is embedded again under its new name
+        fs::rename(destination.join("Photos"), destination.join("Pictures"))?;
+        let (matcher, texts) = session();
+        matcher.find_matching_subfolder(&file, &destination)?;
+        assert_eq!(*texts.lock().unwrap(), vec!["tax 2023", "pictures"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_folder_listing_is_cached_until_invalidated() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Taxes"))?;
+        let cache = FolderCache::default();
+        let reads = AtomicUsize::new(0);
+        let counting_read = |dir: &Path| {
+            reads.fetch_add(1, Ordering::SeqCst);
+            read_subfolders(dir)
+        };
+
+        // Two files organized back to back share one read
+        let first = cache.subfolders(temp_dir.path(), counting_read);
+        let second = cache.subfolders(temp_dir.path(), counting_read);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        assert_eq!(first, second);
+
+        // A folder created by AutoFile shows up after invalidation
+        fs::create_dir(temp_dir.path().join("Receipts"))?;
+        cache.invalidate(temp_dir.path());
+        assert_eq!(cache.subfolders(temp_dir.path(), counting_read).len(), 2);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_model_is_chosen_by_name() {
+        let default = MatcherConfig::default().model.parse::<EmbeddingModel>();
+        assert_eq!(default, Ok(EmbeddingModel::default()));
+        assert_eq!(
+            "multilinguale5small".parse::<EmbeddingModel>(),
+            Ok(EmbeddingModel::MultilingualE5Small)
+        );
+
+        // Rejected before anything is downloaded
+        let config = MatcherConfig {
+            model: "NoSuchModel".to_string(),
+            ..Default::default()
+        };
+        assert!(SubfolderMatcher::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_accented_names_reach_the_model_intact() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Comptabilité"))?;
+        fs::create_dir(temp_dir.path().join("Übersetzungen"))?;
+
+        let texts = Arc::new(Mutex::new(Vec::new()));
+        let embedder = RecordingEmbedder {
+            inner: FakeEmbedder::new(&[
+                ("facture électricité français", vec![1.0, 0.0]),
+                ("comptabilité", vec![1.0, 0.0]),
+                ("übersetzungen", vec![0.0, 1.0]),
+            ]),
+            texts: texts.clone(),
+        };
+        let config = MatcherConfig {
+            language_hint: Some("français".to_string()),
+            ..Default::default()
+        };
+        let matcher = SubfolderMatcher::with_embedder(Box::new(embedder), &config);
+
+        let file = temp_dir.path().join("Facture_Électricité.pdf");
+        assert_eq!(
+            matcher.find_matching_subfolder(&file, temp_dir.path())?,
+            temp_dir.path().join("Comptabilité")
+        );
+        let mut texts = texts.lock().unwrap().clone();
+        texts.sort();
+        assert_eq!(texts, ["comptabilité", "facture électricité français", "übersetzungen"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_category_hint_steers_ambiguous_stem() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Journal"))?;
+        fs::create_dir(temp_dir.path().join("Snippets"))?;
+
+        let embedder = || {
+            FakeEmbedder::new(&[
+                ("notes", vec![1.0, 0.0]),
+                ("notes source code project", vec![0.0, 1.0]),
+                ("journal", vec![1.0, 0.0]),
+                ("snippets", vec![0.0, 1.0]),
+            ])
+        };
+        let file = temp_dir.path().join("notes.md");
+
+        // Hints are off by default
+        let plain = SubfolderMatcher::with_embedder(embedder(), &MatcherConfig::default());
+        assert_eq!(
+            plain.find_category_match(&file, temp_dir.path(), &FileCategory::Code)?.path,
+            temp_dir.path().join("Journal")
+        );
+
+        let config = MatcherConfig {
+            category_hints: HashMap::from([("Code".to_string(), "source code project".to_string())]),
+            ..Default::default()
+        };
+        let hinted = SubfolderMatcher::with_embedder(embedder(), &config);
+        assert_eq!(
+            hinted.find_category_match(&file, temp_dir.path(), &FileCategory::Code)?.path,
+            temp_dir.path().join("Snippets")
+        );
+        // Other categories are unaffected
+        assert_eq!(
+            hinted.find_category_match(&file, temp_dir.path(), &FileCategory::Document)?.path,
+            temp_dir.path().join("Journal")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_poisoned_model_lock_is_recovered() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Taxes"))?;
+        let matcher = SubfolderMatcher::with_embedder(FakeEmbedder::new(&[]), &MatcherConfig::default());
+
+        // A thread panicking while holding the lock poisons it
+        let model = matcher.model.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = model.lock().unwrap();
+            panic!("embedding failed");
+        })
+        .join();
+        assert!(matcher.model.is_poisoned());
+
+        let file = temp_dir.path().join("tax_2023.pdf");
+        assert_eq!(matcher.find_matching_subfolder(&file, temp_dir.path())?, temp_dir.path());
+        assert!(!matcher.model.is_poisoned());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_level_is_not_matched() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Taxes"))?;
+        let embedder = || {
+            FakeEmbedder::new(&[("tax 2023", vec![1.0, 0.0]), ("taxes", vec![1.0, 0.0])])
+        };
+        let file = temp_dir.path().join("tax_2023.pdf");
+
+        let matcher = SubfolderMatcher::with_embedder(embedder(), &MatcherConfig::default());
+        assert_eq!(
+            matcher.find_matching_subfolder(&file, temp_dir.path())?,
+            temp_dir.path().join("Taxes")
+        );
+
+        let config = MatcherConfig {
+            min_folders_to_match: 2,
+            ..Default::default()
+        };
+        let texts = Arc::new(Mutex::new(Vec::new()));
+        let recording = RecordingEmbedder {
+            inner: embedder(),
+            texts: texts.clone(),
+        };
+        let matcher = SubfolderMatcher::with_embedder(Box::new(recording), &config);
+        assert_eq!(matcher.find_matching_subfolder(&file, temp_dir.path())?, temp_dir.path());
+        // The level is skipped before anything, the file name included, is embedded
+        assert!(texts.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_context_steers_match() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("ClientA"))?;
+        fs::create_dir(temp_dir.path().join("ClientB"))?;
+        let matcher = SubfolderMatcher::with_embedder(
+            FakeEmbedder::new(&[
+                ("notes", vec![0.0, 1.0]),
+                ("notes client a", vec![1.0, 0.0]),
+                ("client a", vec![1.0, 0.0]),
+                ("client b", vec![0.0, 1.0]),
+            ]),
+            &MatcherConfig::default(),
+        );
+        let match_with = |name: &str, context| {
+            let file = temp_dir.path().join(name);
+            matcher.find_contextual_match(&file, temp_dir.path(), &FileCategory::Document, context)
+        };
+
+        assert_eq!(match_with("notes.pdf", None)?.path, temp_dir.path().join("ClientB"));
+        assert_eq!(match_with("notes.pdf", Some("ClientA"))?.path, temp_dir.path().join("ClientA"));
+        // Opaque names are matched by their context alone
+        assert_eq!(match_with("IMG_4821.jpg", None)?.path, temp_dir.path());
+        assert_eq!(
+            match_with("IMG_4821.jpg", Some("ClientA"))?.path,
+            temp_dir.path().join("ClientA")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_near_ties_prefer_lexically_closer_folder() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("Archive"))?;
+        fs::create_dir(temp_dir.path().join("Taxes"))?;
+        fs::create_dir(temp_dir.path().join("Bills"))?;
+        let matcher = SubfolderMatcher::with_embedder(
+            FakeEmbedder::new(&[
+                ("tax", vec![1.0, 0.0]),
+                ("archive", vec![1.0, 0.001]),
+                ("taxes", vec![1.0, 0.002]),
+                ("bills", vec![0.0, 1.0]),
+            ]),
+            &MatcherConfig::default(),
+        );
+
+        let file = temp_dir.path().join("tax.pdf");
+        assert_eq!(
+            matcher.find_matching_subfolder(&file, temp_dir.path())?,
+            temp_dir.path().join("Taxes")
+        );
+
+        let mut scored = vec![
+            (PathBuf::from("b"), "Receipts".to_string(), 0.9),
+            (PathBuf::from("a"), "Invoices".to_string(), 0.895),
+            (PathBuf::from("c"), "Invoice".to_string(), 0.5),
+        ];
+        break_ties(&mut scored, "invoice_march");
+        assert_eq!(scored[0].1, "Invoices");
+        assert_eq!(scored[1].1, "Receipts");
+        Ok(())
+    }
+}
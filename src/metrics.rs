@@ -0,0 +1,176 @@
+use crate::organizer::OrganizeOutcome;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long a scrape may take to send its request before it's dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters exported in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    /// Category name to files moved
+    organized: Mutex<BTreeMap<String, u64>>,
+    /// Outcome name to files processed
+    outcomes: Mutex<BTreeMap<&'static str, u64>>,
+    errors: AtomicU64,
+    bytes_moved: AtomicU64,
+    match_hits: AtomicU64,
+    match_misses: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the outcome of organizing one file detected as `category`
+    pub fn record(&self, result: &Result<OrganizeOutcome>, category: Option<&str>) {
+        let outcome = match result {
+            Ok(outcome) => outcome.name(),
+            Err(_) => "error",
+        };
+        *self.outcomes.lock().unwrap().entry(outcome).or_default() += 1;
+
+        match result {
+            Ok(OrganizeOutcome::Moved(path)) => {
+                let category = category.unwrap_or("unknown").to_string();
+                *self.organized.lock().unwrap().entry(category).or_default() += 1;
+                let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+                self.bytes_moved.fetch_add(size, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    /// Count a subfolder search, which hit if it found a folder
+    pub fn record_match(&self, hit: bool) {
+        let counter = if hit { &self.match_hits } else { &self.match_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Every counter in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+
+        let name = "files_organized_total";
+        header(&mut text, name, "Files moved to their destination, by category");
+        for (category, count) in self.organized.lock().unwrap().iter() {
+            let category = escape_label(category);
+            let _ = writeln!(text, "autofile_{}{{category=\"{}\"}} {}", name, category, count);
+        }
+
+        let name = "files_processed_total";
+        header(&mut text, name, "Files processed, by outcome");
+        for (outcome, count) in self.outcomes.lock().unwrap().iter() {
+            let _ = writeln!(text, "autofile_{}{{outcome=\"{}\"}} {}", name, outcome, count);
+        }
+
+        let counters = [
+            ("errors_total", "Files that failed to organize", &self.errors),
+            ("bytes_moved_total", "Bytes of files moved", &self.bytes_moved),
+            ("match_hits_total", "Subfolder searches that found a folder", &self.match_hits),
+            ("match_misses_total", "Subfolder searches that found no folder", &self.match_misses),
+        ];
+        for (name, help, counter) in counters {
+            header(&mut text, name, help);
+            let _ = writeln!(text, "autofile_{} {}", name, counter.load(Ordering::Relaxed));
+        }
+        text
+    }
+}
+
+fn header(text: &mut String, name: &str, help: &str) {
+    let _ = writeln!(text, "# HELP autofile_{} {}", name, help);
+    let _ = writeln!(text, "# TYPE autofile_{} counter", name);
+}
+
+/// Escape a label value as the text format requires
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serve `metrics` at `http://<listen>/metrics` from a background thread
+/// Returns the address actually bound, which differs from `listen` for port 0.
+pub fn serve(listen: &str, metrics: Arc<Metrics>) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(listen)
+        .context(format!("Failed to listen for metrics scrapes on {}", listen))?;
+    let address = listener.local_addr()?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = respond(stream, &metrics) {
+                log::debug!("Failed to answer metrics request: {}", e);
+            }
+        }
+    });
+    Ok(address)
+}
+
+/// Answer one HTTP request, with the metrics for `GET /metrics` and 404 otherwise
+fn respond(stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers don't matter, but are read so the client sees a clean close
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_serves_counters_in_text_format() -> Result<()> {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record(&Err(anyhow::anyhow!("disk full")), Some("video"));
+        metrics.record_match(false);
+        let address = serve("127.0.0.1:0", metrics)?;
+
+        let mut stream = TcpStream::connect(address)?;
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("autofile_errors_total 1\n"));
+        assert!(response.contains("autofile_files_processed_total{outcome=\"error\"} 1\n"));
+        assert!(response.contains("autofile_match_misses_total 1\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label(r#"My "Taxes"\2024"#), r#"My \"Taxes\"\\2024"#);
+    }
+}
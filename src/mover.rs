@@ -1,77 +1,577 @@
+use crate::config::NormalizeFilenamesConfig;
+use crate::filesystem::{FileSystem, RealFileSystem};
 use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
 use std::fs;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Largest amount copied between throttle and progress checks
+const COPY_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Progress is reported each time another this-many percent has been copied
+const PROGRESS_STEP_PERCENT: u64 = 10;
+
+/// Token bucket limiting copy throughput to a fixed number of bytes per second
+///
+/// Shared across moves so the limit applies to the organizer as a whole.
+pub struct Throttle {
+    bytes_per_sec: u64,
+    /// Available bytes and when they were last topped up
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            bucket: Mutex::new((0.0, Instant::now())),
+        }
+    }
+
+    /// Block until `bytes` may be written without exceeding the limit
+    fn acquire(&self, bytes: usize) {
+        let rate = self.bytes_per_sec as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let (tokens, refilled_at) = &mut *bucket;
+            let now = Instant::now();
+            // At most one second of unused bandwidth can be saved up
+            *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * rate).min(rate);
+            *refilled_at = now;
+            *tokens -= bytes as f64;
+            if *tokens < 0.0 {
+                Duration::from_secs_f64(-*tokens / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+        std::thread::sleep(wait);
+    }
+
+    fn chunk_size(&self) -> usize {
+        COPY_CHUNK_SIZE.min(self.bytes_per_sec as usize).max(1)
+    }
+}
+
+/// Longest file name most filesystems accept (ext4, APFS, and NTFS in all but rare cases)
+const MAX_FILE_NAME_BYTES: usize = 255;
+
+/// Suffix of the temporary name staged copies are written under
+const PARTIAL_SUFFIX: &str = "autofile-partial";
+
+/// What to do when the destination folder already has a file of the same name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Keep both, numbering the moved file ("name (1).ext")
+    #[default]
+    Rename,
+    /// Leave the new file where it is
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Replace the existing file if the new one was modified more recently,
+    /// otherwise leave the new file where it is
+    OverwriteIfNewer,
+}
+
+/// Template for what `ConflictStrategy::Rename` appends to a file's stem, with
+/// `{n}` standing for the number (" ({n})" gives "name (1).ext")
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConflictSuffix(String);
+
+impl ConflictSuffix {
+    /// Fails unless `template` contains the `{n}` placeholder
+    pub fn new(template: impl Into<String>) -> Result<Self> {
+        let template = template.into();
+        if !template.contains("{n}") {
+            anyhow::bail!("Conflict suffix {:?} must contain the {{n}} placeholder", template);
+        }
+        Ok(Self(template))
+    }
+
+    fn render(&self, n: u32) -> String {
+        self.0.replace("{n}", &n.to_string())
+    }
+
+    /// The number in `text` if it is this suffix rendered with one
+    fn parse(&self, text: &str) -> Option<u32> {
+        let (before, after) = self.0.split_once("{n}")?;
+        let digits = text.strip_prefix(before)?.strip_suffix(after)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+}
+
+impl Default for ConflictSuffix {
+    fn default() -> Self {
+        Self(" ({n})".to_string())
+    }
+}
+
+impl TryFrom<String> for ConflictSuffix {
+    type Error = anyhow::Error;
+
+    fn try_from(template: String) -> Result<Self> {
+        Self::new(template)
+    }
+}
+
+impl From<ConflictSuffix> for String {
+    fn from(suffix: ConflictSuffix) -> Self {
+        suffix.0
+    }
+}
+
+/// What happened to a file whose name was already taken in the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Given a numbered name
+    Renamed,
+    /// Left where it was
+    Skipped,
+    /// Replaced the existing file
+    Overwritten,
+}
+
+/// Where a move would put a file, from `FileMover::plan_move`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedMove {
+    /// `None` if the file would be left in place
+    pub destination: Option<PathBuf>,
+    /// How an existing file with the same name was dealt with, if there was one
+    pub conflict: Option<ConflictResolution>,
+}
+
+/// How a move handles name conflicts and its copy fallback
+#[derive(Clone, Copy)]
+pub struct MoveOptions<'a> {
+    /// Filesystem the move happens on; the real disk by default
+    pub fs: &'a dyn FileSystem,
+    /// Limit copies to this rate
+    pub throttle: Option<&'a Throttle>,
+    /// Copy to a hidden temporary name first and rename it once complete, so a
+    /// half-copied file never appears under the final name
+    pub staged: bool,
+    pub conflict: ConflictStrategy,
+    /// How renamed files are numbered; " ({n})" when unset
+    pub suffix: Option<&'a ConflictSuffix>,
+    /// Tidy the file name before placing it, and before checking for conflicts
+    pub names: Option<&'a NormalizeFilenamesConfig>,
+    /// Write a gzipped copy named "name.ext.gz" instead of moving the file; the
+    /// original is left for the caller to dispose of
+    pub compress: bool,
+    /// Copy the file instead of moving it, leaving the source in place
+    pub keep_source: bool,
+}
+
+impl Default for MoveOptions<'_> {
+    fn default() -> Self {
+        Self {
+            fs: &RealFileSystem,
+            throttle: None,
+            staged: false,
+            conflict: ConflictStrategy::default(),
+            suffix: None,
+            names: None,
+            compress: false,
+            keep_source: false,
+        }
+    }
+}
 
 pub struct FileMover;
 
 impl FileMover {
     pub fn move_file(source: &Path, destination_dir: &Path) -> Result<PathBuf> {
-        if !source.exists() {
+        // Name conflicts are renamed by default, so the file is always moved
+        Self::move_file_with(source, destination_dir, MoveOptions::default())?
+            .context("File was left in place")
+    }
+
+    /// Move a file, copying according to `options` when a rename isn't possible
+    /// Returns `None` when the conflict strategy left the file in place.
+    pub fn move_file_with(
+        source: &Path,
+        destination_dir: &Path,
+        options: MoveOptions,
+    ) -> Result<Option<PathBuf>> {
+        let fs = options.fs;
+        if !fs.exists(source) {
             anyhow::bail!("Source file does not exist: {:?}", source);
         }
 
-        if !destination_dir.exists() {
-            fs::create_dir_all(destination_dir).context(format!(
+        if !fs.exists(destination_dir) {
+            fs.create_dir_all(destination_dir).context(format!(
                 "Failed to create destination directory: {:?}",
                 destination_dir
             ))?;
         }
 
-        let file_name = source
-            .file_name()
-            .context("Could not extract file name")?;
+        let Some(destination) = Self::planned_destination(source, destination_dir, options)?
+        else {
+            return Ok(None);
+        };
 
-        let mut destination = destination_dir.join(file_name);
-
-        // Handle file name conflicts
-        destination = Self::resolve_conflict(&destination)?;
+        if options.compress || options.keep_source {
+            let verb = if options.compress { "Compressing" } else { "Copying" };
+            log::info!("{} {:?} -> {:?}", verb, source, destination);
+            Self::copy_across(source, &destination, options, |_, _| Ok(()))?;
+            return Ok(Some(destination));
+        }
 
         log::info!("Moving {:?} -> {:?}", source, destination);
 
         // Attempt to move the file
-        match fs::rename(source, &destination) {
+        match fs.rename(source, &destination) {
             Ok(_) => {
                 log::info!("Successfully moved file to {:?}", destination);
-                Ok(destination)
+                Ok(Some(destination))
             }
             Err(e) => {
                 // If rename fails (e.g., across filesystems), try copy + delete
                 log::warn!("Rename failed, attempting copy + delete: {}", e);
-                fs::copy(source, &destination).context("Failed to copy file")?;
-                fs::remove_file(source).context("Failed to remove source file after copy")?;
+                Self::copy_across(source, &destination, options, |copied, total| {
+                    log::info!(
+                        "Copying {:?}: {}% ({} of {} bytes)",
+                        source.file_name().unwrap_or_default(),
+                        copied * 100 / total.max(1),
+                        copied,
+                        total
+                    );
+                    Ok(())
+                })?;
+                fs.remove_file(source).context("Failed to remove source file after copy")?;
                 log::info!("Successfully copied and removed file to {:?}", destination);
-                Ok(destination)
+                Ok(Some(destination))
             }
         }
     }
 
-    fn resolve_conflict(path: &Path) -> Result<PathBuf> {
-        if !path.exists() {
-            return Ok(path.to_path_buf());
+    /// Copy `source` to `destination`, staging it under a temporary name if requested
+    /// The source is left untouched; when staged, nothing appears under the final name on failure
+    fn copy_across(
+        source: &Path,
+        destination: &Path,
+        options: MoveOptions,
+        on_progress: impl FnMut(u64, u64) -> Result<()>,
+    ) -> Result<()> {
+        let fs = options.fs;
+        Self::check_space(fs, source, destination)?;
+        let copy = |to: &Path| {
+            if options.compress {
+                Self::compress_chunked(fs, source, to, options.throttle, on_progress)
+            } else {
+                Self::copy_chunked(fs, source, to, options.throttle, on_progress)
+            }
+        };
+        if !options.staged {
+            return copy(destination);
         }
 
-        let file_stem = path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .context("Could not extract file stem")?;
+        let partial = partial_path(destination)?;
+        let copied = copy(&partial)
+            .and_then(|_| fs.rename(&partial, destination).context("Failed to rename staged copy"));
+        if copied.is_err() {
+            let _ = fs.remove_file(&partial);
+        }
+        copied
+    }
 
-        let extension = path
-            .extension()
-            .and_then(|s| s.to_str())
-            .unwrap_or("");
+    /// Fail if the filesystem `destination` is on hasn't room for a copy of `source`,
+    /// so a copy doesn't run out of space halfway through
+    fn check_space(fs: &dyn FileSystem, source: &Path, destination: &Path) -> Result<()> {
+        let needed = fs.metadata(source).context("Failed to open source file")?.len;
+        let dir = destination.parent().context("Could not get parent directory")?;
+        let available = match fs.available_space(dir) {
+            Ok(available) => available,
+            Err(e) => {
+                log::debug!("Could not check free space in {:?}: {}", dir, e);
+                return Ok(());
+            }
+        };
+        if needed > available {
+            anyhow::bail!(
+                "Insufficient space in {:?} for {:?}: needs {} bytes, {} available",
+                dir,
+                source,
+                needed,
+                available
+            );
+        }
+        Ok(())
+    }
+
+    /// Copy in chunks, waiting on `throttle` (if any) before writing each one
+    /// `on_progress(copied, total)` is called every `PROGRESS_STEP_PERCENT` percent;
+    /// an error from it aborts the copy
+    fn copy_chunked(
+        fs: &dyn FileSystem,
+        source: &Path,
+        destination: &Path,
+        throttle: Option<&Throttle>,
+        on_progress: impl FnMut(u64, u64) -> Result<()>,
+    ) -> Result<()> {
+        let reader = fs.open(source).context("Failed to open source file")?;
+        let mut writer = fs.create(destination).context("Failed to create destination file")?;
+        let total = fs.metadata(source).context("Failed to open source file")?.len;
+        Self::copy_stream(reader, &mut writer, total, throttle, on_progress)?;
+
+        writer.flush().context("Failed to copy file")?;
+        fs.copy_permissions(source, destination)
+            .context("Failed to copy file permissions")?;
+        Ok(())
+    }
+
+    /// Like `copy_chunked`, but gzips what is written to `destination`
+    fn compress_chunked(
+        fs: &dyn FileSystem,
+        source: &Path,
+        destination: &Path,
+        throttle: Option<&Throttle>,
+        on_progress: impl FnMut(u64, u64) -> Result<()>,
+    ) -> Result<()> {
+        let reader = fs.open(source).context("Failed to open source file")?;
+        let writer = fs.create(destination).context("Failed to create destination file")?;
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        let total = fs.metadata(source).context("Failed to open source file")?.len;
+        Self::copy_stream(reader, &mut encoder, total, throttle, on_progress)?;
+
+        encoder
+            .finish()
+            .and_then(|mut writer| writer.flush())
+            .context("Failed to compress file")?;
+        fs.copy_permissions(source, destination)
+            .context("Failed to copy file permissions")?;
+        Ok(())
+    }
+
+    /// Copy all of `reader` (`total` bytes long) to `writer` in chunks, reporting
+    /// progress and waiting on `throttle` as described for `copy_chunked`
+    fn copy_stream(
+        mut reader: impl Read,
+        writer: &mut impl Write,
+        total: u64,
+        throttle: Option<&Throttle>,
+        mut on_progress: impl FnMut(u64, u64) -> Result<()>,
+    ) -> Result<()> {
+        let chunk_size = throttle.map_or(COPY_CHUNK_SIZE, Throttle::chunk_size);
+        let mut buffer = vec![0; chunk_size];
+        let mut copied = 0;
+        let mut reported_step = 0;
+
+        loop {
+            let read = reader.read(&mut buffer).context("Failed to read source file")?;
+            if read == 0 {
+                break;
+            }
+            if let Some(throttle) = throttle {
+                throttle.acquire(read);
+            }
+            writer
+                .write_all(&buffer[..read])
+                .context("Failed to write destination file")?;
+
+            copied += read as u64;
+            let step = copied * 100 / total.max(1) / PROGRESS_STEP_PERCENT;
+            if step > reported_step {
+                reported_step = step;
+                on_progress(copied, total)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Path `source` would be moved to in `destination_dir`, after normalizing its
+    /// name and handling a name conflict; `None` if the file would be left in place
+    pub fn planned_destination(
+        source: &Path,
+        destination_dir: &Path,
+        options: MoveOptions,
+    ) -> Result<Option<PathBuf>> {
+        Ok(Self::plan_move(source, destination_dir, options)?.destination)
+    }
+
+    /// Like `planned_destination`, also reporting how a name conflict was resolved
+    pub fn plan_move(
+        source: &Path,
+        destination_dir: &Path,
+        options: MoveOptions,
+    ) -> Result<PlannedMove> {
+        let file_name = source
+            .file_name()
+            .context("Could not extract file name")?;
+        let mut path = match (options.names, file_name.to_str()) {
+            (Some(rules), Some(name)) => destination_dir.join(normalize_file_name(name, rules)),
+            _ => destination_dir.join(file_name),
+        };
+        if options.compress {
+            let mut compressed_name = path.file_name().unwrap_or_default().to_os_string();
+            compressed_name.push(".gz");
+            path.set_file_name(compressed_name);
+        }
+        let path = fit_file_name(&path, "", options.compress);
+        if !options.fs.exists(&path) {
+            return Ok(PlannedMove {
+                destination: Some(path),
+                conflict: None,
+            });
+        }
+
+        let skipped = PlannedMove {
+            destination: None,
+            conflict: Some(ConflictResolution::Skipped),
+        };
+        // Copied by an earlier run, e.g. organized again after a restart
+        if options.keep_source {
+            let default_suffix = ConflictSuffix::default();
+            let suffix = options.suffix.unwrap_or(&default_suffix);
+            let copies = Self::numbered_copies(options.fs, &path, suffix, options.compress)?;
+            let mut candidates =
+                std::iter::once(path.clone()).chain(copies.into_iter().map(|(_, copy)| copy));
+            if let Some(copy) =
+                candidates.find(|copy| Self::same_contents(options.fs, source, copy))
+            {
+                log::info!("{:?} is already a copy of {:?}, not copying again", copy, source);
+                return Ok(skipped);
+            }
+        }
+        let replace = match options.conflict {
+            ConflictStrategy::Rename => false,
+            ConflictStrategy::Skip => {
+                log::warn!("{:?} already exists, leaving {:?} in place", path, source);
+                return Ok(skipped);
+            }
+            ConflictStrategy::Overwrite => true,
+            ConflictStrategy::OverwriteIfNewer => {
+                if !Self::is_newer(options.fs, source, &path)? {
+                    log::warn!("{:?} is not older, leaving {:?} in place", path, source);
+                    return Ok(skipped);
+                }
+                true
+            }
+        };
+
+        // Folders are never replaced, only files
+        if replace && options.fs.is_file(&path) {
+            log::warn!("File conflict detected, overwriting {:?}", path);
+            return Ok(PlannedMove {
+                destination: Some(path),
+                conflict: Some(ConflictResolution::Overwritten),
+            });
+        }
+        let renamed = Self::resolve_conflict(options.fs, &path, options.suffix, options.compress)?;
+        Ok(PlannedMove {
+            destination: Some(renamed),
+            conflict: Some(ConflictResolution::Renamed),
+        })
+    }
+
+    /// Whether the files `a` and `b` hold the same bytes
+    fn same_contents(fs: &dyn FileSystem, a: &Path, b: &Path) -> bool {
+        let same_size = match (fs.metadata(a), fs.metadata(b)) {
+            (Ok(a), Ok(b)) => !a.is_dir && !b.is_dir && a.len == b.len,
+            _ => false,
+        };
+        let (Ok(mut a), Ok(mut b)) = (fs.open(a), fs.open(b)) else {
+            return false;
+        };
+        if !same_size {
+            return false;
+        }
+
+        let (mut chunk_a, mut chunk_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+        loop {
+            match (fill(&mut a, &mut chunk_a), fill(&mut b, &mut chunk_b)) {
+                (Ok(0), Ok(0)) => return true,
+                (Ok(read_a), Ok(read_b)) if chunk_a[..read_a] == chunk_b[..read_b] => {}
+                _ => return false,
+            }
+        }
+    }
+
+    /// Whether `source` was modified more recently than `existing`
+    fn is_newer(fs: &dyn FileSystem, source: &Path, existing: &Path) -> Result<bool> {
+        let modified = |path: &Path| {
+            fs.metadata(path)
+                .map(|info| info.modified)
+                .context(format!("Failed to read modification time of {:?}", path))
+        };
+        Ok(modified(source)? > modified(existing)?)
+    }
+
+    /// Whether `file` is directly inside `dir`, after resolving symlinks and `..`
+    /// Always false when either path doesn't exist
+    pub fn is_in_directory(file: &Path, dir: &Path) -> bool {
+        let parent = file.parent().and_then(|parent| fs::canonicalize(parent).ok());
+        parent.is_some() && parent == fs::canonicalize(dir).ok()
+    }
+
+    /// Existing copies of `path` renamed on conflict, with their numbers
+    fn numbered_copies(
+        fs: &dyn FileSystem,
+        path: &Path,
+        suffix: &ConflictSuffix,
+        compressed: bool,
+    ) -> Result<Vec<(u32, PathBuf)>> {
+        let (file_stem, extension) = split_extension(path, compressed);
+        let file_stem = file_stem.to_str().context("Could not extract file stem")?;
+        let extension = extension.and_then(|s| s.to_str()).unwrap_or("");
 
         let parent = path.parent().context("Could not get parent directory")?;
 
-        // Try numbered suffixes until we find an available name
-        for i in 1..10000 {
-            let new_name = if extension.is_empty() {
-                format!("{}_{}", file_stem, i)
+        let numbered = |name: &str| {
+            let name = if extension.is_empty() {
+                name
             } else {
-                format!("{}_{}.{}", file_stem, i, extension)
+                name.strip_suffix(extension)?.strip_suffix('.')?
             };
+            suffix.parse(name.strip_prefix(file_stem)?)
+        };
+        Ok(fs
+            .read_dir(parent)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| Some((entry.file_name()?.to_str().and_then(numbered)?, entry)))
+            .collect())
+    }
+
+    fn resolve_conflict(
+        fs: &dyn FileSystem,
+        path: &Path,
+        suffix: Option<&ConflictSuffix>,
+        compressed: bool,
+    ) -> Result<PathBuf> {
+        if !fs.exists(path) {
+            return Ok(path.to_path_buf());
+        }
+
+        let default_suffix = ConflictSuffix::default();
+        let suffix = suffix.unwrap_or(&default_suffix);
 
-            let new_path = parent.join(new_name);
-            if !new_path.exists() {
+        // Continue after the highest number in use, so a folder with many copies
+        // takes one listing rather than a check per copy
+        let highest = Self::numbered_copies(fs, path, suffix, compressed)?
+            .into_iter()
+            .map(|(n, _)| n)
+            .max()
+            .unwrap_or(0);
+
+        let mut i = highest;
+        loop {
+            i = i.checked_add(1).context("Ran out of numbers for conflicting file names")?;
+            let new_path = fit_file_name(path, &suffix.render(i), compressed);
+            if !fs.exists(&new_path) {
                 log::warn!(
                     "File conflict detected, using new name: {:?}",
                     new_path.file_name()
@@ -79,15 +579,123 @@ impl FileMover {
                 return Ok(new_path);
             }
         }
+    }
+}
+
+/// Temporary path a staged copy to `destination` is written under
+/// ".{name}.autofile-partial", with the name shortened so it still fits in
+/// `MAX_FILE_NAME_BYTES`
+fn partial_path(destination: &Path) -> Result<PathBuf> {
+    let file_name = destination
+        .file_name()
+        .context("Could not extract file name")?
+        .to_string_lossy();
+    let room = MAX_FILE_NAME_BYTES - PARTIAL_SUFFIX.len() - 2;
+    let file_name = truncate_bytes(&file_name, room);
+    Ok(destination.with_file_name(format!(".{}.{}", file_name, PARTIAL_SUFFIX)))
+}
+
+/// `path` with `suffix` appended to its file stem, shortening the stem so the name
+/// fits in `MAX_FILE_NAME_BYTES`; the suffix and extension are kept whole
+fn fit_file_name(path: &Path, suffix: &str, compressed: bool) -> PathBuf {
+    let (stem, extension) = split_extension(path, compressed);
+    let mut tail = std::ffi::OsString::from(suffix);
+    if let Some(extension) = extension {
+        tail.push(".");
+        tail.push(extension);
+    }
+    let mut name = stem.to_os_string();
+    name.push(&tail);
+    // Names that aren't valid UTF-8 are left as they are
+    let (Some(stem), Some(tail)) = (stem.to_str(), tail.to_str()) else {
+        return path.with_file_name(name);
+    };
+    if name.len() <= MAX_FILE_NAME_BYTES {
+        return path.with_file_name(name);
+    }
+
+    // An extension too long to keep is cut along with everything else
+    let name = if tail.len() < MAX_FILE_NAME_BYTES {
+        format!("{}{}", truncate_bytes(stem, MAX_FILE_NAME_BYTES - tail.len()), tail)
+    } else {
+        truncate_bytes(&format!("{}{}", stem, tail), MAX_FILE_NAME_BYTES).to_string()
+    };
+    log::warn!("File name of {:?} is too long, shortened to {:?}", path, name);
+    path.with_file_name(name)
+}
+
+/// Stem and extension of `path`'s file name, where the extension of a file AutoFile
+/// `compressed` includes its original one ("server.log.gz" is "server" and "log.gz")
+fn split_extension(path: &Path, compressed: bool) -> (&OsStr, Option<&OsStr>) {
+    let name = path.file_name().and_then(|name| name.to_str()).filter(|_| compressed);
+    let compound = name.and_then(|name| {
+        let original = Path::new(name.strip_suffix(".gz")?);
+        original.extension()?;
+        let stem = original.file_stem()?.to_str()?;
+        Some((OsStr::new(stem), Some(OsStr::new(&name[stem.len() + 1..]))))
+    });
+    compound.unwrap_or_else(|| (path.file_stem().unwrap_or_default(), path.extension()))
+}
+
+/// The longest prefix of `text` at most `max` bytes long that doesn't split a character
+fn truncate_bytes(text: &str, max: usize) -> &str {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// `file_name` with `rules` applied to its stem and, if enabled, its extension
+/// Parts that would end up empty (e.g. all emoji with `ascii_only`) are kept as they were.
+pub fn normalize_file_name(file_name: &str, rules: &NormalizeFilenamesConfig) -> String {
+    let normalize = |part: &str| {
+        let mut text = if rules.ascii_only {
+            deunicode::deunicode_with_tofu(part, "")
+        } else {
+            part.to_string()
+        };
+        if rules.lowercase {
+            text = text.to_lowercase();
+        }
+        if let Some(separator) = &rules.replace_spaces {
+            text = text.split_whitespace().collect::<Vec<_>>().join(separator);
+        }
+        if text.is_empty() {
+            part.to_string()
+        } else {
+            text
+        }
+    };
+
+    let path = Path::new(file_name);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(file_name);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if rules.extension => format!("{}.{}", normalize(stem), normalize(ext)),
+        Some(ext) => format!("{}.{}", normalize(stem), ext),
+        None => normalize(stem),
+    }
+}
 
-        anyhow::bail!("Could not resolve file name conflict after 10000 attempts");
+/// Read into `buffer` until it is full or the reader is exhausted, returning how
+/// many bytes were read
+fn fill(reader: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
     }
+    Ok(filled)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filesystem::MemoryFileSystem;
     use std::fs::File;
+    use std::time::SystemTime;
     use tempfile::TempDir;
 
     #[test]
@@ -108,15 +716,363 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_throttled_copy_respects_rate() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source = temp_dir.path().join("large.bin");
+        let destination = temp_dir.path().join("copy.bin");
+        fs::write(&source, vec![7u8; 150_000])?;
+
+        let started = Instant::now();
+        let throttle = Throttle::new(100_000);
+        let fs = RealFileSystem;
+        FileMover::copy_chunked(&fs, &source, &destination, Some(&throttle), |_, _| Ok(()))?;
+
+        // 150 KB at 100 KB/s, starting from an empty bucket
+        assert!(started.elapsed() >= Duration::from_millis(1400));
+        assert_eq!(fs::read(&destination)?, fs::read(&source)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_copy_reports_progress() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source = temp_dir.path().join("video.mp4");
+        let destination = temp_dir.path().join("copy.mp4");
+        let contents: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &contents)?;
+
+        let mut reports = Vec::new();
+        FileMover::copy_chunked(&RealFileSystem, &source, &destination, None, |copied, total| {
+            reports.push((copied, total));
+            Ok(())
+        })?;
+
+        assert_eq!(fs::read(&destination)?, contents);
+        assert!(reports.len() > 1);
+        assert_eq!(reports.last(), Some(&(contents.len() as u64, contents.len() as u64)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_staged_copy_leaves_no_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source = temp_dir.path().join("video.mp4");
+        let destination_dir = temp_dir.path().join("Videos");
+        fs::create_dir(&destination_dir)?;
+        let destination = destination_dir.join("video.mp4");
+        let contents = vec![3u8; 2 * 1024 * 1024];
+        fs::write(&source, &contents)?;
+
+        let options = MoveOptions {
+            staged: true,
+            ..Default::default()
+        };
+        // Fail partway through, like a dropped network drive
+        let result = FileMover::copy_across(&source, &destination, options, |_, _| {
+            anyhow::bail!("connection lost")
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(&destination_dir)?.count(), 0);
+        assert_eq!(fs::read(&source)?, contents);
+
+        FileMover::copy_across(&source, &destination, options, |_, _| Ok(()))?;
+        assert_eq!(fs::read(&destination)?, contents);
+        assert_eq!(fs::read_dir(&destination_dir)?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_staged_copy_of_longest_name() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let name = format!("{}.pdf", "a".repeat(246));
+        assert_eq!(name.len(), 250);
+        let source = temp_dir.path().join(&name);
+        fs::write(&source, b"content")?;
+        let destination_dir = temp_dir.path().join("Documents");
+        fs::create_dir(&destination_dir)?;
+
+        let options = MoveOptions {
+            staged: true,
+            ..Default::default()
+        };
+        let destination = destination_dir.join(&name);
+        FileMover::copy_across(&source, &destination, options, |_, _| Ok(()))?;
+        assert_eq!(fs::read(&destination)?, b"content");
+        assert_eq!(fs::read_dir(&destination_dir)?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_filesystem_move_falls_back_to_copy() -> Result<()> {
+        let fs = MemoryFileSystem::new().with_mount("/mnt/usb");
+        let source = Path::new("/home/user/Downloads/movie.mp4");
+        let contents = vec![5u8; 3 * COPY_CHUNK_SIZE];
+        fs.add_file(source, &contents, SystemTime::now());
+
+        let options = MoveOptions {
+            fs: &fs,
+            staged: true,
+            ..Default::default()
+        };
+        let moved = FileMover::move_file_with(source, Path::new("/mnt/usb/Videos"), options)?;
+
+        let destination = Path::new("/mnt/usb/Videos/movie.mp4");
+        assert_eq!(moved.as_deref(), Some(destination));
+        assert_eq!(fs.contents(destination), Some(contents));
+        assert!(!fs.exists(source));
+        // The staged copy was renamed into place
+        assert_eq!(fs.read_dir(Path::new("/mnt/usb/Videos"))?, vec![destination]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_rejected_when_destination_lacks_space() -> Result<()> {
+        let fs = MemoryFileSystem::new()
+            .with_mount("/mnt/usb")
+            .with_available_space("/mnt/usb", 10);
+        let source = Path::new("/home/user/Downloads/backup.tar");
+        fs.add_file(source, &[0u8; 100], SystemTime::now());
+
+        let options = MoveOptions {
+            fs: &fs,
+            ..Default::default()
+        };
+        let error = FileMover::move_file_with(source, Path::new("/mnt/usb/Backups"), options)
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Insufficient space"));
+        // Nothing was copied and the source is untouched
+        assert_eq!(fs.read_dir(Path::new("/mnt/usb/Backups"))?, Vec::<PathBuf>::new());
+        assert!(fs.exists(source));
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlong_unicode_name_is_shortened_whole_characters() -> Result<()> {
+        let fs = MemoryFileSystem::new();
+        let source = PathBuf::from(format!("/downloads/{}.pdf", "日本語".repeat(40)));
+        fs.add_file(&source, b"content", SystemTime::now());
+        let options = MoveOptions {
+            fs: &fs,
+            ..Default::default()
+        };
+
+        let docs = Path::new("/docs");
+        let planned = FileMover::planned_destination(&source, docs, options)?.unwrap();
+        let name = planned.file_name().unwrap().to_str().unwrap();
+        // 251 bytes are left for the stem, so 83 whole characters of 3 bytes each
+        assert_eq!(name, format!("{}日本.pdf", "日本語".repeat(27)));
+        assert!(name.len() <= MAX_FILE_NAME_BYTES);
+
+        // A numbered copy keeps its suffix and extension too
+        fs.add_file(&planned, b"earlier", SystemTime::now());
+        let renamed = FileMover::planned_destination(&source, docs, options)?.unwrap();
+        let name = renamed.file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with(" (1).pdf"), "{}", name);
+        assert!(name.len() <= MAX_FILE_NAME_BYTES);
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicts_on_memory_filesystem() -> Result<()> {
+        let now = SystemTime::now();
+        let fs = MemoryFileSystem::new();
+        let source = Path::new("/downloads/report.pdf");
+        fs.add_file(source, b"new", now);
+        fs.add_file("/docs/report.pdf", b"old", now + Duration::from_secs(60));
+        fs.add_file("/docs/report (1).pdf", b"older", now);
+
+        let options = |conflict| MoveOptions {
+            fs: &fs,
+            conflict,
+            ..Default::default()
+        };
+        let docs = Path::new("/docs");
+        let renamed = FileMover::plan_move(source, docs, options(ConflictStrategy::Rename))?;
+        assert_eq!(renamed.destination, Some(PathBuf::from("/docs/report (2).pdf")));
+
+        // The existing file is newer, so it's kept
+        let if_newer = options(ConflictStrategy::OverwriteIfNewer);
+        let planned = FileMover::plan_move(source, docs, if_newer)?;
+        assert_eq!(planned.conflict, Some(ConflictResolution::Skipped));
+
+        let moved = FileMover::move_file_with(source, docs, options(ConflictStrategy::Overwrite))?;
+        assert_eq!(moved, Some(PathBuf::from("/docs/report.pdf")));
+        assert_eq!(fs.contents("/docs/report.pdf"), Some(b"new".to_vec()));
+        Ok(())
+    }
+
     #[test]
     fn test_conflict_resolution() -> Result<()> {
         let temp_dir = TempDir::new()?;
         let path1 = temp_dir.path().join("test.txt");
         File::create(&path1)?;
 
-        let resolved = FileMover::resolve_conflict(&path1)?;
+        let resolved = FileMover::resolve_conflict(&RealFileSystem, &path1, None, false)?;
         assert_eq!(resolved, temp_dir.path().join("test (1).txt"));
 
         Ok(())
     }
+
+    #[test]
+    fn test_compressed_conflict_resolution() -> Result<()> {
+        let fs = MemoryFileSystem::new();
+        let now = SystemTime::now();
+        for name in ["backup.tar.gz", "server.log.gz", "server (1).log.gz", "notes.gz"] {
+            fs.add_file(format!("/logs/{}", name), b"", now);
+        }
+
+        // Only names AutoFile added ".gz" to keep the original extension together
+        for (name, compressed, renamed) in [
+            ("backup.tar.gz", false, "backup.tar (1).gz"),
+            ("backup.tar.gz", true, "backup (1).tar.gz"),
+            ("server.log.gz", true, "server (2).log.gz"),
+            ("notes.gz", true, "notes (1).gz"),
+        ] {
+            let path = Path::new("/logs").join(name);
+            let resolved = FileMover::resolve_conflict(&fs, &path, None, compressed)?;
+            assert_eq!(resolved, Path::new("/logs").join(renamed));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflict_numbering_continues_after_highest() -> Result<()> {
+        let fs = MemoryFileSystem::new();
+        let now = SystemTime::now();
+        fs.add_file("/docs/report.pdf", b"", now);
+        // Earlier copies were deleted; gaps aren't refilled, so names keep increasing
+        for i in (1..=50).filter(|i| i % 7 != 0) {
+            fs.add_file(format!("/docs/report ({}).pdf", i), b"", now);
+        }
+        fs.add_file("/docs/report (99).txt", b"", now);
+        fs.add_file("/docs/report (abc).pdf", b"", now);
+
+        let path = Path::new("/docs/report.pdf");
+        let resolved = FileMover::resolve_conflict(&fs, path, None, false)?;
+        assert_eq!(resolved, Path::new("/docs/report (51).pdf"));
+
+        let versioned = ConflictSuffix::new("_v{n}")?;
+        fs.add_file("/docs/report_v3.pdf", b"", now);
+        let resolved = FileMover::resolve_conflict(&fs, path, Some(&versioned), false)?;
+        assert_eq!(resolved, Path::new("/docs/report_v4.pdf"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflict_suffix_format() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let fs = RealFileSystem;
+        let report = temp_dir.path().join("report.pdf");
+        File::create(&report)?;
+
+        let versioned = ConflictSuffix::new("_v{n}")?;
+        let resolved = FileMover::resolve_conflict(&fs, &report, Some(&versioned), false)?;
+        assert_eq!(resolved, temp_dir.path().join("report_v1.pdf"));
+
+        File::create(&resolved)?;
+        let resolved = FileMover::resolve_conflict(&fs, &report, Some(&versioned), false)?;
+        assert_eq!(resolved, temp_dir.path().join("report_v2.pdf"));
+
+        let default_suffix = Some(&ConflictSuffix::default());
+        let resolved = FileMover::resolve_conflict(&fs, &report, default_suffix, false)?;
+        assert_eq!(resolved, temp_dir.path().join("report (1).pdf"));
+
+        assert!(ConflictSuffix::new("_copy").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_file_name() {
+        let mut rules = NormalizeFilenamesConfig {
+            lowercase: true,
+            replace_spaces: Some("_".to_string()),
+            ascii_only: true,
+            extension: true,
+        };
+        let name = "My Résumé (final).PDF";
+        assert_eq!(normalize_file_name(name, &rules), "my_resume_(final).pdf");
+
+        rules.extension = false;
+        assert_eq!(normalize_file_name(name, &rules), "my_resume_(final).PDF");
+
+        rules.lowercase = false;
+        rules.replace_spaces = None;
+        assert_eq!(normalize_file_name(name, &rules), "My Resume (final).PDF");
+
+        let unchanged = NormalizeFilenamesConfig::default();
+        assert_eq!(normalize_file_name(name, &unchanged), name);
+    }
+
+    #[test]
+    fn test_normalized_name_is_checked_for_conflicts() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source = temp_dir.path().join("Tax Return.PDF");
+        let dest_dir = temp_dir.path().join("Documents");
+        fs::create_dir(&dest_dir)?;
+        fs::write(&source, b"new")?;
+        fs::write(dest_dir.join("tax_return.pdf"), b"old")?;
+
+        let rules = NormalizeFilenamesConfig {
+            lowercase: true,
+            replace_spaces: Some("_".to_string()),
+            ..Default::default()
+        };
+        let options = MoveOptions {
+            names: Some(&rules),
+            ..Default::default()
+        };
+        let moved = FileMover::move_file_with(&source, &dest_dir, options)?;
+        assert_eq!(moved, Some(dest_dir.join("tax_return (1).pdf")));
+        Ok(())
+    }
+
+    /// Move a new `report.txt` into a folder that already has an older one
+    fn move_conflicting(conflict: ConflictStrategy, source_is_newer: bool) -> Result<TempDir> {
+        let temp_dir = TempDir::new()?;
+        let source = temp_dir.path().join("report.txt");
+        let dest_dir = temp_dir.path().join("Documents");
+        fs::create_dir(&dest_dir)?;
+        fs::write(&source, b"new")?;
+        fs::write(dest_dir.join("report.txt"), b"old")?;
+
+        let old = std::time::SystemTime::now() - Duration::from_secs(3600);
+        let older = if source_is_newer { dest_dir.join("report.txt") } else { source.clone() };
+        File::options().write(true).open(older)?.set_modified(old)?;
+
+        let options = MoveOptions {
+            conflict,
+            ..Default::default()
+        };
+        let moved = FileMover::move_file_with(&source, &dest_dir, options)?;
+        assert_eq!(moved.is_some(), !source.exists());
+        Ok(temp_dir)
+    }
+
+    #[test]
+    fn test_conflict_strategies() -> Result<()> {
+        let contents = |dir: &TempDir, name: &str| fs::read_to_string(dir.path().join(name));
+
+        let renamed = move_conflicting(ConflictStrategy::Rename, true)?;
+        assert_eq!(contents(&renamed, "Documents/report.txt")?, "old");
+        assert_eq!(contents(&renamed, "Documents/report (1).txt")?, "new");
+
+        let skipped = move_conflicting(ConflictStrategy::Skip, true)?;
+        assert_eq!(contents(&skipped, "Documents/report.txt")?, "old");
+        assert_eq!(contents(&skipped, "report.txt")?, "new");
+
+        let overwritten = move_conflicting(ConflictStrategy::Overwrite, false)?;
+        assert_eq!(contents(&overwritten, "Documents/report.txt")?, "new");
+        assert_eq!(fs::read_dir(overwritten.path().join("Documents"))?.count(), 1);
+
+        let newer = move_conflicting(ConflictStrategy::OverwriteIfNewer, true)?;
+        assert_eq!(contents(&newer, "Documents/report.txt")?, "new");
+        assert!(!newer.path().join("report.txt").exists());
+
+        let not_newer = move_conflicting(ConflictStrategy::OverwriteIfNewer, false)?;
+        assert_eq!(contents(&not_newer, "Documents/report.txt")?, "old");
+        assert_eq!(contents(&not_newer, "report.txt")?, "new");
+        Ok(())
+    }
 }
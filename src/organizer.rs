@@ -1,87 +1,600 @@
-use crate::categorizer::Categorizer;
-use crate::config::Config;
-use crate::detector::{FileCategory, FileDetector};
-use crate::matcher::SubfolderMatcher;
-use crate::mover::FileMover;
-use crate::preprocessor::PreprocessorPipeline;
-use anyhow::Result;
-use std::path::Path;
+use crate::categorizer::{Categorizer, CategorySource, DestinationSource, Route};
+use crate::config::{
+    CompressConfig, Config, DedupConfig, NormalizeFilenamesConfig, QuarantineConfig,
+};
+use crate::dedup::{DedupCheck, Deduplicator};
+use crate::detector::FileCategory;
+use crate::embedding_cache::EMBEDDING_CACHE_FILE;
+use crate::events::{EventSink, OrganizeEvent};
+use crate::filesystem::{FileSystem, RealFileSystem};
+use crate::history::{self, MoveHistory, MANIFEST_FILE};
+use crate::hooks::{MovedFile, PostMoveHooks};
+use crate::image_kind::ImageClassifier;
+use crate::matcher::{SubfolderMatch, SubfolderMatcher};
+use crate::metrics::Metrics;
+use crate::mover::{
+    ConflictResolution, ConflictStrategy, ConflictSuffix, FileMover, MoveOptions, Throttle,
+};
+use crate::preprocessor::{PreprocessPhase, PreprocessorPipeline};
+use crate::prompt::MatchPrompt;
+use crate::quarantine::{self, FailureTracker};
+use crate::recent::{RecentMoves, RecentStems};
+use crate::status::StatusReporter;
+use crate::tags;
+use crate::utils::file::{
+    is_bundle, is_file_open, is_in_sync_folder, is_inside_bundle, is_sync_metadata, DateSource,
+};
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long to wait before retrying a file that is still open elsewhere
+const OPEN_FILE_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// What `organize_file` did with a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrganizeOutcome {
+    /// The file was moved to this path
+    Moved(PathBuf),
+    /// The file was left where it is
+    Skipped,
+    /// The file isn't ready to be organized yet; try again after this delay
+    Deferred(Duration),
+    /// The file failed too many times and was moved to the quarantine folder
+    Quarantined(PathBuf),
+    /// An identical file was already organized to this path, so the file wasn't moved
+    Duplicate(PathBuf),
+}
+
+impl OrganizeOutcome {
+    /// Lowercase name of the outcome, as used in events and metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrganizeOutcome::Moved(_) => "moved",
+            OrganizeOutcome::Skipped => "skipped",
+            OrganizeOutcome::Deferred(_) => "deferred",
+            OrganizeOutcome::Quarantined(_) => "quarantined",
+            OrganizeOutcome::Duplicate(_) => "duplicate",
+        }
+    }
+}
+
+/// Where a file would be organized and why, from `FileOrganizer::explain_file`
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub file: PathBuf,
+    pub route: Route,
+    /// Subfolder search result; `None` when matching wouldn't run
+    pub subfolder: Option<SubfolderMatch>,
+    /// Final path including any numbered conflict suffix; `None` if the file would stay
+    pub destination: Option<PathBuf>,
+    /// How a file already using the name in the folder would be dealt with
+    pub conflict: Option<ConflictResolution>,
+    /// Why the file would be left in place
+    pub skip_reason: Option<&'static str>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "File:        {}", self.file.display())?;
+        write!(f, "Category:    {}", self.route.category.name())?;
+        match (&self.route.detection_method, &self.route.mime) {
+            (Some(method), Some(mime)) => writeln!(f, " (by {:?}, {})", method, mime)?,
+            (Some(method), None) => writeln!(f, " (by {:?})", method)?,
+            _ => writeln!(f)?,
+        }
+        writeln!(f, "Routing:     {}", self.route.explain())?;
+
+        if let Some(subfolder) = &self.subfolder {
+            writeln!(f, "Candidates:")?;
+            for (folder, similarity) in &subfolder.candidates {
+                writeln!(f, "  {:.3}  {}", similarity, folder.display())?;
+            }
+            if subfolder.candidates.is_empty() {
+                writeln!(f, "  (no subfolders)")?;
+            }
+            writeln!(f, "Folder:      {}", subfolder.path.display())?;
+        }
+
+        if let Some(conflict) = self.conflict {
+            let resolution = match conflict {
+                ConflictResolution::Renamed => "numbering the new file",
+                ConflictResolution::Skipped => "leaving the new file in place",
+                ConflictResolution::Overwritten => "overwriting the existing file",
+            };
+            writeln!(f, "Conflict:    name already taken, {}", resolution)?;
+        }
+
+        match (&self.destination, self.skip_reason) {
+            (Some(destination), _) => write!(f, "Destination: {}", destination.display()),
+            (None, Some(reason)) => write!(f, "Destination: left in place ({})", reason),
+            (None, None) => write!(f, "Destination: left in place"),
+        }
+    }
+}
 
 pub struct FileOrganizer {
     categorizer: Categorizer,
-    matcher: SubfolderMatcher,
+    /// `None` when semantic matching is disabled, so the model is never loaded
+    matcher: Option<SubfolderMatcher>,
     preprocessor: PreprocessorPipeline,
+    lazy_preprocessing: bool,
+    recent_moves: RecentMoves,
+    recent_videos: RecentStems,
+    explain: bool,
+    follow_symlinks: bool,
+    min_file_age: Option<Duration>,
+    date_source: DateSource,
+    defer_open_files: bool,
+    sync_move_delay: Option<Duration>,
+    sync_folders: Vec<PathBuf>,
+    /// When the last file was moved into a cloud-synced folder
+    last_sync_move: Mutex<Option<Instant>>,
+    events: Vec<Box<dyn EventSink>>,
+    status: Option<Arc<StatusReporter>>,
+    metrics: Option<Arc<Metrics>>,
+    history: Option<MoveHistory>,
+    quarantine: Option<QuarantineConfig>,
+    failures: FailureTracker,
+    match_within_overrides: bool,
+    enabled_categories: Vec<FileCategory>,
+    match_categories: Vec<FileCategory>,
+    dedup: Option<Deduplicator>,
+    compress: Option<CompressConfig>,
+    copy_throttle: Option<Throttle>,
+    staged_copies: bool,
+    conflict_strategy: ConflictStrategy,
+    conflict_suffix: ConflictSuffix,
+    conflict_suffixes: HashMap<FileCategory, ConflictSuffix>,
+    normalize_filenames: NormalizeFilenamesConfig,
+    skip_empty_files: bool,
+    hooks: PostMoveHooks,
+    use_source_path_context: bool,
+    preserve_source_structure: bool,
+    remove_empty_source_dirs: bool,
+    /// Copy files rather than moving them, and never delete anything
+    safe_mode: bool,
+    /// Where macOS bundles like `.app`s are moved
+    bundle_destination: PathBuf,
+    folder_manifest: bool,
+    /// Source folders files were moved out of this session, the only ones removed
+    /// once empty
+    emptied_dirs: Mutex<HashSet<PathBuf>>,
+    tag_files: bool,
+    image_classifier: Option<ImageClassifier>,
+    watch_root: Option<PathBuf>,
+    /// Where files are moved, copied and compressed; the disk outside of tests
+    fs: Arc<dyn FileSystem>,
 }
 
 impl FileOrganizer {
     pub fn new() -> Result<Self> {
-        let categorizer = Categorizer::new()?;
+        Self::from_config(&Config::load()?)
+    }
+
+    /// Build an organizer, its categorizer and semantic matcher from `config`
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let categorizer = Categorizer::from_config(config)?;
         categorizer.ensure_destinations_exist()?;
+        Self::with_matcher_from_config(config, categorizer)
+    }
 
-        // Load configuration
-        let config = Config::load()?;
+    /// Like `from_config`, but leaves missing destination folders uncreated, so
+    /// `explain_file` doesn't touch the filesystem
+    pub fn for_explaining(config: &Config) -> Result<Self> {
+        let categorizer = Categorizer::from_config(config)?;
+        Self::with_matcher_from_config(config, categorizer)
+    }
+
+    fn with_matcher_from_config(config: &Config, categorizer: Categorizer) -> Result<Self> {
+        if !config.semantic_matching {
+            log::info!("Semantic matching disabled, files go to top-level destinations");
+            return Ok(Self::build(config, categorizer, None));
+        }
 
         log::info!("Initializing semantic matcher...");
-        let matcher = SubfolderMatcher::new(config.matcher.excluded_folders)?;
+        let mut matcher = SubfolderMatcher::new(&config.matcher)?;
+        if config.matcher.persist_embeddings {
+            match Config::config_dir() {
+                Ok(dir) => matcher = matcher.with_embedding_cache(dir.join(EMBEDDING_CACHE_FILE)),
+                Err(e) => log::warn!("Folder embeddings won't persist: {}", e),
+            }
+        }
         log::info!("Semantic matcher initialized");
 
+        Ok(Self::from_parts(config, categorizer, matcher))
+    }
+
+    /// Build an organizer from an already configured categorizer and matcher
+    /// Organizer-level settings are taken from `config`; the matcher goes unused
+    /// when semantic matching is disabled there
+    pub fn from_parts(config: &Config, categorizer: Categorizer, matcher: SubfolderMatcher) -> Self {
+        Self::build(config, categorizer, config.semantic_matching.then_some(matcher))
+    }
+
+    fn build(config: &Config, categorizer: Categorizer, matcher: Option<SubfolderMatcher>) -> Self {
         // Initialize preprocessing pipeline
-        let preprocessor = PreprocessorPipeline::new();
+        let preprocessor = PreprocessorPipeline::from_config(config);
+
+        let conflict_strategy = match config.conflict_strategy {
+            ConflictStrategy::Overwrite | ConflictStrategy::OverwriteIfNewer
+                if config.safe_mode =>
+            {
+                log::info!("Safe mode: renaming files on name conflicts instead of overwriting");
+                ConflictStrategy::Rename
+            }
+            strategy => strategy,
+        };
 
-        Ok(Self {
+        Self {
             categorizer,
             matcher,
             preprocessor,
-        })
+            lazy_preprocessing: config.lazy_preprocessing,
+            recent_moves: RecentMoves::default(),
+            recent_videos: RecentStems::default(),
+            explain: false,
+            follow_symlinks: config.follow_symlinks,
+            min_file_age: config.min_file_age_secs.map(Duration::from_secs),
+            date_source: config.date_source,
+            defer_open_files: config.defer_open_files,
+            sync_move_delay: config.sync_move_delay_ms.map(Duration::from_millis),
+            sync_folders: config.sync_folders.clone(),
+            last_sync_move: Mutex::new(None),
+            events: Vec::new(),
+            status: None,
+            metrics: None,
+            history: None,
+            quarantine: config.quarantine.clone(),
+            failures: FailureTracker::new(),
+            match_within_overrides: config.match_within_overrides,
+            enabled_categories: config.enabled_categories.clone(),
+            match_categories: config.matcher.match_categories.clone(),
+            dedup: config.dedup.as_ref().map(|dedup| {
+                let trash_duplicates = dedup.trash_duplicates && !config.safe_mode;
+                Deduplicator::from_config(&DedupConfig { trash_duplicates, ..dedup.clone() })
+            }),
+            compress: config.compress.clone(),
+            copy_throttle: config.copy_rate_limit.map(Throttle::new),
+            staged_copies: config.staged_copies,
+            conflict_strategy,
+            conflict_suffix: config.conflict_suffix_format.clone(),
+            conflict_suffixes: config.conflict_suffix_formats.clone(),
+            normalize_filenames: config.normalize_filenames.clone(),
+            skip_empty_files: config.skip_empty_files,
+            hooks: PostMoveHooks::from_config(&config.hooks),
+            use_source_path_context: config.use_source_path_context,
+            preserve_source_structure: config.preserve_source_structure,
+            remove_empty_source_dirs: config.remove_empty_source_dirs && !config.safe_mode,
+            safe_mode: config.safe_mode,
+            bundle_destination: config.bundle_destination.clone().unwrap_or_else(|| {
+                dirs::home_dir().unwrap_or_else(|| PathBuf::from("/")).join("Applications")
+            }),
+            folder_manifest: config.folder_manifest,
+            emptied_dirs: Mutex::new(HashSet::new()),
+            tag_files: config.tag_files,
+            image_classifier: config.image_kinds.as_ref().map(ImageClassifier::from_config),
+            watch_root: None,
+            fs: Arc::new(RealFileSystem),
+        }
+    }
+
+    /// Ask for confirmation of uncertain subfolder matches
+    pub fn with_prompt(mut self, prompt: Box<dyn MatchPrompt>) -> Self {
+        self.matcher = self.matcher.map(|matcher| matcher.with_prompt(prompt));
+        self
+    }
+
+    /// Preprocess files with `pipeline` instead of the default preprocessors
+    pub fn with_preprocessors(mut self, pipeline: PreprocessorPipeline) -> Self {
+        self.preprocessor = pipeline;
+        self
+    }
+
+    /// Move files within `fs` instead of on disk
+    pub fn with_file_system(mut self, fs: Arc<dyn FileSystem>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    /// Report an event for every processed file to `sink`, as well as to any sinks
+    /// added before
+    pub fn with_event_sink(mut self, sink: Box<dyn EventSink>) -> Self {
+        self.events.push(sink);
+        self
+    }
+
+    /// Count every processed file's outcome in `status`
+    pub fn with_status_reporter(mut self, status: Arc<StatusReporter>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Count every processed file in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Record every move in `history`, for `autofile recent`
+    pub fn with_history(mut self, history: MoveHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Log the rule that decided each file's destination at info level
+    pub fn with_explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Folder being watched, relative to which source path context and structure are taken
+    pub fn with_watch_root(mut self, watch_root: PathBuf) -> Self {
+        self.watch_root = Some(watch_root);
+        self
+    }
+
+    /// Every folder this organizer may move files into, including the quarantine and
+    /// the bundle destination
+    pub fn destination_roots(&self) -> Vec<PathBuf> {
+        let mut roots = self.categorizer.destination_roots();
+        roots.push(self.bundle_destination.clone());
+        roots.extend(self.quarantine.as_ref().map(|quarantine| quarantine.destination.clone()));
+        roots
+    }
+
+    /// Handle to the set of paths this organizer recently moved files to
+    pub fn recent_moves(&self) -> RecentMoves {
+        self.recent_moves.clone()
+    }
+
+    /// Organize `files` in order, stopping once `max_files` of them have been moved
+    /// Returns how many were moved and whether the limit stopped the run early.
+    pub fn organize_files(&self, files: &[PathBuf], max_files: Option<usize>) -> (usize, bool) {
+        let mut moved = 0;
+        for (i, file) in files.iter().enumerate() {
+            if max_files.is_some_and(|max| moved >= max) {
+                let left = files.len() - i;
+                log::warn!("Reached the limit of {} file(s), leaving {} unprocessed", moved, left);
+                return (moved, true);
+            }
+            match self.organize_file(file) {
+                Ok(OrganizeOutcome::Moved(_)) => moved += 1,
+                Ok(_) => {}
+                Err(e) => log::error!("Error organizing file {:?}: {}", file, e),
+            }
+        }
+        (moved, false)
     }
 
-    pub fn organize_file(&self, file_path: &Path) -> Result<()> {
+    pub fn organize_file(&self, file_path: &Path) -> Result<OrganizeOutcome> {
+        let mut event = OrganizeEvent::new(file_path);
+        let started = Instant::now();
+        let mut timings = StageTimings::default();
+        let mut result = self.organize(file_path, &mut event, &mut timings);
+
+        if let Ok(OrganizeOutcome::Moved(_)) = &result {
+            let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+            let total = started.elapsed().as_millis();
+            log::info!("Organized {} in {}ms", name, total);
+            log::debug!("Time per stage for {}: {}", name, timings.summary());
+        }
+
+        match &result {
+            Ok(OrganizeOutcome::Moved(_))
+            | Ok(OrganizeOutcome::Skipped)
+            | Ok(OrganizeOutcome::Duplicate(_)) => {
+                self.failures.clear(file_path);
+            }
+            Err(e) => {
+                if let Some(quarantined) = self.quarantine_if_failing(file_path, e) {
+                    result = Ok(OrganizeOutcome::Quarantined(quarantined));
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(status) = &self.status {
+            status.record(&result);
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&result, event.category.as_deref());
+        }
+
+        if !self.events.is_empty() {
+            match &result {
+                Ok(outcome) => {
+                    event.outcome = outcome.name().to_string();
+                    if let OrganizeOutcome::Moved(path)
+                    | OrganizeOutcome::Quarantined(path)
+                    | OrganizeOutcome::Duplicate(path) = outcome
+                    {
+                        event.final_path = Some(path.clone());
+                    }
+                }
+                Err(e) => {
+                    event.outcome = "error".to_string();
+                    event.error = Some(e.to_string());
+                }
+            }
+            for sink in &self.events {
+                sink.emit(&event);
+            }
+        }
+
+        result
+    }
+
+    /// Count a failure for `file_path` and quarantine it once it has failed too often
+    /// Returns the quarantined path if the file was moved
+    fn quarantine_if_failing(&self, file_path: &Path, error: &anyhow::Error) -> Option<PathBuf> {
+        let quarantine = self.quarantine.as_ref()?;
+        if !file_path.exists() {
+            return None;
+        }
+
+        let failures = self.failures.record_failure(file_path);
+        if failures < quarantine.max_failures {
+            log::warn!(
+                "{:?} failed to organize ({} of {} attempts before quarantine)",
+                file_path, failures, quarantine.max_failures
+            );
+            return None;
+        }
+        if self.safe_mode {
+            log::warn!(
+                "Safe mode: leaving {:?} in place instead of quarantining it after {} failures",
+                file_path, failures
+            );
+            return None;
+        }
+
+        match quarantine::quarantine_file(file_path, &quarantine.destination, &format!("{:#}", error)) {
+            Ok(quarantined) => {
+                self.failures.clear(file_path);
+                self.recent_moves.record(&quarantined);
+                self.recent_moves.record(&quarantine::error_file_path(&quarantined));
+                Some(quarantined)
+            }
+            Err(e) => {
+                log::error!("Failed to quarantine {:?}: {}", file_path, e);
+                None
+            }
+        }
+    }
+
+    /// Run the organize pipeline, recording decisions in `event` and the time spent
+    /// per stage in `timings` as they are made
+    fn organize(
+        &self,
+        file_path: &Path,
+        event: &mut OrganizeEvent,
+        timings: &mut StageTimings,
+    ) -> Result<OrganizeOutcome> {
         if !file_path.exists() {
             log::warn!("File no longer exists, skipping: {:?}", file_path);
-            return Ok(());
+            return Ok(OrganizeOutcome::Skipped);
+        }
+
+        if file_path.file_name().is_some_and(|name| name == MANIFEST_FILE) {
+            log::debug!("Destination manifest, skipping: {:?}", file_path);
+            return Ok(OrganizeOutcome::Skipped);
+        }
+
+        // iCloud placeholders are picked up again once downloaded, under their real name
+        if is_sync_metadata(file_path) {
+            log::debug!("Cloud sync metadata, skipping: {:?}", file_path);
+            return Ok(OrganizeOutcome::Skipped);
+        }
+
+        if is_inside_bundle(file_path) {
+            log::debug!("Part of an app bundle, skipping: {:?}", file_path);
+            return Ok(OrganizeOutcome::Skipped);
+        }
+
+        // `is_file` below follows links, which could move files from outside the watch folder
+        let is_symlink = std::fs::symlink_metadata(file_path)?.file_type().is_symlink();
+        if is_symlink && !self.follow_symlinks {
+            log::debug!("Symlink, skipping: {:?}", file_path);
+            return Ok(OrganizeOutcome::Skipped);
+        }
+
+        if is_bundle(file_path) {
+            return self.organize_bundle(file_path, event);
         }
 
         if !file_path.is_file() {
             log::warn!("Path is not a file, skipping: {:?}", file_path);
-            return Ok(());
+            return Ok(OrganizeOutcome::Skipped);
         }
 
-        log::info!("Processing file: {:?}", file_path);
+        // Leave recent files alone; they may still be being written
+        if let Some(min_age) = self.min_file_age {
+            let date = self.date_source.date_of(file_path, &std::fs::metadata(file_path)?)?;
+            let age = SystemTime::now().duration_since(date).unwrap_or_default();
+            if age < min_age {
+                let delay = (min_age - age).max(Duration::from_secs(1));
+                log::info!(
+                    "File is {:.1}s old, deferring for {:.1}s: {:?}",
+                    age.as_secs_f32(),
+                    delay.as_secs_f32(),
+                    file_path
+                );
+                return Ok(OrganizeOutcome::Deferred(delay));
+            }
+        }
 
-        // Apply preprocessing (e.g., HEIC to PNG conversion)
-        let processed_path = self.preprocessor.process(file_path)?;
+        // Programs that keep a file open while writing or mapping it aren't done with it
+        if self.defer_open_files && is_file_open(file_path) {
+            log::info!("File is open in another program, deferring: {:?}", file_path);
+            return Ok(OrganizeOutcome::Deferred(OPEN_FILE_RETRY_DELAY));
+        }
 
-        // Detect file category (using processed path)
-        let category = match FileDetector::detect_category(&processed_path) {
-            Ok(cat) => cat,
-            Err(e) => {
-                log::error!("Failed to detect file category: {}", e);
-                return Err(e);
-            }
-        };
+        if let Err(reason) = check_permissions(file_path) {
+            log::warn!("Insufficient permissions, skipping {:?}: {}", file_path, reason);
+            return Ok(OrganizeOutcome::Skipped);
+        }
 
-        log::info!("Detected category: {:?}", category);
+        // Apps often create an empty placeholder before writing the real content
+        if self.skip_empty_files && std::fs::metadata(file_path)?.len() == 0 {
+            log::debug!("File is empty, skipping: {:?}", file_path);
+            return Ok(OrganizeOutcome::Skipped);
+        }
 
-        // Skip unknown files
-        if category == FileCategory::Unknown {
-            log::warn!("Unknown file type, skipping: {:?}", processed_path);
-            return Ok(());
+        log::info!("Processing file: {:?}", file_path);
+
+        // Files a preprocessor produced come back through the watcher; leave them be
+        let preprocess = !self.preprocessor.take_produced(file_path);
+
+        // Preprocessing that may change how the file is detected, or all of it when not lazy
+        let mut processed_path = file_path.to_path_buf();
+        if preprocess {
+            processed_path = timed(&mut timings.preprocess, || {
+                let pipeline = &self.preprocessor;
+                let path = pipeline.process_phase(file_path, PreprocessPhase::PreDetection)?;
+                if self.lazy_preprocessing {
+                    return Ok(path);
+                }
+                pipeline.process_phase(&path, PreprocessPhase::PostDetection)
+            })?;
         }
 
-        // Get top-level destination from rules
-        let top_level_destination = match self.categorizer.get_destination(&category) {
-            Some(dest) => dest,
-            None => {
-                log::warn!("No rule configured for category {:?}, skipping", category);
-                return Ok(());
-            }
+        let mut route = self.route_file(&processed_path, event, timings)?;
+        let Some(mut top_level_destination) = self.organizable_destination(&route, &processed_path)
+        else {
+            return Ok(OrganizeOutcome::Skipped);
         };
 
+        // Expensive preprocessing (e.g., HEIC to PNG conversion) only for files being moved
+        let late = PreprocessPhase::PostDetection;
+        let pipeline = &self.preprocessor;
+        if preprocess && self.lazy_preprocessing && pipeline.would_process(&processed_path, late) {
+            processed_path =
+                timed(&mut timings.preprocess, || pipeline.process_phase(&processed_path, late))?;
+            // Route the result again even if its path is unchanged, since a
+            // preprocessor may have rewritten the file into another type
+            route = self.route_file(&processed_path, event, timings)?;
+            match self.organizable_destination(&route, &processed_path) {
+                Some(destination) => top_level_destination = destination,
+                None => return Ok(OrganizeOutcome::Skipped),
+            }
+        }
+
         // Find matching subfolder within the top-level destination
-        let final_destination = self.matcher.find_matching_subfolder(
-            &processed_path,
-            top_level_destination,
-        )?;
+        let (final_destination, subfolder) = timed(&mut timings.matching, || {
+            self.choose_folder(file_path, &processed_path, &route, &top_level_destination)
+        })?;
+        if let (Some(metrics), Some(subfolder)) = (&self.metrics, &subfolder) {
+            metrics.record_match(subfolder.similarity.is_some());
+        }
+        if let Some(subfolder) = subfolder.filter(|subfolder| subfolder.similarity.is_some()) {
+            event.matched_folder = Some(subfolder.path);
+            event.similarity = subfolder.similarity;
+        }
 
         log::info!(
             "Destination: {} -> {}",
@@ -89,11 +602,82 @@ impl FileOrganizer {
             final_destination.display()
         );
 
+        // Moving within the same folder would only rename the file to a numbered copy
+        if FileMover::is_in_directory(&processed_path, &final_destination) {
+            log::debug!("Already in its destination, leaving in place: {:?}", processed_path);
+            return Ok(OrganizeOutcome::Skipped);
+        }
+
+        // Don't import a second copy of a file that was already organized
+        let content_hash = match &self.dedup {
+            Some(dedup) => match timed(&mut timings.dedup, || dedup.check(&processed_path))? {
+                DedupCheck::Unique(hash) => Some(hash),
+                DedupCheck::Duplicate(existing) => return Ok(OrganizeOutcome::Duplicate(existing)),
+            },
+            None => None,
+        };
+
+        if let Some(delay) = self.sync_move_delay {
+            if is_in_sync_folder(&final_destination, &self.sync_folders) {
+                self.pace_sync_move(delay);
+            }
+        }
+
         // Move the file
-        match FileMover::move_file(&processed_path, &final_destination) {
-            Ok(new_path) => {
+        let creates_folder = !self.fs.exists(&final_destination);
+        let options = self.move_options(&processed_path, &route.category);
+        let moved = timed(&mut timings.move_file, || {
+            let moved = FileMover::move_file_with(&processed_path, &final_destination, options)?;
+            if options.compress && !options.keep_source && moved.is_some() {
+                self.dispose_compressed(&processed_path)?;
+            }
+            Ok(moved)
+        });
+        match moved {
+            Ok(None) => Ok(OrganizeOutcome::Skipped),
+            Ok(Some(new_path)) => {
+                let parent = final_destination.parent().filter(|_| creates_folder);
+                if let (Some(matcher), Some(parent)) = (&self.matcher, parent) {
+                    matcher.invalidate_folder(parent);
+                }
+                if let (Some(dedup), Some(hash)) = (&self.dedup, content_hash) {
+                    dedup.record(hash, &new_path);
+                }
+                self.recent_moves.record(&new_path);
+                if options.keep_source {
+                    // Changes to the source while copying aren't new files
+                    self.recent_moves.record(file_path);
+                    self.recent_moves.record(&processed_path);
+                }
+                if let Some(history) = &self.history {
+                    if let Err(e) = history.record(file_path, &new_path) {
+                        log::warn!("{:#}", e);
+                    }
+                }
+                if self.folder_manifest {
+                    if let Err(e) = history::record_in_manifest(file_path, &new_path) {
+                        log::warn!("{:#}", e);
+                    }
+                }
+                if route.category == FileCategory::Video {
+                    let stem = file_stem(&processed_path);
+                    if let (Some(stem), Some(folder)) = (stem, new_path.parent()) {
+                        self.recent_videos.record(stem, folder);
+                    }
+                }
+                if self.tag_files {
+                    let folder = event.matched_folder.as_deref().and_then(Path::file_name);
+                    let folder = folder.and_then(|name| name.to_str());
+                    if let Err(e) = tags::tag_file(&new_path, route.category.name(), folder) {
+                        log::warn!("{:#}", e);
+                    }
+                }
+                if self.remove_empty_source_dirs {
+                    self.remove_emptied_dirs(file_path);
+                }
                 log::info!("Successfully organized file to: {:?}", new_path);
-                Ok(())
+                self.hooks.run(&MovedFile::new(file_path, &new_path, route.category.name()));
+                Ok(OrganizeOutcome::Moved(new_path))
             }
             Err(e) => {
                 log::error!("Failed to move file: {}", e);
@@ -101,6 +685,323 @@ impl FileOrganizer {
             }
         }
     }
+
+    /// Decide the category and destination of `path`, recording them in `event`
+    fn route_file(
+        &self,
+        path: &Path,
+        event: &mut OrganizeEvent,
+        timings: &mut StageTimings,
+    ) -> Result<Route> {
+        let file_size = std::fs::metadata(path)?.len();
+        let route = timed(&mut timings.detect, || self.categorizer.route(path, file_size));
+        let route = match route {
+            Ok(route) => route,
+            Err(e) => {
+                log::error!("Failed to detect file category: {}", e);
+                return Err(e);
+            }
+        };
+
+        match route.detection_method {
+            Some(method) => log::info!("Detected category: {:?} (by {:?})", route.category, method),
+            None => log::info!("Detected category: {:?}", route.category),
+        }
+        event.category = Some(route.category.name().to_string());
+        event.mime = route.mime.clone();
+        event.destination = route.destination.clone();
+
+        if self.explain {
+            log::info!("Routing {:?}: {}", path, route.explain());
+        } else {
+            log::debug!("Routing {:?}: {}", path, route.explain());
+        }
+        Ok(route)
+    }
+
+    /// Top-level destination of a file routed by `route`, or `None` if it isn't organized
+    fn organizable_destination(&self, route: &Route, path: &Path) -> Option<PathBuf> {
+        if !self.is_enabled(route) {
+            log::info!("Category {:?} is not enabled, skipping: {:?}", route.category, path);
+            return None;
+        }
+
+        // Skip unknown files unless a catch-all destination is configured
+        if route.category == FileCategory::Unknown && route.destination.is_none() {
+            log::warn!("Unknown file type, skipping: {:?}", path);
+            return None;
+        }
+
+        if route.destination.is_none() {
+            log::warn!("No rule configured for category {:?}, skipping", route.category);
+        }
+        route.destination.clone()
+    }
+
+    /// Whether files routed by `route` are organized under `enabled_categories`
+    /// Extension overrides skip all rules, so their files always are.
+    fn is_enabled(&self, route: &Route) -> bool {
+        is_override(route) || is_listed(&self.enabled_categories, &route.category)
+    }
+
+    /// Work out where `file_path` would be organized and why, without changing anything
+    /// Preprocessing is skipped, since converters and renamers modify files.
+    pub fn explain_file(&self, file_path: &Path) -> Result<Explanation> {
+        let route = if is_bundle(file_path) {
+            self.bundle_route()
+        } else {
+            let size = std::fs::metadata(file_path)?.len();
+            self.categorizer.route(file_path, size)?
+        };
+        let mut explanation = Explanation {
+            file: file_path.to_path_buf(),
+            route,
+            subfolder: None,
+            destination: None,
+            conflict: None,
+            skip_reason: None,
+        };
+
+        let route = &explanation.route;
+        if !self.is_enabled(route) {
+            explanation.skip_reason = Some("category not enabled");
+            return Ok(explanation);
+        }
+        let Some(top_level) = &route.destination else {
+            explanation.skip_reason = Some("no destination for this category");
+            return Ok(explanation);
+        };
+
+        // Bundles go straight into the bundle destination, keeping their name
+        let is_bundle = route.category_source == CategorySource::Bundle;
+        if is_bundle && self.safe_mode {
+            explanation.skip_reason = Some("bundles are left in place in safe mode");
+            return Ok(explanation);
+        }
+        let (folder, subfolder) = if is_bundle {
+            (top_level.clone(), None)
+        } else {
+            self.choose_folder(file_path, file_path, route, top_level)?
+        };
+        if FileMover::is_in_directory(file_path, &folder) {
+            explanation.skip_reason = Some("already in its destination");
+        } else {
+            let options = if is_bundle {
+                MoveOptions::default()
+            } else {
+                self.move_options(file_path, &route.category)
+            };
+            let planned = FileMover::plan_move(file_path, &folder, options)?;
+            explanation.destination = planned.destination;
+            explanation.conflict = planned.conflict;
+            if explanation.destination.is_none() {
+                explanation.skip_reason = Some("a file with this name already exists");
+            }
+        }
+        explanation.subfolder = subfolder;
+        Ok(explanation)
+    }
+
+    /// How macOS bundles are routed: as applications, to the bundle destination
+    fn bundle_route(&self) -> Route {
+        Route {
+            category: FileCategory::Application,
+            category_source: CategorySource::Bundle,
+            mime: None,
+            detection_method: None,
+            destination: Some(self.bundle_destination.clone()),
+            destination_source: DestinationSource::BundleDestination,
+        }
+    }
+
+    /// Move the macOS bundle `bundle` whole into the bundle destination, renaming it
+    /// on name conflicts, recording the decisions in `event`
+    /// Bundles are only renamed into place, never copied, so safe mode and other
+    /// drives leave them where they are.
+    fn organize_bundle(&self, bundle: &Path, event: &mut OrganizeEvent) -> Result<OrganizeOutcome> {
+        let route = self.bundle_route();
+        event.category = Some(route.category.name().to_string());
+        event.destination = route.destination.clone();
+        let Some(destination) = self.organizable_destination(&route, bundle) else {
+            return Ok(OrganizeOutcome::Skipped);
+        };
+        if self.safe_mode {
+            log::info!("Safe mode: leaving bundle in place: {:?}", bundle);
+            return Ok(OrganizeOutcome::Skipped);
+        }
+        if FileMover::is_in_directory(bundle, &destination) {
+            log::debug!("Bundle already in its destination: {:?}", bundle);
+            return Ok(OrganizeOutcome::Skipped);
+        }
+
+        std::fs::create_dir_all(&destination)
+            .context(format!("Failed to create bundle destination {:?}", destination))?;
+        let new_path = FileMover::planned_destination(bundle, &destination, MoveOptions::default())?
+            .context("Bundle was left in place")?;
+        log::info!("Moving bundle {:?} -> {:?}", bundle, new_path);
+        std::fs::rename(bundle, &new_path)
+            .context(format!("Failed to move bundle {:?} (bundles aren't copied)", bundle))?;
+
+        self.recent_moves.record(&new_path);
+        if let Some(history) = &self.history {
+            if let Err(e) = history.record(bundle, &new_path) {
+                log::warn!("{:#}", e);
+            }
+        }
+        Ok(OrganizeOutcome::Moved(new_path))
+    }
+
+    /// Wait until `delay` has passed since the last move into a synced folder
+    /// The lock is held while waiting, so concurrent moves line up one by one.
+    fn pace_sync_move(&self, delay: Duration) {
+        let mut last = self.last_sync_move.lock().unwrap();
+        if let Some(wait) = last.map(|last| delay.saturating_sub(last.elapsed())) {
+            if !wait.is_zero() {
+                log::debug!("Pacing move into synced folder, waiting {:?}", wait);
+                std::thread::sleep(wait);
+            }
+        }
+        *last = Some(Instant::now());
+    }
+
+    /// How `path`, detected as `category`, is moved
+    fn move_options(&self, path: &Path, category: &FileCategory) -> MoveOptions<'_> {
+        let compress = self.compress.as_ref().is_some_and(|rules| rules.applies(path, category));
+        MoveOptions {
+            fs: self.fs.as_ref(),
+            throttle: self.copy_throttle.as_ref(),
+            staged: self.staged_copies,
+            conflict: self.conflict_strategy,
+            suffix: Some(self.conflict_suffixes.get(category).unwrap_or(&self.conflict_suffix)),
+            names: Some(&self.normalize_filenames),
+            compress,
+            keep_source: self.safe_mode,
+        }
+    }
+
+    /// Get rid of the original of a file that was compressed into its destination
+    fn dispose_compressed(&self, original: &Path) -> Result<()> {
+        if self.compress.as_ref().is_some_and(|rules| rules.trash_originals) {
+            trash::delete(original).context(format!("Failed to trash {:?}", original))
+        } else {
+            self.fs.remove_file(original).context(format!("Failed to remove {:?}", original))
+        }
+    }
+
+    /// Folder within `top_level` that `processed_path` (found at `source`) goes to,
+    /// with the subfolder search result when matching ran
+    fn choose_folder(
+        &self,
+        source: &Path,
+        processed_path: &Path,
+        route: &Route,
+        top_level: &Path,
+    ) -> Result<(PathBuf, Option<SubfolderMatch>)> {
+        if route.category == FileCategory::Subtitle {
+            if let Some(folder) = self.paired_video_folder(processed_path) {
+                log::info!("Filing subtitle next to its video in {:?}", folder);
+                return Ok((folder, None));
+            }
+        }
+
+        if self.preserve_source_structure {
+            if let Some(relative) = self.relative_source_dir(source) {
+                return Ok((top_level.join(relative), None));
+            }
+        }
+
+        // Photos, screenshots and icons each get a folder within the images folder
+        let kind_folder = match (&self.image_classifier, &route.category) {
+            (Some(classifier), FileCategory::Image) => classifier
+                .classify(processed_path)
+                .map(|kind| classifier.folder(kind, top_level)),
+            _ => None,
+        };
+        let top_level = kind_folder.as_deref().unwrap_or(top_level);
+
+        let skip_matching = if is_override(route) {
+            !self.match_within_overrides
+        } else {
+            !is_listed(&self.match_categories, &route.category)
+        };
+        let Some(matcher) = self.matcher.as_ref().filter(|_| !skip_matching) else {
+            return Ok((top_level.to_path_buf(), None));
+        };
+
+        let source_context = self.source_context(source);
+        let subfolder = matcher.find_contextual_match(
+            processed_path,
+            top_level,
+            &route.category,
+            source_context.as_deref(),
+        )?;
+        Ok((subfolder.path.clone(), Some(subfolder)))
+    }
+
+    /// Folder a recently organized video with the subtitle's name was moved to
+    /// Language and other suffixes are dropped one at a time, so "Movie.en.forced.srt"
+    /// pairs with "Movie.mkv"
+    fn paired_video_folder(&self, subtitle: &Path) -> Option<PathBuf> {
+        let mut stem = file_stem(subtitle)?;
+        loop {
+            if let Some(folder) = self.recent_videos.folder_for(stem) {
+                return Some(folder);
+            }
+            stem = stem.rsplit_once('.')?.0;
+        }
+    }
+
+    /// Names of the folders between the watch root and `file_path` (e.g. "Work ClientA"),
+    /// or `None` when disabled or the file is directly in the watch root
+    fn source_context(&self, file_path: &Path) -> Option<String> {
+        if !self.use_source_path_context {
+            return None;
+        }
+        let relative = self.relative_source_dir(file_path)?;
+        let folders: Vec<_> = relative.iter().map(|name| name.to_string_lossy()).collect();
+        Some(folders.join(" "))
+    }
+
+    /// Remove the folder `file_path` was moved out of if that left it empty, then
+    /// each parent emptied by that in turn, stopping below the watch root
+    /// Only folders this organizer moved something out of are removed, so empty
+    /// folders that were already there stay.
+    fn remove_emptied_dirs(&self, file_path: &Path) {
+        let Some(watch_root) = &self.watch_root else {
+            return;
+        };
+        let mut emptied = self.emptied_dirs.lock().unwrap();
+        let mut dir = file_path.parent();
+        if let Some(dir) = dir {
+            emptied.insert(dir.to_path_buf());
+        }
+
+        let below_root = |dir: &&Path| dir.starts_with(watch_root) && dir != watch_root;
+        while let Some(current) = dir.filter(below_root) {
+            let mut entries = std::fs::read_dir(current);
+            let empty = entries.as_mut().is_ok_and(|entries| entries.next().is_none());
+            if !empty || !emptied.contains(current) {
+                return;
+            }
+            if let Err(e) = std::fs::remove_dir(current) {
+                log::warn!("Could not remove emptied folder {:?}: {}", current, e);
+                return;
+            }
+            log::info!("Removed emptied folder {:?}", current);
+            emptied.remove(current);
+            dir = current.parent();
+            if let Some(parent) = dir {
+                emptied.insert(parent.to_path_buf());
+            }
+        }
+    }
+
+    /// Folder of `file_path` relative to the watch root (e.g. "receipts/2023"),
+    /// or `None` when the file is directly in the watch root or outside it
+    fn relative_source_dir<'a>(&self, file_path: &'a Path) -> Option<&'a Path> {
+        let relative = file_path.parent()?.strip_prefix(self.watch_root.as_ref()?).ok()?;
+        relative.components().next().is_some().then_some(relative)
+    }
 }
 
 impl Default for FileOrganizer {
@@ -108,3 +1009,1404 @@ impl Default for FileOrganizer {
         Self::new().expect("Failed to create default file organizer")
     }
 }
+
+/// Time spent in each stage of organizing one file; stages that didn't run are `None`
+#[derive(Debug, Default)]
+struct StageTimings {
+    preprocess: Option<Duration>,
+    detect: Option<Duration>,
+    matching: Option<Duration>,
+    dedup: Option<Duration>,
+    move_file: Option<Duration>,
+}
+
+impl StageTimings {
+    /// e.g. "preprocess 0ms, detect 3ms, match 390ms, move 19ms"
+    fn summary(&self) -> String {
+        [
+            ("preprocess", self.preprocess),
+            ("detect", self.detect),
+            ("match", self.matching),
+            ("dedup", self.dedup),
+            ("move", self.move_file),
+        ]
+        .iter()
+        .filter_map(|(stage, duration)| duration.map(|d| format!("{} {}ms", stage, d.as_millis())))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+/// Whether `category` is in a configured category list; an empty list allows all
+fn is_listed(categories: &[FileCategory], category: &FileCategory) -> bool {
+    categories.is_empty()
+        || categories
+            .iter()
+            .any(|listed| listed.name().eq_ignore_ascii_case(category.name()))
+}
+
+/// Whether an `[extension_overrides]` entry routed the file, bypassing categorization
+fn is_override(route: &Route) -> bool {
+    matches!(route.destination_source, DestinationSource::ExtensionOverride { .. })
+}
+
+/// File name without its last extension, if it is valid UTF-8
+fn file_stem(path: &Path) -> Option<&str> {
+    path.file_stem().and_then(|stem| stem.to_str())
+}
+
+/// Run `stage`, adding how long it took to `slot`
+fn timed<T>(slot: &mut Option<Duration>, stage: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = stage();
+    *slot = Some(slot.unwrap_or_default() + started.elapsed());
+    result
+}
+
+/// Check up front that `path` can be read and removed from its folder, so files
+/// owned by another user are skipped instead of failing deep inside the move
+fn check_permissions(path: &Path) -> std::result::Result<(), String> {
+    std::fs::File::open(path).map_err(|e| format!("cannot read file: {}", e))?;
+
+    let parent = path.parent().ok_or("file has no parent folder")?;
+    check_folder_writable(parent)
+}
+
+/// Ask the OS whether files can be removed from `folder`; its mode bits alone
+/// ignore who owns it, root and read-only mounts
+#[cfg(unix)]
+fn check_folder_writable(folder: &Path) -> std::result::Result<(), String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(folder.as_os_str().as_bytes())
+        .map_err(|_| format!("folder {:?} has a NUL byte in its name", folder))?;
+    // SAFETY: `c_path` is a valid NUL-terminated string for the duration of the call
+    if unsafe { libc::access(c_path.as_ptr(), libc::W_OK | libc::X_OK) } == 0 {
+        return Ok(());
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EPERM) => {
+            Err(format!("folder {:?} is not writable", folder))
+        }
+        Some(libc::EROFS) => Err(format!("folder {:?} is on a read-only filesystem", folder)),
+        _ => Err(format!("cannot inspect folder: {}", error)),
+    }
+}
+
+/// Windows ignores the read-only attribute on folders, so there is nothing to check
+#[cfg(not(unix))]
+fn check_folder_writable(_folder: &Path) -> std::result::Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::categorizer::CategoryRule;
+    use crate::config::{CategoryMatch, CustomCategory, DedupConfig, MatcherConfig};
+    use crate::filesystem::MemoryFileSystem;
+    use crate::matcher::Embedder;
+    use crate::preprocessor::{HeicConverter, Preprocessor};
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    /// Sink that keeps every emitted event
+    struct CollectingSink(Arc<Mutex<Vec<OrganizeEvent>>>);
+
+    impl EventSink for CollectingSink {
+        fn emit(&self, event: &OrganizeEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    /// Embedder that never produces a subfolder match
+    struct NullEmbedder;
+
+    impl Embedder for NullEmbedder {
+        fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|_| vec![0.0, 0.0]).collect())
+        }
+    }
+
+    /// Embedder that finds every file similar to every folder
+    struct UniformEmbedder;
+
+    impl Embedder for UniformEmbedder {
+        fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+    }
+
+    fn organizer(config: &Config, categorizer: Categorizer) -> FileOrganizer {
+        let matcher = SubfolderMatcher::with_embedder(Box::new(NullEmbedder), &MatcherConfig::default());
+        FileOrganizer::from_parts(config, categorizer, matcher)
+    }
+
+    fn documents_categorizer(destination: &Path) -> Categorizer {
+        let mut rules = HashMap::new();
+        rules.insert(
+            FileCategory::Document,
+            CategoryRule {
+                name: "Documents".to_string(),
+                destination: destination.to_path_buf(),
+            },
+        );
+        Categorizer::_with_custom_rules(rules)
+    }
+
+    /// Post-detection stand-in for `HeicConverter`, counting the files it converts
+    struct LateConverter(Arc<AtomicUsize>);
+
+    impl Preprocessor for LateConverter {
+        fn name(&self) -> &str {
+            "Late converter"
+        }
+
+        fn should_process(&self, path: &Path) -> bool {
+            path.extension().is_some_and(|ext| ext == "heic")
+        }
+
+        fn phase(&self) -> PreprocessPhase {
+            PreprocessPhase::PostDetection
+        }
+
+        fn process(&self, path: &Path) -> Result<PathBuf> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let output = path.with_extension("png");
+            fs::rename(path, &output)?;
+            Ok(output)
+        }
+    }
+
+    /// Post-detection step that turns `.dat` files into PNGs in place
+    struct InPlaceDecoder;
+
+    impl Preprocessor for InPlaceDecoder {
+        fn name(&self) -> &str {
+            "In-place decoder"
+        }
+
+        fn should_process(&self, path: &Path) -> bool {
+            path.extension().is_some_and(|ext| ext == "dat")
+        }
+
+        fn phase(&self) -> PreprocessPhase {
+            PreprocessPhase::PostDetection
+        }
+
+        fn process(&self, path: &Path) -> Result<PathBuf> {
+            fs::write(path, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+            Ok(path.to_path_buf())
+        }
+    }
+
+    /// Post-detection stand-in for `HeicConverter` writing a real PNG header
+    struct PngConverter;
+
+    impl Preprocessor for PngConverter {
+        fn name(&self) -> &str {
+            "PNG converter"
+        }
+
+        fn should_process(&self, path: &Path) -> bool {
+            path.extension().is_some_and(|ext| ext == "heic")
+        }
+
+        fn phase(&self) -> PreprocessPhase {
+            PreprocessPhase::PostDetection
+        }
+
+        fn process(&self, path: &Path) -> Result<PathBuf> {
+            let output = path.with_extension("png");
+            fs::write(&output, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+            fs::remove_file(path)?;
+            Ok(output)
+        }
+    }
+
+    #[test]
+    fn test_category_is_detected_from_preprocessed_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let (pictures, misc) = (temp_dir.path().join("Pictures"), temp_dir.path().join("Misc"));
+        let rule = |name: &str, destination: &Path| CategoryRule {
+            name: name.to_string(),
+            destination: destination.to_path_buf(),
+        };
+        let rules = HashMap::from([
+            (FileCategory::Image, rule("Images", &pictures)),
+            (FileCategory::Unknown, rule("Misc", &misc)),
+        ]);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let pipeline = PreprocessorPipeline::with_preprocessors(vec![
+            Box::new(PngConverter),
+            Box::new(InPlaceDecoder),
+        ]);
+        let organizer = organizer(&Config::default(), Categorizer::_with_custom_rules(rules))
+            .with_preprocessors(pipeline)
+            .with_event_sink(Box::new(CollectingSink(events.clone())));
+
+        // HEIC converted to PNG is detected as an image through the PNG
+        let photo = temp_dir.path().join("IMG_0001.heic");
+        fs::write(&photo, b"\0\0\0\x18ftypheic\0\0\0\0mif1heic")?;
+        assert_eq!(
+            organizer.organize_file(&photo)?,
+            OrganizeOutcome::Moved(pictures.join("IMG_0001.png"))
+        );
+        assert_eq!(events.lock().unwrap()[0].mime.as_deref(), Some("image/png"));
+
+        // Unknown data decoded into an image in place, keeping its path, is an image
+        let scan = temp_dir.path().join("scan.dat");
+        fs::write(&scan, b"encoded pixels")?;
+        assert_eq!(
+            organizer.organize_file(&scan)?,
+            OrganizeOutcome::Moved(pictures.join("scan.dat"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_detection_preprocessing_only_runs_for_moved_files() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let pictures = temp_dir.path().join("Pictures");
+        let rules = HashMap::from([(
+            FileCategory::Image,
+            CategoryRule {
+                name: "Images".to_string(),
+                destination: pictures.clone(),
+            },
+        )]);
+        // Just enough of an HEIF header for detection: an ftyp box with the heic brand
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+
+        let converted = Arc::new(AtomicUsize::new(0));
+        let organizer = |config: &Config| {
+            let pipeline = PreprocessorPipeline::with_preprocessors(vec![Box::new(LateConverter(
+                converted.clone(),
+            ))]);
+            organizer(config, Categorizer::_with_custom_rules(rules.clone()))
+                .with_preprocessors(pipeline)
+        };
+
+        let photo = temp_dir.path().join("IMG_0001.heic");
+        fs::write(&photo, heic)?;
+        let outcome = organizer(&Config::default()).organize_file(&photo)?;
+        assert_eq!(outcome, OrganizeOutcome::Moved(pictures.join("IMG_0001.png")));
+        assert_eq!(converted.load(Ordering::SeqCst), 1);
+
+        // Images aren't enabled, so there's no point converting it
+        let skipped = temp_dir.path().join("IMG_0002.heic");
+        fs::write(&skipped, heic)?;
+        let config = Config {
+            enabled_categories: vec![FileCategory::Document],
+            ..Default::default()
+        };
+        assert_eq!(organizer(&config).organize_file(&skipped)?, OrganizeOutcome::Skipped);
+        assert_eq!(converted.load(Ordering::SeqCst), 1);
+        assert!(skipped.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics_count_organized_files_by_category() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let pictures = temp_dir.path().join("Pictures");
+        let rules = HashMap::from([(
+            FileCategory::Image,
+            CategoryRule {
+                name: "Images".to_string(),
+                destination: pictures,
+            },
+        )]);
+        let metrics = Arc::new(Metrics::new());
+        let organizer = organizer(&Config::default(), Categorizer::_with_custom_rules(rules))
+            .with_metrics(metrics.clone());
+
+        for name in ["one.png", "two.png"] {
+            let image = temp_dir.path().join(name);
+            fs::write(&image, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+            assert!(matches!(organizer.organize_file(&image)?, OrganizeOutcome::Moved(_)));
+        }
+
+        let text = metrics.render();
+        assert!(text.contains("autofile_files_organized_total{category=\"image\"} 2\n"));
+        assert!(text.contains("autofile_files_processed_total{outcome=\"moved\"} 2\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_configured_logs_arrive_gzipped() -> Result<()> {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let temp_dir = TempDir::new()?;
+        let logs = temp_dir.path().join("Logs");
+        let config = Config {
+            compress: Some(CompressConfig {
+                extensions: vec!["LOG".to_string(), "gz".to_string()],
+                categories: Vec::new(),
+                trash_originals: false,
+            }),
+            ..Default::default()
+        };
+        let mut rules = HashMap::new();
+        rules.insert(
+            FileCategory::Unknown,
+            CategoryRule {
+                name: "Logs".to_string(),
+                destination: logs.clone(),
+            },
+        );
+        let organizer = organizer(&config, Categorizer::_with_custom_rules(rules));
+
+        let contents = "GET /index.html 200\n".repeat(500);
+        fs::create_dir_all(&logs)?;
+        fs::write(logs.join("server.log.gz"), b"an earlier day")?;
+        let log_file = temp_dir.path().join("server.log");
+        fs::write(&log_file, &contents)?;
+
+        let outcome = organizer.organize_file(&log_file)?;
+        let compressed = logs.join("server (1).log.gz");
+        assert_eq!(outcome, OrganizeOutcome::Moved(compressed.clone()));
+        assert!(!log_file.exists());
+
+        let mut decompressed = String::new();
+        GzDecoder::new(fs::File::open(&compressed)?).read_to_string(&mut decompressed)?;
+        assert_eq!(decompressed, contents);
+
+        // Already-compressed types are moved as they are, even when listed
+        let rules = config.compress.as_ref().unwrap();
+        assert!(!rules.applies(Path::new("old.gz"), &FileCategory::Unknown));
+        Ok(())
+    }
+
+    #[test]
+    fn test_moves_go_through_the_file_system() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("lease.pdf");
+        fs::write(&file, b"%PDF-1.4\n")?;
+        let memory = Arc::new(MemoryFileSystem::new());
+        memory.add_file(&file, b"%PDF-1.4\n", SystemTime::now());
+        memory.add_file("/documents/lease.pdf", b"earlier", SystemTime::now());
+
+        let config = Config {
+            semantic_matching: false,
+            ..Config::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(Path::new("/documents")))
+            .with_file_system(memory.clone());
+        let outcome = organizer.organize_file(&file)?;
+
+        let renamed = PathBuf::from("/documents/lease (1).pdf");
+        assert_eq!(outcome, OrganizeOutcome::Moved(renamed.clone()));
+        assert_eq!(memory.contents(&renamed), Some(b"%PDF-1.4\n".to_vec()));
+        assert_eq!(memory.contents(&file), None);
+        // Nothing on disk was touched
+        assert!(file.exists());
+        assert!(!Path::new("/documents").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_folder_hook_gets_final_path_after_conflict() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        fs::create_dir_all(&documents)?;
+        fs::write(documents.join("lease.pdf"), b"earlier")?;
+        let received = temp_dir.path().join("received.txt");
+        let script = format!("printf '%s' \"$1\" > '{}'", received.display());
+        let mut config = Config::default();
+        config.hooks.folders.insert(
+            documents.clone(),
+            vec![vec!["sh".to_string(), "-c".to_string(), script, "hook".to_string()]],
+        );
+        let organizer = organizer(&config, documents_categorizer(&documents));
+
+        let file = temp_dir.path().join("lease.pdf");
+        fs::write(&file, b"%PDF-1.4\n")?;
+        organizer.organize_file(&file)?;
+
+        // Hooks run in the background
+        let started = std::time::Instant::now();
+        let mut contents = String::new();
+        while contents.is_empty() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(20));
+            contents = fs::read_to_string(&received).unwrap_or_default();
+        }
+        assert_eq!(contents, documents.join("lease (1).pdf").display().to_string());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tags_organized_files_with_their_category() -> Result<()> {
+        if !crate::utils::file::xattrs_supported() {
+            return Ok(());
+        }
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let config = Config {
+            tag_files: true,
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents));
+
+        let file = temp_dir.path().join("lease.pdf");
+        fs::write(&file, b"%PDF-1.4\n")?;
+        organizer.organize_file(&file)?;
+
+        let category = xattr::get(documents.join("lease.pdf"), "user.autofile.category")?;
+        assert_eq!(category.as_deref(), Some(&b"document"[..]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_lists_most_recent_move_first() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let history_path = temp_dir.path().join("history.jsonl");
+        let organizer = organizer(&Config::default(), documents_categorizer(&documents))
+            .with_history(MoveHistory::new(history_path.clone()));
+
+        // Nothing recorded yet
+        assert!(MoveHistory::new(history_path.clone()).recent(10)?.is_empty());
+
+        for name in ["first.pdf", "second.pdf"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, b"%PDF-1.4\n")?;
+            organizer.organize_file(&file)?;
+        }
+
+        let recent = MoveHistory::new(history_path).recent(1)?;
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].source, temp_dir.path().join("second.pdf"));
+        assert_eq!(recent[0].destination, documents.join("second.pdf"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_folder_manifest_lists_placed_files() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let config = Config {
+            folder_manifest: true,
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents));
+
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, b"content")?;
+        organizer.organize_file(&file)?;
+
+        let manifest = documents.join(MANIFEST_FILE);
+        let entries = MoveHistory::new(manifest.clone()).recent(10)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].source, file);
+        assert_eq!(entries[0].destination, documents.join("notes.txt"));
+
+        // The manifest itself is never organized, wherever it turns up
+        assert_eq!(organizer.organize_file(&manifest)?, OrganizeOutcome::Skipped);
+        let stray = temp_dir.path().join(MANIFEST_FILE);
+        fs::copy(&manifest, &stray)?;
+        assert_eq!(organizer.organize_file(&stray)?, OrganizeOutcome::Skipped);
+        assert!(crate::utils::file::is_hidden_file(&stray));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_files_use_catch_all_destination() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let misc = temp_dir.path().join("Misc");
+        let file = temp_dir.path().join("blob.xyz");
+        fs::write(&file, b"no recognizable format")?;
+
+        // Without a catch-all, unknown files stay put
+        let config = Config::default();
+        let outcome = organizer(&config, Categorizer::_with_custom_rules(HashMap::new())).organize_file(&file)?;
+        assert_eq!(outcome, OrganizeOutcome::Skipped);
+        assert!(file.exists());
+
+        let categorizer = Categorizer::_with_custom_rules(HashMap::new())
+            .with_unknown_destination(Some(misc.clone()));
+        organizer(&config, categorizer).organize_file(&file)?;
+        assert!(!file.exists());
+        assert!(misc.join("blob.xyz").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_files_are_deferred_until_closed() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let file = temp_dir.path().join("recording.txt");
+        let handle = fs::File::create(&file)?;
+
+        let config = Config {
+            defer_open_files: true,
+            skip_empty_files: false,
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents));
+        assert!(matches!(organizer.organize_file(&file)?, OrganizeOutcome::Deferred(_)));
+        assert!(file.exists());
+
+        drop(handle);
+        assert!(matches!(organizer.organize_file(&file)?, OrganizeOutcome::Moved(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fresh_files_are_deferred_until_old_enough() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let file = temp_dir.path().join("export.txt");
+        fs::write(&file, b"still being written")?;
+
+        let config = Config {
+            min_file_age_secs: Some(60),
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents));
+
+        assert!(matches!(organizer.organize_file(&file)?, OrganizeOutcome::Deferred(_)));
+        assert!(file.exists());
+
+        // Once the file has aged past the threshold it is organized
+        fs::File::options()
+            .write(true)
+            .open(&file)?
+            .set_modified(SystemTime::now() - Duration::from_secs(120))?;
+        assert_eq!(
+            organizer.organize_file(&file)?,
+            OrganizeOutcome::Moved(documents.join("export.txt"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_metadata_is_left_alone_and_synced_moves_are_paced() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Dropbox").join("Documents");
+        let config = Config {
+            sync_move_delay_ms: Some(300),
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents));
+
+        for name in ["~$budget.txt", "budget (Jane's conflicted copy 2024-01-02).txt"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, b"sync client file")?;
+            assert_eq!(organizer.organize_file(&file)?, OrganizeOutcome::Skipped);
+            assert!(file.exists());
+        }
+
+        let started = Instant::now();
+        for name in ["first.txt", "second.txt"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, b"notes")?;
+            let moved = documents.join(name);
+            assert_eq!(organizer.organize_file(&file)?, OrganizeOutcome::Moved(moved));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_app_bundles_move_whole() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let applications = temp_dir.path().join("Applications");
+        let binary = downloads.join("Tool.app").join("Contents").join("MacOS").join("tool");
+        fs::create_dir_all(binary.parent().unwrap())?;
+        fs::write(&binary, b"executable")?;
+        let config = Config {
+            bundle_destination: Some(applications.clone()),
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&temp_dir.path().join("Docs")));
+
+        // Files inside the bundle are part of it, not files of their own
+        assert_eq!(organizer.organize_file(&binary)?, OrganizeOutcome::Skipped);
+        assert!(binary.exists());
+        let listed = crate::utils::file::list_files(&downloads, true)?;
+        assert_eq!(listed, vec![downloads.join("Tool.app")]);
+
+        let moved = applications.join("Tool.app");
+        assert_eq!(
+            organizer.organize_file(&downloads.join("Tool.app"))?,
+            OrganizeOutcome::Moved(moved.clone())
+        );
+        assert!(moved.join("Contents").join("MacOS").join("tool").is_file());
+        assert!(!downloads.join("Tool.app").exists());
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_app_bundles_are_the_application_category() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Docs");
+        let applications = temp_dir.path().join("Applications");
+        let bundle = temp_dir.path().join("Downloads").join("Tool.app");
+        fs::create_dir_all(bundle.join("Contents"))?;
+        let config = |enabled| Config {
+            bundle_destination: Some(applications.clone()),
+            enabled_categories: vec![enabled],
+            ..Default::default()
+        };
+
+        let images = config(FileCategory::Image);
+        let images_only = organizer(&images, documents_categorizer(&documents));
+        assert_eq!(images_only.organize_file(&bundle)?, OrganizeOutcome::Skipped);
+        assert!(bundle.exists());
+        let explanation = images_only.explain_file(&bundle)?;
+        assert_eq!(explanation.skip_reason, Some("category not enabled"));
+
+        let apps = config(FileCategory::Application);
+        let apps = organizer(&apps, documents_categorizer(&documents));
+        let explanation = apps.explain_file(&bundle)?;
+        assert_eq!(explanation.route.category, FileCategory::Application);
+        assert_eq!(explanation.destination, Some(applications.join("Tool.app")));
+        assert!(bundle.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_age_follows_date_source() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let file = temp_dir.path().join("old-report.txt");
+        fs::write(&file, b"report")?;
+        // Modified long ago, but only just opened
+        let times = fs::FileTimes::new()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .set_accessed(SystemTime::now());
+        fs::File::options().write(true).open(&file)?.set_times(times)?;
+
+        let config = Config {
+            min_file_age_secs: Some(60),
+            date_source: DateSource::Accessed,
+            ..Default::default()
+        };
+        let by_access = organizer(&config, documents_categorizer(&documents));
+        assert!(matches!(by_access.organize_file(&file)?, OrganizeOutcome::Deferred(_)));
+
+        let config = Config { min_file_age_secs: Some(60), ..Default::default() };
+        let by_modification = organizer(&config, documents_categorizer(&documents));
+        assert!(matches!(by_modification.organize_file(&file)?, OrganizeOutcome::Moved(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_enabled_categories_are_organized() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let pictures = temp_dir.path().join("Pictures");
+        let report = temp_dir.path().join("report.txt");
+        fs::write(&report, b"quarterly numbers")?;
+        let photo = temp_dir.path().join("photo.png");
+        fs::write(&photo, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+
+        let rules = HashMap::from([
+            (
+                FileCategory::Document,
+                CategoryRule {
+                    name: "Documents".to_string(),
+                    destination: documents.clone(),
+                },
+            ),
+            (
+                FileCategory::Image,
+                CategoryRule {
+                    name: "Pictures".to_string(),
+                    destination: pictures.clone(),
+                },
+            ),
+        ]);
+        let config = Config {
+            enabled_categories: vec![FileCategory::Image, FileCategory::Video],
+            ..Default::default()
+        };
+        let organizer = organizer(&config, Categorizer::_with_custom_rules(rules));
+
+        assert_eq!(organizer.organize_file(&report)?, OrganizeOutcome::Skipped);
+        assert!(report.exists());
+        assert_eq!(
+            organizer.organize_file(&photo)?,
+            OrganizeOutcome::Moved(pictures.join("photo.png"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_extension_overrides_ignore_enabled_categories() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let designs = temp_dir.path().join("Designs");
+        let sketch = temp_dir.path().join("logo.sketch");
+        fs::write(&sketch, b"sketch file")?;
+
+        let config = Config {
+            enabled_categories: vec![FileCategory::Image],
+            ..Default::default()
+        };
+        let categorizer = documents_categorizer(&documents)
+            .with_extension_overrides(HashMap::from([("sketch".to_string(), designs.clone())]));
+        let organizer = organizer(&config, categorizer);
+
+        let explanation = organizer.explain_file(&sketch)?;
+        assert_eq!(explanation.skip_reason, None);
+        assert_eq!(explanation.destination, Some(designs.join("logo.sketch")));
+        assert_eq!(
+            organizer.organize_file(&sketch)?,
+            OrganizeOutcome::Moved(designs.join("logo.sketch"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_already_in_destination_is_left_alone() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, b"meeting notes")?;
+        fs::create_dir(temp_dir.path().join("Sub"))?;
+
+        // The same folder, spelled differently
+        let destination = temp_dir.path().join("Sub").join("..");
+        let organizer = organizer(&Config::default(), documents_categorizer(&destination));
+        assert_eq!(organizer.organize_file(&file)?, OrganizeOutcome::Skipped);
+        assert!(file.exists());
+        assert!(!temp_dir.path().join("notes (1).txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtitle_is_filed_next_to_its_video() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let videos = temp_dir.path().join("Videos").join("Films");
+        let subtitles = temp_dir.path().join("Subtitles");
+        let rule = |name: &str, destination: &Path| CategoryRule {
+            name: name.to_string(),
+            destination: destination.to_path_buf(),
+        };
+        let rules = HashMap::from([
+            (FileCategory::Video, rule("Videos", &videos)),
+            (FileCategory::Subtitle, rule("Subtitles", &subtitles)),
+        ]);
+        let organizer = organizer(&Config::default(), Categorizer::_with_custom_rules(rules));
+
+        let video = temp_dir.path().join("Movie.2023.mkv");
+        fs::write(&video, b"not really matroska")?;
+        organizer.organize_file(&video)?;
+
+        // Language suffixes don't prevent pairing
+        let subtitle = temp_dir.path().join("Movie.2023.en.srt");
+        fs::write(&subtitle, b"1\n00:00:01,000 --> 00:00:02,000\nHello\n")?;
+        assert_eq!(
+            organizer.organize_file(&subtitle)?,
+            OrganizeOutcome::Moved(videos.join("Movie.2023.en.srt"))
+        );
+
+        // Without a matching video, subtitles use their own destination
+        let unpaired = temp_dir.path().join("Other.srt");
+        fs::write(&unpaired, b"1\n00:00:01,000 --> 00:00:02,000\nBye\n")?;
+        assert_eq!(
+            organizer.organize_file(&unpaired)?,
+            OrganizeOutcome::Moved(subtitles.join("Other.srt"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_structure_is_preserved() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let documents = temp_dir.path().join("Documents");
+        let nested = downloads.join("receipts").join("2023");
+        fs::create_dir_all(&nested)?;
+        let receipt = nested.join("hardware.txt");
+        fs::write(&receipt, b"hammer, nails")?;
+        let loose = downloads.join("todo.txt");
+        fs::write(&loose, b"buy nails")?;
+
+        let config = Config {
+            preserve_source_structure: true,
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents))
+            .with_watch_root(downloads.clone());
+        assert_eq!(
+            organizer.organize_file(&receipt)?,
+            OrganizeOutcome::Moved(documents.join("receipts").join("2023").join("hardware.txt"))
+        );
+        // Files directly in the watched folder are organized as usual
+        assert_eq!(
+            organizer.organize_file(&loose)?,
+            OrganizeOutcome::Moved(documents.join("todo.txt"))
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_are_skipped_by_default() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let target = temp_dir.path().join("elsewhere").join("report.txt");
+        fs::create_dir(target.parent().unwrap())?;
+        fs::write(&target, b"quarterly numbers")?;
+        let link = temp_dir.path().join("report.txt");
+        std::os::unix::fs::symlink(&target, &link)?;
+
+        let skipping = organizer(&Config::default(), documents_categorizer(&documents));
+        assert_eq!(skipping.organize_file(&link)?, OrganizeOutcome::Skipped);
+        assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert!(target.exists());
+
+        let config = Config {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let following = organizer(&config, documents_categorizer(&documents));
+        assert_eq!(
+            following.organize_file(&link)?,
+            OrganizeOutcome::Moved(documents.join("report.txt"))
+        );
+        assert!(target.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_only_runs_for_match_categories() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let projects = temp_dir.path().join("Projects");
+        fs::create_dir_all(documents.join("Taxes"))?;
+        fs::create_dir_all(projects.join("Website"))?;
+        let notes = temp_dir.path().join("notes.txt");
+        fs::write(&notes, b"deductions")?;
+        let script = temp_dir.path().join("deploy.rs");
+        fs::write(&script, b"fn main() {}")?;
+
+        let rule = |name: &str, destination: &Path| CategoryRule {
+            name: name.to_string(),
+            destination: destination.to_path_buf(),
+        };
+        let categorizer = Categorizer::_with_custom_rules(HashMap::from([
+            (FileCategory::Document, rule("Documents", &documents)),
+            (FileCategory::Code, rule("Projects", &projects)),
+        ]));
+        let config = Config {
+            matcher: MatcherConfig {
+                match_categories: vec![FileCategory::Document],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let matcher = SubfolderMatcher::with_embedder(Box::new(UniformEmbedder), &config.matcher);
+        let organizer = FileOrganizer::from_parts(&config, categorizer, matcher);
+
+        assert_eq!(
+            organizer.organize_file(&notes)?,
+            OrganizeOutcome::Moved(documents.join("Taxes").join("notes.txt"))
+        );
+        assert_eq!(
+            organizer.organize_file(&script)?,
+            OrganizeOutcome::Moved(projects.join("deploy.rs"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_reports_route_without_moving() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        fs::create_dir_all(documents.join("Taxes"))?;
+        fs::write(documents.join("Taxes").join("notes.txt"), b"already here")?;
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, b"deductions")?;
+
+        let config = Config::default();
+        let matcher = SubfolderMatcher::with_embedder(Box::new(UniformEmbedder), &config.matcher);
+        let organizer = FileOrganizer::from_parts(&config, documents_categorizer(&documents), matcher);
+
+        let explanation = organizer.explain_file(&file)?;
+        let expected = documents.join("Taxes").join("notes (1).txt");
+        assert_eq!(explanation.route.category, FileCategory::Document);
+        assert_eq!(explanation.destination.as_ref(), Some(&expected));
+
+        let report = explanation.to_string();
+        assert!(report.contains("Category:    document (by Extension)"));
+        assert!(report.contains(&format!("1.000  {}", documents.join("Taxes").display())));
+        assert!(report.contains(&format!("Destination: {}", expected.display())));
+        assert!(file.exists());
+        assert!(!expected.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_files_are_skipped_unless_disabled() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let file = temp_dir.path().join("notes.txt");
+        fs::write(&file, b"")?;
+
+        let skipping = organizer(&Config::default(), documents_categorizer(&documents));
+        assert_eq!(skipping.organize_file(&file)?, OrganizeOutcome::Skipped);
+        assert!(file.exists());
+
+        let config = Config {
+            skip_empty_files: false,
+            ..Default::default()
+        };
+        let moving = organizer(&config, documents_categorizer(&documents));
+        assert_eq!(
+            moving.organize_file(&file)?,
+            OrganizeOutcome::Moved(documents.join("notes.txt"))
+        );
+        Ok(())
+    }
+
+    /// Root may write anywhere, so permission tests can't fail it
+    #[cfg(unix)]
+    fn running_as_root() -> bool {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_in_read_only_folder_is_skipped() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if running_as_root() {
+            return Ok(());
+        }
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let locked = temp_dir.path().join("locked");
+        fs::create_dir(&locked)?;
+        let file = locked.join("notes.txt");
+        fs::write(&file, b"can't be moved out")?;
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555))?;
+
+        let organizer = organizer(&Config::default(), documents_categorizer(&documents));
+        let outcome = organizer.organize_file(&file);
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+        assert_eq!(outcome?, OrganizeOutcome::Skipped);
+        assert!(file.exists());
+        assert!(!documents.exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_in_folder_owned_by_another_user_is_skipped() -> Result<()> {
+        // /etc is 0755 and owned by root, so only its owner can remove files from it
+        let file = Path::new("/etc/passwd");
+        if running_as_root() || !file.exists() {
+            return Ok(());
+        }
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+
+        let organizer = organizer(&Config::default(), documents_categorizer(&documents));
+        assert_eq!(organizer.organize_file(file)?, OrganizeOutcome::Skipped);
+        assert!(file.exists());
+        assert!(!documents.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stage_timings_are_recorded() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let file = temp_dir.path().join("report.txt");
+        fs::write(&file, b"quarterly numbers")?;
+
+        let organizer = organizer(&Config::default(), documents_categorizer(&documents));
+        let mut timings = StageTimings::default();
+        organizer.organize(&file, &mut OrganizeEvent::new(&file), &mut timings)?;
+
+        assert!(timings.preprocess.is_some());
+        assert!(timings.detect.is_some());
+        assert!(timings.matching.is_some());
+        assert!(timings.move_file.is_some());
+        // Dedup is disabled, so it neither runs nor appears in the summary
+        assert!(timings.dedup.is_none());
+        let summary = timings.summary();
+        for stage in ["preprocess ", "detect ", "match ", "move "] {
+            assert!(summary.contains(stage), "{} missing from {}", stage, summary);
+        }
+        assert!(!summary.contains("dedup"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_event_for_organized_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let file = temp_dir.path().join("report.txt");
+        fs::write(&file, b"quarterly numbers")?;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let socket_events = Arc::new(Mutex::new(Vec::new()));
+        organizer(&Config::default(), documents_categorizer(&documents))
+            .with_event_sink(Box::new(CollectingSink(events.clone())))
+            .with_event_sink(Box::new(CollectingSink(socket_events.clone())))
+            .organize_file(&file)?;
+
+        // Every sink gets every event
+        assert_eq!(socket_events.lock().unwrap().len(), 1);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let json = serde_json::to_value(&events[0])?;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "input": file,
+                "outcome": "moved",
+                "category": "document",
+                "mime": null,
+                "destination": documents,
+                "matched_folder": null,
+                "similarity": null,
+                "final_path": documents.join("report.txt"),
+                "error": null,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_organize_files_stops_at_limit() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let documents = temp_dir.path().join("Documents");
+        fs::create_dir(&downloads)?;
+        let files: Vec<PathBuf> = (0..10)
+            .map(|i| downloads.join(format!("notes{}.txt", i)))
+            .collect();
+        for file in &files {
+            fs::write(file, b"meeting notes")?;
+        }
+
+        let organizer = organizer(&Config::default(), documents_categorizer(&documents));
+        assert_eq!(organizer.organize_files(&files, Some(3)), (3, true));
+        assert_eq!(fs::read_dir(&documents)?.count(), 3);
+        assert_eq!(fs::read_dir(&downloads)?.count(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_reporter_counts_outcomes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let status_dir = temp_dir.path().join("status");
+        let file = temp_dir.path().join("report.txt");
+        fs::write(&file, b"quarterly numbers")?;
+
+        let status = Arc::new(StatusReporter::new(&status_dir, temp_dir.path(), "default"));
+        let organizer = organizer(&Config::default(), documents_categorizer(&documents))
+            .with_status_reporter(status.clone());
+        organizer.organize_file(&file)?;
+        organizer.organize_file(&temp_dir.path().join("missing.txt"))?;
+        status.write()?;
+
+        let statuses = crate::status::read_statuses(&status_dir)?;
+        assert_eq!(statuses[0].counts.moved, 1);
+        assert_eq!(statuses[0].counts.skipped, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeatedly_failing_file_is_quarantined() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let quarantine_dir = temp_dir.path().join("Quarantine");
+        let file = temp_dir.path().join("stuck.txt");
+        fs::write(&file, b"cannot be moved")?;
+
+        // A regular file where the destination folder should be makes every move fail
+        let blocker = temp_dir.path().join("Documents");
+        fs::write(&blocker, b"")?;
+
+        let config = Config {
+            quarantine: Some(QuarantineConfig {
+                destination: quarantine_dir.clone(),
+                max_failures: 3,
+            }),
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&blocker));
+
+        assert!(organizer.organize_file(&file).is_err());
+        assert!(organizer.organize_file(&file).is_err());
+        assert!(file.exists());
+
+        let quarantined = quarantine_dir.join("stuck.txt");
+        assert_eq!(
+            organizer.organize_file(&file)?,
+            OrganizeOutcome::Quarantined(quarantined.clone())
+        );
+        assert!(!file.exists());
+        assert!(quarantined.exists());
+        assert!(fs::read_to_string(quarantine_dir.join("stuck.txt.error"))?.contains("Error:"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_safe_mode_never_quarantines() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let quarantine_dir = temp_dir.path().join("Quarantine");
+        let file = temp_dir.path().join("stuck.txt");
+        fs::write(&file, b"cannot be copied")?;
+        let blocker = temp_dir.path().join("Documents");
+        fs::write(&blocker, b"")?;
+
+        let config = Config {
+            safe_mode: true,
+            quarantine: Some(QuarantineConfig {
+                destination: quarantine_dir.clone(),
+                max_failures: 2,
+            }),
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&blocker));
+        for _ in 0..3 {
+            assert!(organizer.organize_file(&file).is_err());
+        }
+        assert!(file.exists());
+        assert!(!quarantine_dir.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_destination_roots_include_quarantine_and_bundles() {
+        let temp_dir = TempDir::new().unwrap();
+        let documents = temp_dir.path().join("Documents");
+        let quarantine_dir = temp_dir.path().join("Quarantine");
+        let applications = temp_dir.path().join("Applications");
+        let config = Config {
+            quarantine: Some(QuarantineConfig {
+                destination: quarantine_dir.clone(),
+                max_failures: 3,
+            }),
+            bundle_destination: Some(applications.clone()),
+            ..Default::default()
+        };
+        let roots = organizer(&config, documents_categorizer(&documents)).destination_roots();
+        for root in [documents, quarantine_dir, applications] {
+            assert!(roots.contains(&root), "{:?} missing from {:?}", root, roots);
+        }
+    }
+
+    #[test]
+    fn test_identical_files_are_not_imported_twice() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let documents = temp_dir.path().join("Documents");
+        let config = Config {
+            dedup: Some(DedupConfig {
+                trash_duplicates: false,
+                max_entries: 100,
+                index_path: Some(temp_dir.path().join("hashes.json")),
+            }),
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents));
+
+        let original = temp_dir.path().join("report.txt");
+        fs::write(&original, b"quarterly numbers")?;
+        organizer.organize_file(&original)?;
+
+        // Same bytes under another name: recognized and left in place
+        let copy = temp_dir.path().join("report (copy).txt");
+        fs::write(&copy, b"quarterly numbers")?;
+        assert_eq!(
+            organizer.organize_file(&copy)?,
+            OrganizeOutcome::Duplicate(documents.join("report.txt"))
+        );
+        assert!(copy.exists());
+
+        // Same name, one byte different: organized as a separate file
+        let revised = temp_dir.path().join("report.txt");
+        fs::write(&revised, b"quarterly numberz")?;
+        assert_eq!(
+            organizer.organize_file(&revised)?,
+            OrganizeOutcome::Moved(documents.join("report (1).txt"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_without_semantic_matching_files_stay_top_level() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let invoices = temp_dir.path().join("Invoices");
+        fs::create_dir_all(invoices.join("Acme"))?;
+        let config = Config {
+            semantic_matching: false,
+            categories: vec![CustomCategory {
+                name: "Invoices".to_string(),
+                destination: invoices.clone(),
+                matcher: CategoryMatch {
+                    extensions: vec!["pdf".to_string()],
+                    ..Default::default()
+                },
+            }],
+            ..Default::default()
+        };
+
+        // Builds without loading the model
+        let organizer = FileOrganizer::for_explaining(&config)?;
+        assert!(organizer.matcher.is_none());
+
+        let file = temp_dir.path().join("acme_invoice.pdf");
+        fs::write(&file, b"content")?;
+        assert_eq!(
+            organizer.organize_file(&file)?,
+            OrganizeOutcome::Moved(invoices.join("acme_invoice.pdf"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_emptied_source_folders_are_removed() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let documents = temp_dir.path().join("Documents");
+        let nested = downloads.join("export").join("pages");
+        fs::create_dir_all(&nested)?;
+        let untouched = downloads.join("empty");
+        fs::create_dir_all(&untouched)?;
+        let (first, last) = (nested.join("one.txt"), downloads.join("export").join("two.txt"));
+        fs::write(&first, b"one")?;
+        fs::write(&last, b"two")?;
+
+        let config = Config {
+            remove_empty_source_dirs: true,
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents))
+            .with_watch_root(downloads.clone());
+
+        // "pages" is emptied and removed; "export" still holds a file
+        organizer.organize_file(&first)?;
+        assert!(!nested.exists());
+        assert!(downloads.join("export").exists());
+
+        // Organizing the last file removes "export", but never the watch root or
+        // the folder that was empty to begin with
+        organizer.organize_file(&last)?;
+        assert!(!downloads.join("export").exists());
+        assert!(downloads.exists());
+        assert!(untouched.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_safe_mode_copies_and_never_replaces() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let documents = temp_dir.path().join("Documents");
+        fs::create_dir_all(&downloads)?;
+        fs::create_dir_all(&documents)?;
+        let file = downloads.join("notes.txt");
+        fs::write(&file, b"new notes")?;
+        fs::write(documents.join("notes.txt"), b"old notes")?;
+
+        let config = Config {
+            safe_mode: true,
+            conflict_strategy: ConflictStrategy::Overwrite,
+            ..Default::default()
+        };
+        let organizer = organizer(&config, documents_categorizer(&documents));
+
+        // The existing file is kept and the new one copied next to it
+        let copy = documents.join("notes (1).txt");
+        let outcome = organizer.organize_file(&file)?;
+        let copied = matches!(&outcome, OrganizeOutcome::Moved(path) if *path == copy);
+        assert!(copied, "{:?}", outcome);
+        assert_eq!(fs::read(&file)?, b"new notes");
+        assert_eq!(fs::read(&copy)?, b"new notes");
+        assert_eq!(fs::read(documents.join("notes.txt"))?, b"old notes");
+
+        // Once copied, organizing the source again doesn't make another copy
+        let again = organizer.organize_file(&file)?;
+        assert!(matches!(again, OrganizeOutcome::Skipped), "{:?}", again);
+        assert!(file.exists());
+        assert!(!documents.join("notes (2).txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_safe_mode_keeps_heic_originals() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let pictures = temp_dir.path().join("Pictures");
+        fs::create_dir_all(&downloads)?;
+        let rules = HashMap::from([(
+            FileCategory::Image,
+            CategoryRule {
+                name: "Images".to_string(),
+                destination: pictures.clone(),
+            },
+        )]);
+        // Stands in for sips or ImageMagick, which may not be installed
+        let write_png = |_: &Path, output: &Path| -> Result<()> {
+            fs::write(output, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+            Ok(())
+        };
+        let converter = HeicConverter::new()
+            .with_keep_original(true)
+            .with_converter(write_png);
+        let config = Config {
+            safe_mode: true,
+            ..Default::default()
+        };
+        let pipeline = PreprocessorPipeline::with_preprocessors(vec![Box::new(converter)]);
+        let organizer = organizer(&config, Categorizer::_with_custom_rules(rules))
+            .with_preprocessors(pipeline);
+
+        let photo = downloads.join("IMG_0001.heic");
+        fs::write(&photo, b"\0\0\0\x18ftypheic\0\0\0\0mif1heic")?;
+        assert_eq!(
+            organizer.organize_file(&photo)?,
+            OrganizeOutcome::Moved(pictures.join("IMG_0001.png"))
+        );
+        assert!(photo.exists());
+        assert!(pictures.join("IMG_0001.png").exists());
+
+        // Organizing the original again converts it again, but doesn't add a copy
+        assert_eq!(organizer.organize_file(&photo)?, OrganizeOutcome::Skipped);
+        assert!(photo.exists());
+        assert!(!pictures.join("IMG_0001 (1).png").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_context_from_nested_folders() {
+        let root = Path::new("/downloads");
+        let config = Config {
+            use_source_path_context: true,
+            ..Default::default()
+        };
+        let categorizer = || Categorizer::_with_custom_rules(HashMap::new());
+        let enabled = organizer(&config, categorizer()).with_watch_root(root.to_path_buf());
+
+        assert_eq!(
+            enabled.source_context(&root.join("Work/ClientA/notes.pdf")).as_deref(),
+            Some("Work ClientA")
+        );
+        // Files in the watch root itself keep the plain behaviour
+        assert_eq!(enabled.source_context(&root.join("notes.pdf")), None);
+
+        let disabled = organizer(&Config::default(), categorizer())
+            .with_watch_root(root.to_path_buf());
+        assert_eq!(disabled.source_context(&root.join("ClientA/notes.pdf")), None);
+    }
+}
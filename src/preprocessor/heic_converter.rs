@@ -1,14 +1,43 @@
-use super::Preprocessor;
+use super::{PreprocessPhase, Preprocessor};
+use crate::utils::file::normalized_extension;
 use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// Writes a PNG conversion of the HEIC file at the first path to the second
+type ConvertFn = fn(&Path, &Path) -> Result<()>;
+
 /// Preprocessor that converts HEIC/HEIF images to PNG format
-pub struct HeicConverter;
+pub struct HeicConverter {
+    keep_original: bool,
+    /// Replaces sips or ImageMagick, e.g. in tests
+    convert: Option<ConvertFn>,
+}
 
 impl HeicConverter {
     pub fn new() -> Self {
-        Self
+        Self {
+            keep_original: false,
+            convert: None,
+        }
+    }
+
+    /// Convert with `convert` instead of the installed tools
+    #[cfg(test)]
+    pub(crate) fn with_converter(mut self, convert: ConvertFn) -> Self {
+        self.convert = Some(convert);
+        self
+    }
+
+    /// Leave the HEIC file next to its PNG conversion instead of deleting it
+    pub fn with_keep_original(mut self, keep_original: bool) -> Self {
+        self.keep_original = keep_original;
+        self
+    }
+
+    /// Whether the file has a HEIC/HEIF extension, in any case
+    fn is_heic(path: &Path) -> bool {
+        matches!(normalized_extension(path).as_str(), "heic" | "heif")
     }
 
     /// Check if the conversion tools are available
@@ -22,20 +51,34 @@ impl HeicConverter {
                 .is_ok()
         }
 
-        // Check for ImageMagick convert (cross-platform)
+        // Check for ImageMagick (cross-platform)
         #[cfg(not(target_os = "macos"))]
         {
-            Command::new("convert")
-                .arg("-version")
-                .output()
-                .is_ok()
+            Self::imagemagick_command().is_some()
         }
     }
 
+    /// The ImageMagick program to run, if one is installed (probed once per run)
+    #[cfg(not(target_os = "macos"))]
+    fn imagemagick_command() -> Option<&'static str> {
+        static COMMAND: std::sync::OnceLock<Option<&'static str>> = std::sync::OnceLock::new();
+        *COMMAND.get_or_init(|| select_imagemagick(IMAGEMAGICK_CANDIDATES, is_imagemagick))
+    }
+
     /// Convert HEIC to PNG using available tools
     fn convert_heic(&self, source: &Path) -> Result<PathBuf> {
         let output_path = source.with_extension("png");
+        let convert = self.convert.unwrap_or(Self::run_tools);
+        convert(source, &output_path)?;
+
+        self.discard_original(source)?;
+        log::info!("Converted HEIC to PNG: {:?} -> {:?}", source, output_path);
 
+        Ok(output_path)
+    }
+
+    /// Convert `source` to the PNG `output_path` with sips or ImageMagick
+    fn run_tools(source: &Path, output_path: &Path) -> Result<()> {
         #[cfg(target_os = "macos")]
         {
             // Use sips on macOS (built-in, no dependencies)
@@ -45,7 +88,7 @@ impl HeicConverter {
                 .arg("png")
                 .arg(source)
                 .arg("--out")
-                .arg(&output_path)
+                .arg(output_path)
                 .status()
                 .context("Failed to execute sips command")?;
 
@@ -56,26 +99,56 @@ impl HeicConverter {
 
         #[cfg(not(target_os = "macos"))]
         {
-            // Use ImageMagick convert on other platforms
-            let status = Command::new("convert")
+            // Use ImageMagick on other platforms
+            let program = Self::imagemagick_command().context("ImageMagick is not installed")?;
+            let status = Command::new(program)
                 .arg(source)
-                .arg(&output_path)
+                .arg(output_path)
                 .status()
-                .context("Failed to execute convert command")?;
+                .context(format!("Failed to execute {} command", program))?;
 
             if !status.success() {
-                anyhow::bail!("convert command failed with status: {}", status);
+                anyhow::bail!("{} command failed with status: {}", program, status);
             }
         }
+        Ok(())
+    }
+
+    /// Delete the original HEIC file after a successful conversion, unless it is kept
+    fn discard_original(&self, source: &Path) -> Result<()> {
+        if self.keep_original {
+            log::debug!("Keeping original {:?}", source);
+            return Ok(());
+        }
+        std::fs::remove_file(source).context("Failed to remove original HEIC file")
+    }
+}
 
-        // Delete original HEIC file after successful conversion
-        std::fs::remove_file(source)
-            .context("Failed to remove original HEIC file")?;
+/// ImageMagick programs to try, in order. ImageMagick 7 installs `magick`; 6 only
+/// has `convert`, which on Windows is the system disk conversion tool and must
+/// never be run.
+#[cfg(windows)]
+const IMAGEMAGICK_CANDIDATES: &[&str] = &["magick"];
+#[cfg(all(not(windows), not(target_os = "macos")))]
+const IMAGEMAGICK_CANDIDATES: &[&str] = &["magick", "convert"];
 
-        log::info!("Converted HEIC to PNG: {:?} -> {:?}", source, output_path);
+/// First candidate that `is_imagemagick` confirms really is ImageMagick
+#[cfg(any(test, not(target_os = "macos")))]
+fn select_imagemagick(
+    candidates: &[&'static str],
+    is_imagemagick: impl Fn(&str) -> bool,
+) -> Option<&'static str> {
+    candidates.iter().copied().find(|program| is_imagemagick(program))
+}
 
-        Ok(output_path)
-    }
+/// Whether `program -version` identifies itself as ImageMagick
+#[cfg(not(target_os = "macos"))]
+fn is_imagemagick(program: &str) -> bool {
+    Command::new(program)
+        .arg("-version")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("ImageMagick"))
+        .unwrap_or(false)
 }
 
 impl Preprocessor for HeicConverter {
@@ -84,15 +157,46 @@ impl Preprocessor for HeicConverter {
     }
 
     fn should_process(&self, path: &Path) -> bool {
-        if let Some(ext) = path.extension() {
-            let ext_str = ext.to_str().unwrap_or("").to_lowercase();
-            (ext_str == "heic" || ext_str == "heif") && Self::check_tools_available()
-        } else {
-            false
-        }
+        Self::is_heic(path) && (self.convert.is_some() || Self::check_tools_available())
+    }
+
+    /// HEIC is already detected as an image, so only files being moved are converted
+    fn phase(&self) -> PreprocessPhase {
+        PreprocessPhase::PostDetection
     }
 
     fn process(&self, path: &Path) -> Result<PathBuf> {
         self.convert_heic(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heic_extension_any_case() {
+        assert!(HeicConverter::is_heic(Path::new("IMG_0001.HEIC")));
+        assert!(HeicConverter::is_heic(Path::new("IMG_0001.Heic")));
+        assert!(HeicConverter::is_heic(Path::new("burst.heif")));
+        assert!(!HeicConverter::is_heic(Path::new("IMG_0001.png")));
+        assert!(!HeicConverter::is_heic(Path::new("heic")));
+    }
+
+    #[test]
+    fn test_imagemagick_selection_skips_impostors() {
+        // Only `convert` is ImageMagick, e.g. an ImageMagick 6 install
+        let only_convert = |program: &str| program == "convert";
+        assert_eq!(select_imagemagick(&["magick", "convert"], only_convert), Some("convert"));
+        assert_eq!(select_imagemagick(&["magick", "convert"], |_| false), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_never_runs_convert() {
+        assert_eq!(IMAGEMAGICK_CANDIDATES, &["magick"]);
+        // Even when `convert` would answer, only `magick` is considered
+        assert_eq!(select_imagemagick(IMAGEMAGICK_CANDIDATES, |p| p == "convert"), None);
+        assert_eq!(select_imagemagick(IMAGEMAGICK_CANDIDATES, |_| true), Some("magick"));
+    }
+}
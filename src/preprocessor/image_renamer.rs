@@ -1,20 +1,34 @@
-use super::Preprocessor;
+use super::{PreprocessPhase, Preprocessor};
+use crate::config::{ImageRenamerConfig, MatcherConfig};
+use crate::dedup::hash_file;
+use crate::matcher::is_opaque_stem;
+use crate::utils::file::normalized_extension;
 use anyhow::{Context, Result};
+use image::ImageDecoder;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::fs;
 use base64::{engine::general_purpose, Engine as _};
 
-/// Preprocessor that renames images using AI-generated descriptions
+/// Length of the content hash used in template names
+const SHORT_HASH_LEN: usize = 8;
+
+/// Preprocessor that renames images with generic names
 ///
-/// Uses a lightweight vision model API to:
+/// Photos with EXIF metadata are described by a lightweight vision model API:
 /// 1. Analyze the image content
 /// 2. Generate a descriptive caption
 /// 3. Rename the file based on the caption
 ///
 /// Example: IMG_1234.jpg -> sunset_over_mountains_2024.jpg
+///
+/// Images without metadata, typically screenshots, are named from their
+/// dimensions and a short content hash instead: 1920x1080_a3f9c2d1.png
 pub struct ImageRenamer {
     enabled: bool,
     client: reqwest::blocking::Client,
+    name_template: String,
+    boilerplate_words: HashSet<String>,
 }
 
 impl ImageRenamer {
@@ -26,24 +40,34 @@ impl ImageRenamer {
                 .timeout(std::time::Duration::from_secs(30))
                 .build()
                 .unwrap_or_else(|_| reqwest::blocking::Client::new()),
+            name_template: ImageRenamerConfig::default().name_template,
+            boilerplate_words: HashSet::new(),
         }
+        .with_boilerplate_words(&MatcherConfig::default().boilerplate_words)
+    }
+
+    /// Name template for images without metadata; see `ImageRenamerConfig`
+    pub fn with_name_template(mut self, name_template: String) -> Self {
+        self.name_template = name_template;
+        self
+    }
+
+    /// Words that, together with numbers and hashes, make up a generic name
+    pub fn with_boilerplate_words(mut self, words: &[String]) -> Self {
+        self.boilerplate_words = words.iter().map(|word| word.to_lowercase()).collect();
+        self
     }
 
     /// Check if the file is an image that should be renamed
     fn is_renameable_image(&self, path: &Path) -> bool {
-        if let Some(ext) = path.extension() {
-            let ext_str = ext.to_str().unwrap_or("").to_lowercase();
-            matches!(
-                ext_str.as_str(),
-                "jpg" | "jpeg" | "png" | "webp" | "bmp" | "gif"
-            )
-        } else {
-            false
-        }
+        matches!(
+            normalized_extension(path).as_str(),
+            "jpg" | "jpeg" | "png" | "webp" | "bmp" | "gif"
+        )
     }
 
-    /// Check if the filename looks like it was auto-generated by a camera
-    /// Examples: IMG_1234.jpg, DSC_5678.png, 20231215_143022.jpg
+    /// Check if the filename looks like it was auto-generated by a camera or app
+    /// Examples: IMG_1234.jpg, DSC_5678.png, 20231215_143022.jpg, "Screenshot 2024-01-02 at 10.11.12.png"
     fn has_generic_name(&self, path: &Path) -> bool {
         if let Some(stem) = path.file_stem() {
             let stem_str = stem.to_str().unwrap_or("");
@@ -55,6 +79,7 @@ impl ImageRenamer {
                 stem_str.starts_with("DCIM_"),
                 stem_str.starts_with("PXL_"),
                 stem_str.chars().all(|c| c.is_ascii_digit() || c == '_'),
+                is_opaque_stem(stem_str, &self.boilerplate_words),
             ];
 
             patterns.iter().any(|&p| p)
@@ -135,26 +160,45 @@ impl ImageRenamer {
             .collect()
     }
 
+    /// Fill in the name template for an image of the given size and content hash
+    fn template_name(&self, (width, height): (u32, u32), hash: &str) -> String {
+        self.name_template
+            .replace("{width}", &width.to_string())
+            .replace("{height}", &height.to_string())
+            .replace("{hash}", &hash[..SHORT_HASH_LEN.min(hash.len())])
+    }
+
     /// Rename the image file with a descriptive name
     fn rename_image(&self, path: &Path) -> Result<PathBuf> {
-        // Generate descriptive name from image content
-        let description = self.generate_descriptive_name(path)?;
-        let sanitized = self.sanitize_filename(&description);
-
-        // Add timestamp to avoid conflicts
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let (dimensions, has_exif) = read_image_info(path)?;
+
+        let name = if has_exif {
+            // Generate descriptive name from image content
+            let description = self.generate_descriptive_name(path)?;
+            let sanitized = self.sanitize_filename(&description);
+
+            // Add timestamp to avoid conflicts
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            format!("{}_{}", sanitized, timestamp)
+        } else {
+            // The content hash keeps names of different images apart
+            self.template_name(dimensions, &hash_file(path)?)
+        };
 
         // Construct new filename
         let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("jpg");
-        let new_filename = format!("{}_{}.{}", sanitized, timestamp, extension);
+        let new_filename = format!("{}.{}", name, extension);
 
         // Build new path in the same directory
         let new_path = path.parent()
             .ok_or_else(|| anyhow::anyhow!("Could not get parent directory"))?
             .join(new_filename);
+        if new_path.exists() {
+            anyhow::bail!("{:?} already exists", new_path);
+        }
 
         // Rename the file
         std::fs::rename(path, &new_path)
@@ -166,6 +210,18 @@ impl ImageRenamer {
     }
 }
 
+/// Pixel dimensions of an image and whether it carries EXIF metadata
+fn read_image_info(path: &Path) -> Result<((u32, u32), bool)> {
+    let mut decoder = image::ImageReader::open(path)
+        .context("Failed to open image")?
+        .with_guessed_format()
+        .context("Failed to read image")?
+        .into_decoder()
+        .context("Failed to decode image")?;
+    let has_exif = decoder.exif_metadata().ok().flatten().is_some_and(|exif| !exif.is_empty());
+    Ok((decoder.dimensions(), has_exif))
+}
+
 impl Preprocessor for ImageRenamer {
     fn name(&self) -> &str {
         "AI Image Renamer"
@@ -177,12 +233,17 @@ impl Preprocessor for ImageRenamer {
             && self.has_generic_name(path)
     }
 
+    /// Asking the vision model is slow; only worth it for images being moved
+    fn phase(&self) -> PreprocessPhase {
+        PreprocessPhase::PostDetection
+    }
+
     fn process(&self, path: &Path) -> Result<PathBuf> {
         // If renaming is disabled or fails, just return original path
         match self.rename_image(path) {
             Ok(new_path) => Ok(new_path),
             Err(e) => {
-                log::debug!("Image renaming failed (expected if not implemented yet): {}", e);
+                log::debug!("Image renaming failed, keeping the original name: {}", e);
                 Ok(path.to_path_buf())
             }
         }
@@ -219,4 +280,30 @@ mod tests {
             "cat_playing_with_yarn"
         );
     }
+
+    #[test]
+    fn test_images_without_metadata_use_name_template() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let screenshot = temp_dir.path().join("Screenshot 2024-01-02 at 10.11.12.png");
+        image::RgbImage::new(3, 2).save(&screenshot)?;
+        let named = temp_dir.path().join("sunset.png");
+        image::RgbImage::new(3, 2).save(&named)?;
+
+        let renamer = ImageRenamer::new();
+        assert!(renamer.should_process(&screenshot));
+        assert!(!renamer.should_process(&named));
+
+        let renamed = renamer.process(&screenshot)?;
+        let hash = hash_file(&renamed)?;
+        assert_eq!(renamed, temp_dir.path().join(format!("3x2_{}.png", &hash[..8])));
+        assert!(!screenshot.exists());
+
+        let renamer = ImageRenamer::new().with_name_template("screen_{hash}_{width}w".to_string());
+        let other = temp_dir.path().join("IMG_0001.png");
+        image::RgbImage::new(5, 4).save(&other)?;
+        let renamed = renamer.process(&other)?;
+        let hash = hash_file(&renamed)?;
+        assert_eq!(renamed, temp_dir.path().join(format!("screen_{}_5w.png", &hash[..8])));
+        Ok(())
+    }
 }
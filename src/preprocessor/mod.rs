@@ -1,20 +1,40 @@
 mod heic_converter;
 mod image_renamer;
 
+use crate::config::Config;
 use anyhow::Result;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 pub use heic_converter::HeicConverter;
 pub use image_renamer::ImageRenamer;
 
+/// When a preprocessor runs relative to category detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreprocessPhase {
+    /// On every file, before its category and destination are decided
+    PreDetection,
+    /// Only on files that will be moved; the file is routed again if it changes
+    PostDetection,
+}
+
 /// Trait for file preprocessors that transform files before organization
 pub trait Preprocessor: Send + Sync {
     /// Returns the name of this preprocessor
     fn name(&self) -> &str;
 
     /// Check if this preprocessor should handle the given file
+    /// Should be cheap and only look at the path; files a preprocessor produced
+    /// are never offered to the pipeline again, so no need to detect those
     fn should_process(&self, path: &Path) -> bool;
 
+    /// When to run; expensive preprocessors should run after detection so files
+    /// that end up skipped aren't processed for nothing
+    fn phase(&self) -> PreprocessPhase {
+        PreprocessPhase::PreDetection
+    }
+
     /// Process the file and return the new path (or original if unchanged)
     /// The original file may be deleted/replaced depending on the preprocessor
     fn process(&self, path: &Path) -> Result<PathBuf>;
@@ -23,21 +43,33 @@ pub trait Preprocessor: Send + Sync {
 /// Manages multiple preprocessors and applies them in order
 pub struct PreprocessorPipeline {
     preprocessors: Vec<Box<dyn Preprocessor>>,
+    /// Files created by a preprocessor, which are passed through untouched the
+    /// next time they come back (e.g. a converted PNG picked up by the watcher)
+    produced: Mutex<HashSet<PathBuf>>,
 }
 
 impl PreprocessorPipeline {
     /// Create a new preprocessing pipeline with default preprocessors
     pub fn new() -> Self {
-        let mut preprocessors: Vec<Box<dyn Preprocessor>> = Vec::new();
-
-        // Add default preprocessors here
-        // Order matters: preprocessors run in the order they are added
+        Self::from_config(&Config::default())
+    }
 
-        // 1. Image renaming (before format conversion)
-        preprocessors.push(Box::new(ImageRenamer::new()));
+    /// Create the default preprocessors, configured from `config`
+    pub fn from_config(config: &Config) -> Self {
+        let image_renamer = ImageRenamer::new()
+            .with_name_template(config.image_renamer.name_template.clone())
+            .with_boilerplate_words(&config.matcher.boilerplate_words);
 
-        // 2. Format conversion (HEIC to PNG, etc.)
-        preprocessors.push(Box::new(HeicConverter::new()));
+        // Add default preprocessors here
+        // Order matters: preprocessors run in the order they are listed
+        let mut preprocessors: Vec<Box<dyn Preprocessor>> = Vec::new();
+        // 1. Image renaming (before format conversion); safe mode leaves the names
+        //    of originals alone
+        if !config.safe_mode {
+            preprocessors.push(Box::new(image_renamer));
+        }
+        // 2. Format conversion (HEIC to PNG, etc.), keeping originals in safe mode
+        preprocessors.push(Box::new(HeicConverter::new().with_keep_original(config.safe_mode)));
 
         log::info!(
             "Initialized preprocessing pipeline with {} preprocessor(s)",
@@ -47,16 +79,51 @@ impl PreprocessorPipeline {
             log::info!("  - {}", preprocessor.name());
         }
 
-        Self { preprocessors }
+        Self::with_preprocessors(preprocessors)
+    }
+
+    /// Create a pipeline running the given preprocessors in order
+    pub fn with_preprocessors(preprocessors: Vec<Box<dyn Preprocessor>>) -> Self {
+        Self {
+            preprocessors,
+            produced: Mutex::new(HashSet::new()),
+        }
     }
 
-    /// Process a file through all applicable preprocessors
+    /// Process a file through all applicable preprocessors, regardless of phase
     /// Returns the final path after all preprocessing
     pub fn process(&self, path: &Path) -> Result<PathBuf> {
+        if self.take_produced(path) {
+            return Ok(path.to_path_buf());
+        }
+        let path = self.process_phase(path, PreprocessPhase::PreDetection)?;
+        self.process_phase(&path, PreprocessPhase::PostDetection)
+    }
+
+    /// Whether `path` was created by this pipeline and should pass through untouched;
+    /// it is only passed through once
+    pub fn take_produced(&self, path: &Path) -> bool {
+        let produced = self.produced.lock().unwrap().remove(path);
+        if produced {
+            log::debug!("Already preprocessed, leaving as is: {:?}", path);
+        }
+        produced
+    }
+
+    /// Whether any preprocessor of `phase` would process `path`
+    pub fn would_process(&self, path: &Path, phase: PreprocessPhase) -> bool {
+        self.preprocessors
+            .iter()
+            .any(|preprocessor| preprocessor.phase() == phase && preprocessor.should_process(path))
+    }
+
+    /// Process a file through the applicable preprocessors of `phase`
+    /// Doesn't check `take_produced` first; `process` does.
+    pub fn process_phase(&self, path: &Path, phase: PreprocessPhase) -> Result<PathBuf> {
         let mut current_path = path.to_path_buf();
 
         for preprocessor in &self.preprocessors {
-            if preprocessor.should_process(&current_path) {
+            if preprocessor.phase() == phase && preprocessor.should_process(&current_path) {
                 log::info!(
                     "Applying preprocessor '{}' to {:?}",
                     preprocessor.name(),
@@ -67,6 +134,13 @@ impl PreprocessorPipeline {
             }
         }
 
+        if current_path != path {
+            let mut produced = self.produced.lock().unwrap();
+            // Forget outputs that have since been organized away
+            produced.retain(|produced_path| produced_path.exists());
+            produced.insert(current_path.clone());
+        }
+
         Ok(current_path)
     }
 }
@@ -76,3 +150,114 @@ impl Default for PreprocessorPipeline {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Stand-in for `HeicConverter` that renames `.heic` to `.png`
+    struct FakeConverter;
+
+    impl Preprocessor for FakeConverter {
+        fn name(&self) -> &str {
+            "Fake converter"
+        }
+
+        fn should_process(&self, path: &Path) -> bool {
+            path.extension().is_some_and(|ext| ext == "heic")
+        }
+
+        fn process(&self, path: &Path) -> Result<PathBuf> {
+            let output = path.with_extension("png");
+            fs::rename(path, &output)?;
+            Ok(output)
+        }
+    }
+
+    /// Counts the PNGs it is asked to process, leaving them unchanged
+    struct CountingRenamer(Arc<AtomicUsize>);
+
+    impl Preprocessor for CountingRenamer {
+        fn name(&self) -> &str {
+            "Counting renamer"
+        }
+
+        fn should_process(&self, path: &Path) -> bool {
+            path.extension().is_some_and(|ext| ext == "png")
+        }
+
+        fn process(&self, path: &Path) -> Result<PathBuf> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(path.to_path_buf())
+        }
+    }
+
+    /// Stand-in for a slow preprocessor that only runs once a file's fate is known
+    struct CountingLateStep(Arc<AtomicUsize>);
+
+    impl Preprocessor for CountingLateStep {
+        fn name(&self) -> &str {
+            "Counting late step"
+        }
+
+        fn should_process(&self, _path: &Path) -> bool {
+            true
+        }
+
+        fn phase(&self) -> PreprocessPhase {
+            PreprocessPhase::PostDetection
+        }
+
+        fn process(&self, path: &Path) -> Result<PathBuf> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(path.to_path_buf())
+        }
+    }
+
+    #[test]
+    fn test_phases_run_separately() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let heic = temp_dir.path().join("IMG_0002.heic");
+        fs::write(&heic, b"heic data")?;
+
+        let late = Arc::new(AtomicUsize::new(0));
+        let pipeline = PreprocessorPipeline::with_preprocessors(vec![
+            Box::new(CountingLateStep(late.clone())),
+            Box::new(FakeConverter),
+        ]);
+
+        let png = pipeline.process_phase(&heic, PreprocessPhase::PreDetection)?;
+        assert_eq!(png, temp_dir.path().join("IMG_0002.png"));
+        assert_eq!(late.load(Ordering::SeqCst), 0);
+
+        pipeline.process_phase(&png, PreprocessPhase::PostDetection)?;
+        assert_eq!(late.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_converted_output_is_not_preprocessed_again() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let heic = temp_dir.path().join("IMG_0001.heic");
+        fs::write(&heic, b"heic data")?;
+
+        let renamed = Arc::new(AtomicUsize::new(0));
+        let pipeline = PreprocessorPipeline::with_preprocessors(vec![
+            Box::new(CountingRenamer(renamed.clone())),
+            Box::new(FakeConverter),
+        ]);
+
+        let png = pipeline.process(&heic)?;
+        assert_eq!(png, temp_dir.path().join("IMG_0001.png"));
+
+        // The watcher reports the converted file as new; it passes straight through
+        assert_eq!(pipeline.process(&png)?, png);
+        assert_eq!(renamed.load(Ordering::SeqCst), 0);
+        assert_eq!(fs::read(&png)?, b"heic data");
+        Ok(())
+    }
+}
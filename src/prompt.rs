@@ -0,0 +1,89 @@
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Answer to an uncertain subfolder match
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchDecision {
+    /// Use the proposed folder
+    Accept,
+    /// Keep the file at the current level instead
+    Reject,
+    /// Use a different candidate folder
+    Choose(PathBuf),
+}
+
+/// Asks the user to confirm low-confidence subfolder matches
+pub trait MatchPrompt: Send + Sync {
+    /// `candidate` is the best match; `alternatives` lists every folder at that level, best first
+    fn confirm(
+        &self,
+        file_stem: &str,
+        candidate: &Path,
+        similarity: f32,
+        alternatives: &[PathBuf],
+    ) -> MatchDecision;
+}
+
+/// Prompts on the terminal, reading the answer from stdin
+pub struct StdinPrompt;
+
+impl MatchPrompt for StdinPrompt {
+    fn confirm(
+        &self,
+        file_stem: &str,
+        candidate: &Path,
+        similarity: f32,
+        alternatives: &[PathBuf],
+    ) -> MatchDecision {
+        println!(
+            "'{}' might belong in {} (similarity {:.3})",
+            file_stem,
+            candidate.display(),
+            similarity
+        );
+        for (i, alternative) in alternatives.iter().enumerate() {
+            println!("  [{}] {}", i + 1, alternative.display());
+        }
+        print!("Move there? [y]es / [n]o / number to choose: ");
+        let _ = io::stdout().flush();
+
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer).is_err() {
+            return MatchDecision::Reject;
+        }
+
+        parse_answer(answer.trim(), alternatives)
+    }
+}
+
+fn parse_answer(answer: &str, alternatives: &[PathBuf]) -> MatchDecision {
+    match answer.to_lowercase().as_str() {
+        "y" | "yes" => MatchDecision::Accept,
+        other => other
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| alternatives.get(i))
+            .map(|path| MatchDecision::Choose(path.clone()))
+            .unwrap_or(MatchDecision::Reject),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answer() {
+        let alternatives = vec![PathBuf::from("/docs/Taxes"), PathBuf::from("/docs/Receipts")];
+
+        assert_eq!(parse_answer("Y", &alternatives), MatchDecision::Accept);
+        assert_eq!(parse_answer("n", &alternatives), MatchDecision::Reject);
+        assert_eq!(parse_answer("", &alternatives), MatchDecision::Reject);
+        assert_eq!(
+            parse_answer("2", &alternatives),
+            MatchDecision::Choose(PathBuf::from("/docs/Receipts"))
+        );
+        assert_eq!(parse_answer("3", &alternatives), MatchDecision::Reject);
+    }
+}
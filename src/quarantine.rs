@@ -0,0 +1,70 @@
+use crate::mover::FileMover;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Size and modification time, used to notice when a failing file has changed
+type FileStamp = (u64, Option<SystemTime>);
+
+/// Counts consecutive organize failures per path
+#[derive(Default)]
+pub struct FailureTracker {
+    failures: Mutex<HashMap<PathBuf, (u32, FileStamp)>>,
+}
+
+impl FailureTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a failure and return how many times in a row this file has failed
+    /// The count starts over if the file changed since its last failure
+    pub fn record_failure(&self, path: &Path) -> u32 {
+        let stamp = file_stamp(path);
+        let mut failures = self.failures.lock().unwrap();
+        let entry = failures.entry(path.to_path_buf()).or_insert((0, stamp));
+
+        if entry.1 != stamp {
+            *entry = (0, stamp);
+        }
+        entry.0 += 1;
+        entry.0
+    }
+
+    /// Forget failures for a path, e.g. after it was organized successfully
+    pub fn clear(&self, path: &Path) {
+        self.failures.lock().unwrap().remove(path);
+    }
+}
+
+fn file_stamp(path: &Path) -> FileStamp {
+    match fs::metadata(path) {
+        Ok(metadata) => (metadata.len(), metadata.modified().ok()),
+        Err(_) => (0, None),
+    }
+}
+
+/// Move a file that keeps failing into the quarantine folder, next to a
+/// `<name>.error` file describing the failure
+pub fn quarantine_file(path: &Path, quarantine_dir: &Path, error: &str) -> Result<PathBuf> {
+    let quarantined = FileMover::move_file(path, quarantine_dir)?;
+
+    fs::write(
+        error_file_path(&quarantined),
+        format!("Original path: {}\nError: {}\n", path.display(), error),
+    )
+    .context("Failed to write quarantine error file")?;
+
+    log::warn!("Quarantined {:?} -> {:?}: {}", path, quarantined, error);
+    Ok(quarantined)
+}
+
+/// Path of the sidecar file describing why `quarantined` was quarantined
+pub fn error_file_path(quarantined: &Path) -> PathBuf {
+    let mut sidecar = quarantined.as_os_str().to_os_string();
+    sidecar.push(".error");
+    PathBuf::from(sidecar)
+}
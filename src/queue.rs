@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, SendError, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Paths sent but not yet picked up for processing
+type Pending = Arc<Mutex<HashSet<PathBuf>>>;
+
+/// Create a queue of files to organize that holds each path at most once
+///
+/// Bursts of watch events for one file would otherwise queue it several times,
+/// and every copy after the first would find the file already moved.
+pub fn file_queue() -> (QueueSender, QueueReceiver) {
+    let (tx, rx) = mpsc::channel();
+    let pending = Pending::default();
+    (
+        QueueSender {
+            tx,
+            pending: pending.clone(),
+        },
+        QueueReceiver { rx, pending },
+    )
+}
+
+#[derive(Clone)]
+pub struct QueueSender {
+    tx: Sender<PathBuf>,
+    pending: Pending,
+}
+
+impl QueueSender {
+    /// Queue `path` unless it is already waiting; returns whether it was queued
+    pub fn send(&self, path: PathBuf) -> Result<bool, SendError<PathBuf>> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.contains(&path) {
+            log::debug!("Already queued: {:?}", path);
+            return Ok(false);
+        }
+
+        self.tx.send(path.clone())?;
+        pending.insert(path);
+        Ok(true)
+    }
+
+    /// Number of paths waiting to be picked up
+    pub fn pending_len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+}
+
+pub struct QueueReceiver {
+    rx: Receiver<PathBuf>,
+    pending: Pending,
+}
+
+impl QueueReceiver {
+    /// Take the next queued path without waiting, if any
+    /// Once taken, the path may be queued again.
+    pub fn try_recv(&self) -> Option<PathBuf> {
+        let path = self.rx.try_recv().ok()?;
+        self.pending.lock().unwrap().remove(&path);
+        Some(path)
+    }
+
+    /// Take every path queued so far without waiting
+    pub fn try_iter(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        std::iter::from_fn(|| self.try_recv())
+    }
+}
+
+/// Blocks for each path until every sender is dropped
+impl Iterator for QueueReceiver {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        let path = self.rx.recv().ok()?;
+        self.pending.lock().unwrap().remove(&path);
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_path_is_queued_once() {
+        let (tx, rx) = file_queue();
+        let path = PathBuf::from("/tmp/report.pdf");
+
+        assert!(tx.send(path.clone()).unwrap());
+        assert!(!tx.send(path.clone()).unwrap());
+        assert!(!tx.send(path.clone()).unwrap());
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![path.clone()]);
+
+        // Once processing has started the path can be queued again (e.g. a retry)
+        assert!(tx.send(path.clone()).unwrap());
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a path AutoFile moved a file to is ignored by the watcher
+const DEFAULT_IGNORE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Short-lived record of destination paths AutoFile has just moved files to
+///
+/// Shared between the organizer (which records moves) and the watcher (which
+/// ignores events for recorded paths), so a destination inside the watched tree
+/// doesn't cause AutoFile to reprocess its own output.
+#[derive(Clone)]
+pub struct RecentMoves {
+    entries: Arc<Mutex<HashMap<PathBuf, Instant>>>,
+    window: Duration,
+}
+
+impl RecentMoves {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            window,
+        }
+    }
+
+    /// Remember that a file was just moved to `path`
+    pub fn record(&self, path: &Path) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(path.to_path_buf(), Instant::now());
+    }
+
+    /// Check whether `path` was moved to within the ignore window
+    /// Expired entries are pruned as a side effect
+    pub fn contains(&self, path: &Path) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let window = self.window;
+        entries.retain(|_, moved_at| moved_at.elapsed() < window);
+        entries.contains_key(path)
+    }
+}
+
+impl Default for RecentMoves {
+    fn default() -> Self {
+        Self::new(DEFAULT_IGNORE_WINDOW)
+    }
+}
+
+/// How long the folder of an organized video is remembered for its subtitles
+const DEFAULT_PAIRING_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Folders recently organized files were moved to, keyed by their file stem
+///
+/// Lets a subtitle downloaded after its video be filed next to it.
+pub struct RecentStems {
+    entries: Mutex<HashMap<String, (PathBuf, Instant)>>,
+    window: Duration,
+}
+
+impl RecentStems {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            window,
+        }
+    }
+
+    /// Remember that a file named `stem` was just moved into `folder`
+    pub fn record(&self, stem: &str, folder: &Path) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(stem.to_lowercase(), (folder.to_path_buf(), Instant::now()));
+    }
+
+    /// Folder a file named `stem` (case-insensitive) was moved into within the window
+    /// Expired entries are pruned as a side effect
+    pub fn folder_for(&self, stem: &str) -> Option<PathBuf> {
+        let mut entries = self.entries.lock().unwrap();
+        let window = self.window;
+        entries.retain(|_, (_, moved_at)| moved_at.elapsed() < window);
+        entries.get(&stem.to_lowercase()).map(|(folder, _)| folder.clone())
+    }
+}
+
+impl Default for RecentStems {
+    fn default() -> Self {
+        Self::new(DEFAULT_PAIRING_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_expire_after_window() {
+        let recent = RecentMoves::new(Duration::from_millis(50));
+        let path = PathBuf::from("/tmp/moved.txt");
+
+        recent.record(&path);
+        assert!(recent.contains(&path));
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(!recent.contains(&path));
+    }
+}
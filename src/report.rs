@@ -0,0 +1,143 @@
+use crate::mover::ConflictResolution;
+use crate::organizer::{Explanation, FileOrganizer};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What organizing one file would do, as listed in a dry-run report
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedAction {
+    pub source: PathBuf,
+    /// `None` when the file couldn't be examined
+    pub category: Option<String>,
+    /// Final path including any numbered conflict suffix; `None` if the file would stay
+    pub destination: Option<PathBuf>,
+    /// Whether the destination folder would have to be created
+    pub creates_folder: bool,
+    /// How a file already using the name would be dealt with
+    pub conflict: Option<ConflictResolution>,
+    /// Why the file would be left in place
+    pub skip_reason: Option<String>,
+    pub error: Option<String>,
+}
+
+impl PlannedAction {
+    fn from_explanation(explanation: Explanation) -> Self {
+        let creates_folder = explanation
+            .destination
+            .as_ref()
+            .and_then(|destination| destination.parent())
+            .is_some_and(|folder| !folder.is_dir());
+        Self {
+            source: explanation.file,
+            category: Some(explanation.route.category.name().to_string()),
+            destination: explanation.destination,
+            creates_folder,
+            conflict: explanation.conflict,
+            skip_reason: explanation.skip_reason.map(str::to_string),
+            error: None,
+        }
+    }
+
+    fn failed(source: &Path, error: anyhow::Error) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            category: None,
+            destination: None,
+            creates_folder: false,
+            conflict: None,
+            skip_reason: None,
+            error: Some(format!("{:#}", error)),
+        }
+    }
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> ", self.source.display())?;
+        match (&self.destination, &self.skip_reason, &self.error) {
+            (Some(destination), _, _) => write!(f, "{}", destination.display()),
+            (None, _, Some(error)) => write!(f, "error: {}", error),
+            (None, Some(reason), None) => write!(f, "left in place ({})", reason),
+            (None, None, None) => write!(f, "left in place"),
+        }
+    }
+}
+
+/// Work out what organizing each of `files` would do, without changing anything
+pub fn plan_files(organizer: &FileOrganizer, files: &[PathBuf]) -> Vec<PlannedAction> {
+    files
+        .iter()
+        .map(|file| match organizer.explain_file(file) {
+            Ok(explanation) => PlannedAction::from_explanation(explanation),
+            Err(e) => PlannedAction::failed(file, e),
+        })
+        .collect()
+}
+
+/// Write `actions` to `path` as a JSON array
+pub fn write_report(path: &Path, actions: &[PlannedAction]) -> Result<()> {
+    let json = serde_json::to_string_pretty(actions)?;
+    fs::write(path, json).context(format!("Failed to write report {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::categorizer::{Categorizer, CategoryRule};
+    use crate::config::{Config, MatcherConfig};
+    use crate::detector::FileCategory;
+    use crate::matcher::{Embedder, SubfolderMatcher};
+    use crate::utils::file::list_files;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    struct NullEmbedder;
+
+    impl Embedder for NullEmbedder {
+        fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|_| vec![0.0, 0.0]).collect())
+        }
+    }
+
+    #[test]
+    fn test_report_plans_without_moving() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let downloads = temp_dir.path().join("Downloads");
+        let documents = temp_dir.path().join("Documents");
+        fs::create_dir(&downloads)?;
+        fs::write(downloads.join("notes.txt"), b"meeting notes")?;
+        fs::write(downloads.join("summary.txt"), b"quarterly summary")?;
+
+        let mut rules = HashMap::new();
+        let rule = CategoryRule {
+            name: "Documents".to_string(),
+            destination: documents.clone(),
+        };
+        rules.insert(FileCategory::Document, rule);
+        let matcher =
+            SubfolderMatcher::with_embedder(Box::new(NullEmbedder), &MatcherConfig::default());
+        let categorizer = Categorizer::_with_custom_rules(rules);
+        let organizer = FileOrganizer::from_parts(&Config::default(), categorizer, matcher);
+
+        let files = list_files(&downloads, false)?;
+        let report = temp_dir.path().join("plan.json");
+        write_report(&report, &plan_files(&organizer, &files))?;
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report)?)?;
+        let actions = json.as_array().unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0]["source"], serde_json::json!(downloads.join("notes.txt")));
+        assert_eq!(actions[0]["destination"], serde_json::json!(documents.join("notes.txt")));
+        assert_eq!(actions[0]["category"], "document");
+        assert_eq!(actions[0]["creates_folder"], true);
+        assert_eq!(actions[1]["destination"], serde_json::json!(documents.join("summary.txt")));
+
+        // Nothing was moved and no folder was created
+        assert_eq!(list_files(&downloads, false)?, files);
+        assert!(!documents.exists());
+        Ok(())
+    }
+}
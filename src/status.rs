@@ -0,0 +1,182 @@
+use crate::organizer::OrganizeOutcome;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often a running instance rewrites its status file
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Status files not updated for this long belong to instances that are gone
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// How many files each outcome was reached for since the instance started
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionCounts {
+    pub moved: u64,
+    pub skipped: u64,
+    pub deferred: u64,
+    pub quarantined: u64,
+    pub duplicates: u64,
+    pub failed: u64,
+}
+
+/// What a running instance reports about itself in its status file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub watch_dir: PathBuf,
+    pub profile: String,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    /// Seconds since the Unix epoch
+    pub updated_at: u64,
+    /// Files waiting to be organized
+    pub queue_length: usize,
+    pub counts: SessionCounts,
+}
+
+impl Status {
+    pub fn uptime(&self) -> Duration {
+        Duration::from_secs(self.updated_at.saturating_sub(self.started_at))
+    }
+}
+
+/// Keeps a running instance's status file, `<pid>.json` in the status directory
+pub struct StatusReporter {
+    path: PathBuf,
+    status: Mutex<Status>,
+}
+
+impl StatusReporter {
+    pub fn new(status_dir: &Path, watch_dir: &Path, profile: &str) -> Self {
+        let pid = std::process::id();
+        let now = unix_now();
+        Self {
+            path: status_dir.join(format!("{}.json", pid)),
+            status: Mutex::new(Status {
+                pid,
+                watch_dir: watch_dir.to_path_buf(),
+                profile: profile.to_string(),
+                started_at: now,
+                updated_at: now,
+                queue_length: 0,
+                counts: SessionCounts::default(),
+            }),
+        }
+    }
+
+    /// Count the outcome of organizing one file
+    pub fn record(&self, result: &Result<OrganizeOutcome>) {
+        let mut status = self.status.lock().unwrap();
+        let counts = &mut status.counts;
+        match result {
+            Ok(OrganizeOutcome::Moved(_)) => counts.moved += 1,
+            Ok(OrganizeOutcome::Skipped) => counts.skipped += 1,
+            Ok(OrganizeOutcome::Deferred(_)) => counts.deferred += 1,
+            Ok(OrganizeOutcome::Quarantined(_)) => counts.quarantined += 1,
+            Ok(OrganizeOutcome::Duplicate(_)) => counts.duplicates += 1,
+            Err(_) => counts.failed += 1,
+        }
+    }
+
+    pub fn set_queue_length(&self, queue_length: usize) {
+        self.status.lock().unwrap().queue_length = queue_length;
+    }
+
+    /// Write the current status, replacing the previous file atomically
+    pub fn write(&self) -> Result<()> {
+        let contents = {
+            let mut status = self.status.lock().unwrap();
+            status.updated_at = unix_now();
+            serde_json::to_string_pretty(&*status)?
+        };
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).context("Failed to create status directory")?;
+        }
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, contents).context("Failed to write status file")?;
+        fs::rename(&temp_path, &self.path).context("Failed to replace status file")?;
+        Ok(())
+    }
+}
+
+impl Drop for StatusReporter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Status of every running instance, oldest first
+/// Files left behind by instances that stopped updating them are removed.
+pub fn read_statuses(status_dir: &Path) -> Result<Vec<Status>> {
+    let entries = match fs::read_dir(status_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let now = unix_now();
+    let mut statuses = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+        let Ok(status) = serde_json::from_str::<Status>(&contents) else {
+            log::warn!("Ignoring unreadable status file {:?}", path);
+            continue;
+        };
+
+        if now.saturating_sub(status.updated_at) > STALE_AFTER.as_secs() {
+            log::debug!("Removing stale status file {:?}", path);
+            let _ = fs::remove_file(&path);
+            continue;
+        }
+        statuses.push(status);
+    }
+
+    statuses.sort_by_key(|status| status.started_at);
+    Ok(statuses)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_status_file_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let status_dir = temp_dir.path().join("status");
+        assert!(read_statuses(&status_dir)?.is_empty());
+
+        let reporter = StatusReporter::new(&status_dir, Path::new("/downloads"), "default");
+        reporter.record(&Ok(OrganizeOutcome::Moved(PathBuf::from("/docs/a.pdf"))));
+        reporter.record(&Ok(OrganizeOutcome::Skipped));
+        reporter.set_queue_length(2);
+        reporter.write()?;
+
+        let statuses = read_statuses(&status_dir)?;
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].watch_dir, PathBuf::from("/downloads"));
+        assert_eq!(statuses[0].queue_length, 2);
+        assert_eq!(statuses[0].counts.moved, 1);
+        assert_eq!(statuses[0].counts.skipped, 1);
+
+        // A stopped instance's file disappears
+        drop(reporter);
+        assert!(read_statuses(&status_dir)?.is_empty());
+        Ok(())
+    }
+}
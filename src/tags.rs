@@ -0,0 +1,131 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Extended attribute holding the category of an organized file
+#[cfg(unix)]
+const CATEGORY_ATTR: &str = "user.autofile.category";
+
+/// Extended attribute holding the subfolder an organized file was matched to
+#[cfg(unix)]
+const FOLDER_ATTR: &str = "user.autofile.folder";
+
+/// Finder tags: a binary property list of "name\ncolor" strings
+#[cfg(target_os = "macos")]
+const FINDER_TAGS_ATTR: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// Tag the organized file at `path` with its category and, if it was matched
+/// to one, its subfolder's name; Finder tags on macOS, extended attributes elsewhere
+#[cfg(unix)]
+pub fn tag_file(path: &Path, category: &str, folder: Option<&str>) -> Result<()> {
+    use anyhow::Context;
+
+    xattr::set(path, CATEGORY_ATTR, category.as_bytes())
+        .context(format!("Failed to tag {:?} with its category", path))?;
+    if let Some(folder) = folder {
+        xattr::set(path, FOLDER_ATTR, folder.as_bytes())
+            .context(format!("Failed to tag {:?} with its folder", path))?;
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        use crate::download_source::plist_strings;
+
+        // Tags the file already has are kept; color 0 is "no color"
+        let mut tags = match xattr::get(path, FINDER_TAGS_ATTR) {
+            Ok(Some(existing)) => plist_strings(&existing).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        for name in std::iter::once(category).chain(folder) {
+            let tag = format!("{}\n0", name);
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        xattr::set(path, FINDER_TAGS_ATTR, &plist_string_array(&tags))
+            .context(format!("Failed to set Finder tags on {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Tag the organized file at `path`; files can't be tagged on this platform, so
+/// this does nothing
+#[cfg(not(unix))]
+pub fn tag_file(_path: &Path, _category: &str, _folder: Option<&str>) -> Result<()> {
+    Ok(())
+}
+
+/// A binary property list ("bplist00") holding an array of `strings`
+/// Object references are single bytes, so at most 254 strings fit.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn plist_string_array(strings: &[String]) -> Vec<u8> {
+    let strings = &strings[..strings.len().min(254)];
+    let mut data = b"bplist00".to_vec();
+    let mut offsets = vec![data.len()];
+
+    // The array is object 0 and refers to the strings as objects 1 onwards
+    push_marker(&mut data, 0xA, strings.len());
+    data.extend((1..=strings.len()).map(|index| index as u8));
+    for string in strings {
+        offsets.push(data.len());
+        if string.is_ascii() {
+            push_marker(&mut data, 0x5, string.len());
+            data.extend(string.as_bytes());
+        } else {
+            let units: Vec<u16> = string.encode_utf16().collect();
+            push_marker(&mut data, 0x6, units.len());
+            data.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+        }
+    }
+
+    let table_start = data.len();
+    let offset_size = if table_start <= u8::MAX as usize {
+        1
+    } else if table_start <= u16::MAX as usize {
+        2
+    } else {
+        4
+    };
+    for offset in &offsets {
+        data.extend(&(*offset as u64).to_be_bytes()[8 - offset_size..]);
+    }
+
+    let mut trailer = [0u8; 32];
+    trailer[6] = offset_size as u8;
+    trailer[7] = 1;
+    trailer[8..16].copy_from_slice(&(offsets.len() as u64).to_be_bytes());
+    trailer[24..32].copy_from_slice(&(table_start as u64).to_be_bytes());
+    data.extend(trailer);
+    data
+}
+
+/// An object marker of `kind` for `len` items, with lengths of 15 or more
+/// following as an integer object
+fn push_marker(data: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 0xF {
+        data.push(kind << 4 | len as u8);
+    } else if len <= u8::MAX as usize {
+        data.extend([kind << 4 | 0xF, 0x10, len as u8]);
+    } else if len <= u16::MAX as usize {
+        data.extend([kind << 4 | 0xF, 0x11]);
+        data.extend((len as u16).to_be_bytes());
+    } else {
+        data.extend([kind << 4 | 0xF, 0x12]);
+        data.extend((len as u32).to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download_source::plist_strings;
+
+    #[test]
+    fn test_plist_string_array_round_trips() {
+        let tags = vec![
+            "document\n0".to_string(),
+            "Steuererklärung 2024\n0".to_string(),
+            "a much longer folder name than fifteen characters\n0".to_string(),
+        ];
+        assert_eq!(plist_strings(&plist_string_array(&tags)), Some(tags));
+    }
+}
@@ -1,10 +1,309 @@
-/// A collection of utility functions for file operations.
+//! A collection of utility functions for file operations.
 
-use std::path::Path;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::SystemTime;
 
 pub fn is_hidden_file(path: &Path) -> bool {
     path.file_name()
         .and_then(|name| name.to_str())
         .map(|s| s.starts_with('.'))
         .unwrap_or(false)
-}
\ No newline at end of file
+}
+
+/// Extensions of macOS bundles: folders that Finder shows, and that move, as one item
+const BUNDLE_EXTENSIONS: &[&str] =
+    &["app", "framework", "bundle", "plugin", "kext", "prefpane", "appex"];
+
+/// Whether `path` is a macOS bundle like an `.app`, to be moved whole and never
+/// looked into; always false on other platforms
+pub fn is_bundle(path: &Path) -> bool {
+    cfg!(target_os = "macos") && has_bundle_extension(path) && path.is_dir()
+}
+
+/// Whether `path` is inside a macOS bundle, like `Tool.app/Contents/MacOS/tool`
+pub fn is_inside_bundle(path: &Path) -> bool {
+    path.ancestors().skip(1).any(is_bundle)
+}
+
+fn has_bundle_extension(path: &Path) -> bool {
+    BUNDLE_EXTENSIONS.contains(&normalized_extension(path).as_str())
+}
+
+/// Names of folders kept in sync by cloud storage clients, or holding such folders
+const SYNC_FOLDER_NAMES: &[&str] =
+    &["Dropbox", "OneDrive", "Google Drive", "iCloud Drive", "Mobile Documents", "CloudStorage"];
+
+/// Whether `path` is a cloud sync client's metadata or temporary file rather than a
+/// user's file: Dropbox attributes and conflicted copies, iCloud placeholders of
+/// files not downloaded yet, and office suites' lock files
+pub fn is_sync_metadata(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    name.starts_with(".dropbox")
+        || name.starts_with(".~lock.")
+        || name.starts_with("~$")
+        || name.contains("conflicted copy")
+        || normalized_extension(path) == "icloud"
+}
+
+/// Whether `path` is inside a cloud-synced folder: one of `sync_folders`, a folder
+/// named like a Dropbox, iCloud Drive, OneDrive or Google Drive folder, or one
+/// marked by Dropbox's `.dropbox` file
+pub fn is_in_sync_folder(path: &Path, sync_folders: &[PathBuf]) -> bool {
+    path.ancestors().any(|folder| {
+        let named = folder.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+            SYNC_FOLDER_NAMES.contains(&name)
+                || name.starts_with("OneDrive - ")
+                || name.starts_with("Dropbox (")
+        });
+        named || sync_folders.iter().any(|sync| sync == folder) || folder.join(".dropbox").is_file()
+    })
+}
+
+/// Extension of `path` without the dot, case-folded for comparison, or "" if it has none
+pub fn normalized_extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(fold_extension)
+        .unwrap_or_default()
+}
+
+/// Case-fold an extension (with or without its leading dot) so `.JPG`, `jpg` and
+/// `.Jpg` compare equal; upper- then lowercasing also folds e.g. `ß` and `SS`
+pub fn fold_extension(extension: &str) -> String {
+    extension.trim_start_matches('.').to_uppercase().to_lowercase()
+}
+
+/// Whether any process, this one included, currently has `path` open or mapped
+/// into memory; false if `/proc` can't be read
+#[cfg(target_os = "linux")]
+pub fn is_file_open(path: &Path) -> bool {
+    let Ok(path) = fs::canonicalize(path) else {
+        return false;
+    };
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return false;
+    };
+    processes
+        .flatten()
+        .filter(|process| process.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+        .any(|process| {
+            // Other users' descriptors and mappings can't be read and are skipped
+            let has_open = fs::read_dir(process.path().join("fd")).is_ok_and(|mut fds| {
+                fds.any(|fd| fd.is_ok_and(|fd| fs::read_link(fd.path()).is_ok_and(|t| t == path)))
+            });
+            // A file can stay mapped after its descriptor is closed
+            has_open || fs::read(process.path().join("maps")).is_ok_and(|maps| {
+                String::from_utf8_lossy(&maps)
+                    .lines()
+                    .filter_map(|line| line.find('/').map(|start| &line[start..]))
+                    .any(|mapped| Path::new(mapped) == path)
+            })
+        })
+}
+
+/// Whether any process, this one included, currently has `path` open; false if
+/// `lsof` is missing
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn is_file_open(path: &Path) -> bool {
+    std::process::Command::new("lsof")
+        .arg("-t")
+        .arg(path)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Whether any process currently has `path` open; not supported here, so always false
+#[cfg(not(unix))]
+pub fn is_file_open(_path: &Path) -> bool {
+    false
+}
+
+/// Whether names in `dir` are case-insensitive, probed by creating a file there and
+/// looking it up with its case flipped; false if `dir` isn't writable
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    let probe = dir.join(format!(".autofile-Case-Probe-{}", std::process::id()));
+    if fs::File::create(&probe).is_err() {
+        return false;
+    }
+    let flipped = dir.join(format!(".AUTOFILE-cASE-pROBE-{}", std::process::id()));
+    let insensitive = fs::symlink_metadata(&flipped).is_ok();
+    let _ = fs::remove_file(&probe);
+    insensitive
+}
+
+/// Whether files in the temp folder take user extended attributes, probed once;
+/// tests needing them return early on filesystems that don't (e.g. older tmpfs)
+#[cfg(all(test, unix))]
+pub(crate) fn xattrs_supported() -> bool {
+    static SUPPORTED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        let Ok(probe) = tempfile::NamedTempFile::new() else {
+            return false;
+        };
+        xattr::SUPPORTED_PLATFORM && xattr::set(probe.path(), "user.autofile.probe", b"1").is_ok()
+    })
+}
+
+/// Regular, non-hidden files in `dir` in name order, including those in non-hidden
+/// subfolders when `recursive`; symlinked folders aren't followed
+pub fn list_files(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let entries = fs::read_dir(dir).context(format!("Failed to read {:?}", dir))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if is_hidden_file(&path) || is_sync_metadata(&path) {
+            continue;
+        }
+        if is_bundle(&path) {
+            files.push(path);
+        } else if recursive && entry.file_type().is_ok_and(|t| t.is_dir()) {
+            files.extend(list_files(&path, recursive)?);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Which timestamp of a file counts as its date, for age checks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    /// Last modification time
+    #[default]
+    Modified,
+    /// Creation ("birth") time, recorded on macOS, Windows and some Linux filesystems
+    Created,
+    /// Last access time, which filesystems mounted with `noatime` don't update
+    Accessed,
+}
+
+impl DateSource {
+    /// The date of the file at `path` with `metadata`, falling back to its
+    /// modification time when the platform or filesystem lacks this timestamp
+    pub fn date_of(self, path: &Path, metadata: &fs::Metadata) -> io::Result<SystemTime> {
+        static FALLBACK_LOGGED: Once = Once::new();
+
+        let date = match self {
+            DateSource::Modified => return metadata.modified(),
+            DateSource::Created => metadata.created(),
+            DateSource::Accessed => metadata.accessed(),
+        };
+        date.or_else(|e| {
+            FALLBACK_LOGGED.call_once(|| {
+                log::warn!("{:?} times unavailable ({}), using modification times", self, e)
+            });
+            log::debug!("No {:?} time for {:?}, using its modification time", self, path);
+            metadata.modified()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalized_extension() {
+        assert_eq!(normalized_extension(Path::new("IMG_1.JPG")), "jpg");
+        assert_eq!(normalized_extension(Path::new("photo.Heic")), "heic");
+        assert_eq!(normalized_extension(Path::new("Straße.STRASSE")), "strasse");
+        assert_eq!(fold_extension(".Straße"), "strasse");
+        assert_eq!(normalized_extension(Path::new("Makefile")), "");
+    }
+
+    #[test]
+    fn test_bundle_extensions() {
+        assert!(has_bundle_extension(Path::new("/Users/me/Downloads/Tool.app")));
+        assert!(has_bundle_extension(Path::new("Sparkle.FRAMEWORK")));
+        assert!(!has_bundle_extension(Path::new("app")));
+        assert!(!has_bundle_extension(Path::new("notes.apple")));
+    }
+
+    #[test]
+    fn test_sync_metadata() -> Result<()> {
+        for name in [
+            ".dropbox.attr",
+            "~$report.docx",
+            ".~lock.budget.ods#",
+            ".Scan.pdf.icloud",
+            "report (Jane's conflicted copy 2024-01-02).docx",
+        ] {
+            assert!(is_sync_metadata(Path::new(name)), "{}", name);
+        }
+        assert!(!is_sync_metadata(Path::new("report.docx")));
+        assert!(!is_sync_metadata(Path::new("icloud-setup.pdf")));
+
+        let temp_dir = tempfile::TempDir::new()?;
+        let team = temp_dir.path().join("Team Share");
+        fs::create_dir_all(team.join("Invoices"))?;
+        assert!(is_in_sync_folder(Path::new("/Users/me/Dropbox/Invoices"), &[]));
+        assert!(is_in_sync_folder(Path::new("/home/me/OneDrive - Acme/Notes"), &[]));
+        assert!(!is_in_sync_folder(&team.join("Invoices"), &[]));
+        assert!(is_in_sync_folder(&team.join("Invoices"), std::slice::from_ref(&team)));
+        fs::write(team.join(".dropbox"), b"{}")?;
+        assert!(is_in_sync_folder(&team.join("Invoices"), &[]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_sources() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join("download.zip");
+        fs::write(&path, b"data")?;
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let (modified, accessed) = (SystemTime::now() - day * 10, SystemTime::now() - day * 20);
+        let times = fs::FileTimes::new().set_modified(modified).set_accessed(accessed);
+        fs::File::options().write(true).open(&path)?.set_times(times)?;
+
+        let metadata = fs::metadata(&path)?;
+        assert_eq!(DateSource::Modified.date_of(&path, &metadata)?, modified);
+        assert_eq!(DateSource::Accessed.date_of(&path, &metadata)?, accessed);
+        // Birth time can't be set; it is just now, or the fallback where unrecorded
+        let created = DateSource::Created.date_of(&path, &metadata)?;
+        assert_eq!(created, metadata.created().unwrap_or(modified));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mapped_file_counts_as_open() -> Result<()> {
+        use std::os::fd::AsRawFd;
+
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join("movie.mp4");
+        fs::write(&path, b"frames")?;
+        let file = fs::File::open(&path)?;
+        let (flags, length) = (libc::MAP_PRIVATE, 6);
+        let mapped = unsafe {
+            libc::mmap(std::ptr::null_mut(), length, libc::PROT_READ, flags, file.as_raw_fd(), 0)
+        };
+        assert_ne!(mapped, libc::MAP_FAILED);
+        drop(file);
+
+        assert!(is_file_open(&path));
+        unsafe { libc::munmap(mapped, length) };
+        assert!(!is_file_open(&path));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_case_sensitive_directory_probe() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        assert!(!is_case_insensitive(temp_dir.path()));
+        // The probe file is cleaned up
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 0);
+        Ok(())
+    }
+}
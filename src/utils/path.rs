@@ -0,0 +1,36 @@
+//! Expansion of user-written paths from the config file.
+
+use std::path::{Path, PathBuf};
+
+/// Expand a leading `~` and `$VAR`/`${VAR}` references in a configured path
+///
+/// Paths that aren't valid UTF-8 or reference an unset variable are returned
+/// unchanged (with a warning for the latter).
+pub fn expand_path(path: &Path) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path.to_path_buf();
+    };
+
+    match shellexpand::full(text) {
+        Ok(expanded) => PathBuf::from(expanded.as_ref()),
+        Err(e) => {
+            log::warn!("Could not expand {:?}: {}", path, e);
+            path.to_path_buf()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_home() {
+        let home = dirs::home_dir().unwrap();
+
+        assert_eq!(expand_path(Path::new("~/Media")), home.join("Media"));
+        assert_eq!(expand_path(Path::new("$HOME/Media")), home.join("Media"));
+        assert_eq!(expand_path(Path::new("${HOME}/Media")), home.join("Media"));
+        assert_eq!(expand_path(Path::new("/srv/media")), PathBuf::from("/srv/media"));
+    }
+}
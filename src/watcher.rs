@@ -1,31 +1,308 @@
+use crate::queue::QueueSender;
+use crate::recent::RecentMoves;
 use crate::utils;
 use anyhow::Result;
+use notify::event::ModifyKind;
 use notify::{Event, EventKind, RecursiveMode, Watcher};
 use notify_debouncer_full::{new_debouncer, DebounceEventResult};
-use std::path::PathBuf;
-use std::sync::mpsc::Sender;
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often a missing or present watch directory is checked
+const DIR_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wait up to `timeout` for `dir` to exist, e.g. for an external drive to be mounted
+/// Returns whether it appeared in time.
+pub fn wait_for_directory(dir: &Path, timeout: Duration) -> bool {
+    wait_for_directory_every(dir, timeout, DIR_POLL_INTERVAL)
+}
+
+fn wait_for_directory_every(dir: &Path, timeout: Duration, poll: Duration) -> bool {
+    let started = Instant::now();
+    while !dir.is_dir() {
+        if started.elapsed() >= timeout {
+            return false;
+        }
+        std::thread::sleep(poll);
+    }
+    true
+}
+
+/// What happens to a folder dropped into the watched folder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DroppedFolders {
+    /// Left alone, apart from files `recursive` watching picks up one by one
+    #[default]
+    Ignore,
+    /// Every file inside is organized once the folder stops changing
+    Organize,
+    /// Like `Organize`, but folders holding a project marker (e.g. `.git`) are kept intact
+    OrganizeUnlessProject,
+}
+
+/// How folders dropped into the watched folder are handled
+#[derive(Clone)]
+struct FolderDrops {
+    watch_path: PathBuf,
+    policy: DroppedFolders,
+    /// Names of files or folders that mark the folder holding them as a project
+    project_markers: Vec<String>,
+    /// How long a folder's contents must stay unchanged before its files are queued
+    settle_time: Duration,
+}
+
+impl FolderDrops {
+    fn is_project(&self, dir: &Path) -> bool {
+        self.policy == DroppedFolders::OrganizeUnlessProject
+            && self.project_markers.iter().any(|marker| dir.join(marker).exists())
+    }
+
+    /// Whether `file` is inside a project folder below the watched folder
+    fn in_project(&self, file: &Path) -> bool {
+        file.ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != self.watch_path && dir.starts_with(&self.watch_path))
+            .any(|dir| self.is_project(dir))
+    }
+
+    /// Queue the files of the dropped folder `dir` once it has settled, from a
+    /// background thread so other events aren't held up meanwhile
+    fn handle(&self, dir: &Path, tx: &QueueSender, queued: &RecentMoves) {
+        if self.policy == DroppedFolders::Ignore {
+            log::debug!("Ignoring dropped folder: {:?}", dir);
+            return;
+        }
+        if self.is_project(dir) {
+            log::info!("Keeping project folder intact: {:?}", dir);
+            return;
+        }
+
+        log::info!("New folder detected, organizing its files once settled: {:?}", dir);
+        let (drops, dir) = (self.clone(), dir.to_path_buf());
+        let (tx, queued) = (tx.clone(), queued.clone());
+        std::thread::spawn(move || {
+            if !wait_until_settled(&dir, drops.settle_time) {
+                log::debug!("Dropped folder disappeared before settling: {:?}", dir);
+                return;
+            }
+            let files = drops.dropped_files(&dir);
+            log::info!("Queueing {} file(s) from dropped folder {:?}", files.len(), dir);
+            for file in files {
+                queued.record(&file);
+                if let Err(e) = tx.send(file) {
+                    log::error!("Failed to send file path: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Queue the macOS bundle `bundle` as one item once it has settled, whatever
+    /// the policy for other folders
+    fn handle_bundle(&self, bundle: &Path, tx: &QueueSender, queued: &RecentMoves) {
+        log::info!("New bundle detected, organizing it once settled: {:?}", bundle);
+        let (settle_time, bundle) = (self.settle_time, bundle.to_path_buf());
+        let (tx, queued) = (tx.clone(), queued.clone());
+        std::thread::spawn(move || {
+            if !wait_until_settled(&bundle, settle_time) {
+                log::debug!("Bundle disappeared before settling: {:?}", bundle);
+                return;
+            }
+            queued.record(&bundle);
+            if let Err(e) = tx.send(bundle) {
+                log::error!("Failed to send file path: {}", e);
+            }
+        });
+    }
+
+    /// Non-hidden files in `dir` and its subfolders, skipping nested project folders
+    /// Bundles are listed whole.
+    fn dropped_files(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if utils::file::is_hidden_file(&path) || utils::file::is_sync_metadata(&path) {
+                continue;
+            }
+            if utils::file::is_bundle(&path) {
+                files.push(path);
+            } else if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                if self.is_project(&path) {
+                    log::info!("Keeping project folder intact: {:?}", path);
+                } else {
+                    files.extend(self.dropped_files(&path));
+                }
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files
+    }
+}
+
+/// Wait until the files below `dir` and their sizes and modification times stay the
+/// same for `settle_time`; false if `dir` disappears meanwhile
+fn wait_until_settled(dir: &Path, settle_time: Duration) -> bool {
+    let mut previous = snapshot(dir);
+    loop {
+        std::thread::sleep(settle_time);
+        if !dir.is_dir() {
+            return false;
+        }
+        let current = snapshot(dir);
+        if current == previous {
+            return true;
+        }
+        previous = current;
+    }
+}
+
+/// Every path below `dir` with its size and modification time, in name order
+fn snapshot(dir: &Path) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            entries.extend(snapshot(&entry.path()));
+        }
+        entries.push((entry.path(), metadata.len(), metadata.modified().ok()));
+    }
+    entries.sort();
+    entries
+}
 
 pub struct FileWatcher {
     watch_path: PathBuf,
+    recent_moves: RecentMoves,
+    process_existing: bool,
+    recursive: bool,
+    folder_drops: FolderDrops,
+    ignored_dirs: Vec<PathBuf>,
 }
 
 impl FileWatcher {
-    pub fn new(watch_path: PathBuf) -> Self {
-        Self { watch_path }
+    pub fn new(watch_path: PathBuf, recent_moves: RecentMoves) -> Self {
+        Self {
+            folder_drops: FolderDrops {
+                watch_path: watch_path.clone(),
+                policy: DroppedFolders::default(),
+                project_markers: Vec::new(),
+                settle_time: Duration::ZERO,
+            },
+            watch_path,
+            recent_moves,
+            process_existing: false,
+            recursive: false,
+            ignored_dirs: Vec::new(),
+        }
+    }
+
+    /// Also queue the files already in the watched folder when watching starts
+    pub fn with_process_existing(mut self, process_existing: bool) -> Self {
+        self.process_existing = process_existing;
+        self
+    }
+
+    /// Also watch the subfolders of the watched folder
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
     }
 
-    pub fn start(self, tx: Sender<PathBuf>) -> Result<impl Drop> {
+    /// Handle folders dropped into the watched folder according to `policy`, once
+    /// their contents have been unchanged for `settle_time`
+    /// Folders containing one of `project_markers` count as projects.
+    pub fn with_dropped_folders(
+        mut self,
+        policy: DroppedFolders,
+        project_markers: Vec<String>,
+        settle_time: Duration,
+    ) -> Self {
+        self.folder_drops.policy = policy;
+        self.folder_drops.project_markers = project_markers;
+        self.folder_drops.settle_time = settle_time;
+        self
+    }
+
+    /// Leave files in these folders alone, like destinations inside the watched
+    /// folder whose files are already organized
+    /// Relative paths are taken from the watched folder. Folders containing the
+    /// watched folder itself are dropped, since they would ignore everything.
+    pub fn with_ignored_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.ignored_dirs = dirs
+            .into_iter()
+            .map(|dir| self.watch_path.join(dir))
+            .filter(|dir| !self.watch_path.starts_with(dir))
+            .collect();
+        self.ignored_dirs.sort();
+        self.ignored_dirs.dedup();
+        if !self.ignored_dirs.is_empty() {
+            log::info!("Ignoring files in {:?}", self.ignored_dirs);
+        }
+        self
+    }
+
+    /// Watch until the process exits, re-establishing the watch whenever the folder
+    /// disappears and comes back (e.g. an external drive is unmounted and remounted)
+    pub fn run(self, tx: QueueSender) -> Result<()> {
+        let mut debouncer = self.start(tx.clone())?;
+        loop {
+            while self.watch_path.is_dir() {
+                std::thread::sleep(DIR_POLL_INTERVAL);
+            }
+            drop(debouncer);
+            log::warn!(
+                "Watch directory {:?} disappeared (drive unmounted?), waiting for it to return",
+                self.watch_path
+            );
+
+            debouncer = loop {
+                while !self.watch_path.is_dir() {
+                    std::thread::sleep(DIR_POLL_INTERVAL);
+                }
+                log::info!("Watch directory {:?} is back (drive mounted?)", self.watch_path);
+                match self.start(tx.clone()) {
+                    Ok(debouncer) => break debouncer,
+                    Err(e) => {
+                        log::error!("Failed to resume watching, retrying: {:#}", e);
+                        std::thread::sleep(DIR_POLL_INTERVAL);
+                    }
+                }
+            };
+        }
+    }
+
+    pub fn start(&self, tx: QueueSender) -> Result<impl Drop> {
         log::info!("Starting file watcher on: {:?}", self.watch_path);
 
+        // Files queued by the startup scan, so their early watch events are ignored
+        let queued = RecentMoves::default();
+
         let tx_clone = tx.clone();
+        let recent_moves = self.recent_moves.clone();
+        let queued_clone = queued.clone();
+        let folder_drops = self.folder_drops.clone();
+        let ignored_dirs = self.ignored_dirs.clone();
         let mut debouncer = new_debouncer(
             Duration::from_secs(2),
             None,
             move |result: DebounceEventResult| match result {
                 Ok(events) => {
                     for event in events {
-                        if let Err(e) = Self::handle_event(&event.event, &tx_clone) {
+                        let result = Self::handle_event(
+                            &event.event,
+                            &tx_clone,
+                            &recent_moves,
+                            &queued_clone,
+                            &folder_drops,
+                            &ignored_dirs,
+                        );
+                        if let Err(e) = result {
                             log::error!("Error handling event: {}", e);
                         }
                     }
@@ -38,25 +315,122 @@ impl FileWatcher {
             },
         )?;
 
-        debouncer
-            .watcher()
-            .watch(&self.watch_path, RecursiveMode::NonRecursive)?;
+        let mode = if self.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        debouncer.watcher().watch(&self.watch_path, mode)?;
 
         log::info!("File watcher initialized successfully");
 
+        // Scan only once watching, so files created in between aren't missed
+        if self.process_existing {
+            let (dir, ignored) = (&self.watch_path, &self.ignored_dirs);
+            let count = Self::queue_existing(dir, self.recursive, ignored, &tx, &queued)?;
+            log::info!("Queued {} existing file(s) from {:?}", count, self.watch_path);
+        }
+
         Ok(debouncer)
     }
 
-    fn handle_event(event: &Event, tx: &Sender<PathBuf>) -> Result<()> {
+    /// Queue the regular, non-hidden files already in `dir`, remembering them in `queued`
+    /// Descends into non-hidden subfolders outside `ignored` when `recursive`;
+    /// returns how many were queued
+    fn queue_existing(
+        dir: &Path,
+        recursive: bool,
+        ignored: &[PathBuf],
+        tx: &QueueSender,
+        queued: &RecentMoves,
+    ) -> Result<usize> {
+        let mut count = 0;
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            let metadata = utils::file::is_sync_metadata(&path);
+            if utils::file::is_hidden_file(&path) || metadata || is_ignored(&path, ignored) {
+                continue;
+            }
+            // file_type() doesn't follow symlinks, so linked folders aren't walked
+            let bundle = utils::file::is_bundle(&path);
+            if recursive && !bundle && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                count += Self::queue_existing(&path, recursive, ignored, tx, queued)?;
+                continue;
+            }
+            if !path.is_file() && !bundle {
+                continue;
+            }
+
+            queued.record(&path);
+            if let Err(e) = tx.send(path) {
+                log::error!("Failed to send file path: {}", e);
+            }
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    fn handle_event(
+        event: &Event,
+        tx: &QueueSender,
+        recent_moves: &RecentMoves,
+        queued: &RecentMoves,
+        folder_drops: &FolderDrops,
+        ignored: &[PathBuf],
+    ) -> Result<()> {
         match &event.kind {
             EventKind::Create(_) | EventKind::Modify(_) => {
                 for path in &event.paths {
+                    if is_ignored(path, ignored) {
+                        log::debug!("Ignoring file in an ignored folder: {:?}", path);
+                        continue;
+                    }
+                    // Bundles are organized whole once they appear
+                    if utils::file::is_inside_bundle(path) {
+                        log::debug!("Ignoring file inside a bundle: {:?}", path);
+                        continue;
+                    }
+                    let new_folder = matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+                    );
+                    if new_folder && path.is_dir() && !utils::file::is_hidden_file(path) {
+                        if utils::file::is_bundle(path) {
+                            folder_drops.handle_bundle(path, tx, queued);
+                        } else {
+                            folder_drops.handle(path, tx, queued);
+                        }
+                        continue;
+                    }
+
                     if path.is_file() {
+                        if folder_drops.in_project(path) {
+                            log::debug!("Ignoring file inside a project folder: {:?}", path);
+                            continue;
+                        }
+
                         // Ignore hidden files.
                         if utils::file::is_hidden_file(path) {
                             log::debug!("Ignoring hidden file: {:?}", path);
                             continue;
                         }
+                        if utils::file::is_sync_metadata(path) {
+                            log::debug!("Ignoring cloud sync metadata: {:?}", path);
+                            continue;
+                        }
+
+                        // Ignore files AutoFile itself just moved here
+                        if recent_moves.contains(path) {
+                            log::debug!("Ignoring recently organized file: {:?}", path);
+                            continue;
+                        }
+
+                        // Already queued by the startup scan
+                        if queued.contains(path) {
+                            log::debug!("Ignoring file queued at startup: {:?}", path);
+                            continue;
+                        }
 
                         log::info!("New file detected: {:?}", path);
 
@@ -74,3 +448,224 @@ impl FileWatcher {
         Ok(())
     }
 }
+
+/// Whether `path` is in (or is) one of the `ignored` folders
+fn is_ignored(path: &Path, ignored: &[PathBuf]) -> bool {
+    ignored.iter().any(|dir| path.starts_with(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::categorizer::{Categorizer, CategoryRule};
+    use crate::detector::FileCategory;
+    use crate::queue::file_queue;
+    use std::collections::HashMap;
+    use notify::event::CreateKind;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn folder_drops(watched: &TempDir, policy: DroppedFolders) -> FolderDrops {
+        FolderDrops {
+            watch_path: watched.path().to_path_buf(),
+            policy,
+            project_markers: vec![".git".to_string(), "package.json".to_string()],
+            settle_time: Duration::from_millis(20),
+        }
+    }
+
+    /// The next `count` paths queued by handling done on a background thread,
+    /// sorted; gives up after a few seconds, returning those queued by then
+    fn queued_soon(rx: &crate::queue::QueueReceiver, count: usize) -> Vec<PathBuf> {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let mut paths = Vec::new();
+        while paths.len() < count && std::time::Instant::now() < deadline {
+            match rx.try_recv() {
+                Some(path) => paths.push(path),
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_waits_for_directory_to_appear() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let drive = temp_dir.path().join("External");
+        let poll = Duration::from_millis(10);
+        assert!(!wait_for_directory_every(&drive, Duration::from_millis(50), poll));
+
+        // Mounted shortly after startup
+        let mount = {
+            let drive = drive.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                fs::create_dir(drive)
+            })
+        };
+        assert!(wait_for_directory_every(&drive, Duration::from_secs(5), poll));
+        mount.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recently_moved_file_is_not_retriggered() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let destination = temp_dir.path().join("Documents");
+        fs::create_dir(&destination)?;
+        let moved = destination.join("report.pdf");
+        fs::write(&moved, b"content")?;
+
+        let recent_moves = RecentMoves::default();
+        recent_moves.record(&moved);
+
+        let (tx, rx) = file_queue();
+        let event = Event::new(EventKind::Create(CreateKind::File)).add_path(moved);
+        let drops = folder_drops(&temp_dir, DroppedFolders::Ignore);
+        let queued = RecentMoves::default();
+        FileWatcher::handle_event(&event, &tx, &recent_moves, &queued, &drops, &[])?;
+
+        assert!(rx.try_recv().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dropped_folder_files_are_queued() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dropped = temp_dir.path().join("Trip");
+        fs::create_dir_all(dropped.join("Day 1"))?;
+        fs::write(dropped.join("itinerary.pdf"), b"content")?;
+        fs::write(dropped.join("Day 1").join("beach.jpg"), b"content")?;
+        fs::write(dropped.join(".DS_Store"), b"")?;
+
+        let (tx, rx) = file_queue();
+        let event = Event::new(EventKind::Create(CreateKind::Folder)).add_path(dropped.clone());
+        let (recent, queued) = (RecentMoves::default(), RecentMoves::default());
+        let drops = folder_drops(&temp_dir, DroppedFolders::Organize);
+        FileWatcher::handle_event(&event, &tx, &recent, &queued, &drops, &[])?;
+
+        let expected = vec![dropped.join("Day 1").join("beach.jpg"), dropped.join("itinerary.pdf")];
+        assert_eq!(queued_soon(&rx, expected.len()), expected);
+
+        // The default leaves dropped folders alone, without a background thread
+        let drops = folder_drops(&temp_dir, DroppedFolders::Ignore);
+        FileWatcher::handle_event(&event, &tx, &recent, &RecentMoves::default(), &drops, &[])?;
+        assert!(rx.try_recv().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dropped_project_folder_is_kept_intact() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let project = temp_dir.path().join("website");
+        fs::create_dir_all(project.join("src"))?;
+        fs::write(project.join("package.json"), b"{}")?;
+        fs::write(project.join("src").join("index.js"), b"content")?;
+        let notes = temp_dir.path().join("Notes");
+        fs::create_dir_all(notes.join("tool").join(".git"))?;
+        fs::write(notes.join("todo.txt"), b"content")?;
+        fs::write(notes.join("tool").join("main.rs"), b"content")?;
+
+        let (tx, rx) = file_queue();
+        let drops = folder_drops(&temp_dir, DroppedFolders::OrganizeUnlessProject);
+        let (recent, queued) = (RecentMoves::default(), RecentMoves::default());
+        for folder in [&project, &notes] {
+            let event = Event::new(EventKind::Create(CreateKind::Folder)).add_path(folder.clone());
+            FileWatcher::handle_event(&event, &tx, &recent, &queued, &drops, &[])?;
+        }
+        // Only the file outside the nested project is organized; wait out a few more
+        // settle periods for anything queued after it
+        let mut queued_paths = queued_soon(&rx, 1);
+        std::thread::sleep(Duration::from_millis(200));
+        queued_paths.extend(rx.try_iter());
+        queued_paths.sort();
+        assert_eq!(queued_paths, vec![notes.join("todo.txt")]);
+        assert!(rx.try_recv().is_none());
+
+        // With recursive watching, files inside the project aren't picked up one by one
+        let event = Event::new(EventKind::Create(CreateKind::File))
+            .add_path(project.join("src").join("index.js"));
+        FileWatcher::handle_event(&event, &tx, &recent, &RecentMoves::default(), &drops, &[])?;
+        assert!(rx.try_recv().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_existing_files_are_queued_once() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let existing = temp_dir.path().join("report.pdf");
+        fs::write(&existing, b"content")?;
+        fs::write(temp_dir.path().join(".DS_Store"), b"")?;
+        fs::create_dir(temp_dir.path().join("Folder"))?;
+
+        let queued = RecentMoves::default();
+        let (tx, rx) = file_queue();
+        FileWatcher::queue_existing(temp_dir.path(), false, &[], &tx, &queued)?;
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![existing.clone()]);
+
+        // An early watch event for the same file doesn't queue it again
+        let event = Event::new(EventKind::Modify(notify::event::ModifyKind::Any)).add_path(existing);
+        let drops = folder_drops(&temp_dir, DroppedFolders::Ignore);
+        FileWatcher::handle_event(&event, &tx, &RecentMoves::default(), &queued, &drops, &[])?;
+        assert!(rx.try_recv().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_destinations_inside_watched_folder_are_ignored() -> Result<()> {
+        let home = TempDir::new()?;
+        let documents = home.path().join("Documents");
+        fs::create_dir_all(documents.join("Taxes"))?;
+        let organized = documents.join("Taxes").join("2023.pdf");
+        fs::write(&organized, b"content")?;
+        let new = home.path().join("invoice.pdf");
+        fs::write(&new, b"content")?;
+
+        let rules = HashMap::from([(
+            FileCategory::Document,
+            CategoryRule {
+                name: "Documents".to_string(),
+                destination: documents.clone(),
+            },
+        )]);
+        let roots = Categorizer::_with_custom_rules(rules).destination_roots();
+        let watcher = FileWatcher::new(home.path().to_path_buf(), RecentMoves::default())
+            .with_recursive(true)
+            .with_ignored_dirs(roots);
+
+        // Neither the startup scan nor watch events pick up files in Documents
+        let (tx, rx) = file_queue();
+        let queued = RecentMoves::default();
+        FileWatcher::queue_existing(home.path(), true, &watcher.ignored_dirs, &tx, &queued)?;
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![new]);
+
+        let event = Event::new(EventKind::Create(CreateKind::File)).add_path(organized);
+        let drops = folder_drops(&home, DroppedFolders::Ignore);
+        let ignored = &watcher.ignored_dirs;
+        FileWatcher::handle_event(&event, &tx, &queued, &queued, &drops, ignored)?;
+        assert!(rx.try_recv().is_none());
+
+        // A destination containing the watched folder would ignore everything
+        let watcher = FileWatcher::new(documents.clone(), RecentMoves::default())
+            .with_ignored_dirs(vec![home.path().to_path_buf()]);
+        assert!(watcher.ignored_dirs.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_recursive_scan_queues_nested_files() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let nested = temp_dir.path().join("ClientA");
+        fs::create_dir_all(nested.join(".git"))?;
+        fs::write(nested.join("notes.pdf"), b"content")?;
+        fs::write(nested.join(".git").join("HEAD"), b"ref")?;
+
+        let (tx, rx) = file_queue();
+        let recent = RecentMoves::default();
+        let count = FileWatcher::queue_existing(temp_dir.path(), true, &[], &tx, &recent)?;
+        assert_eq!(count, 1);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![nested.join("notes.pdf")]);
+        Ok(())
+    }
+}
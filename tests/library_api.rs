@@ -0,0 +1,13 @@
+use autofile::{FileCategory, FileDetector};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_detect_category_through_public_api() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("notes.txt");
+    fs::write(&path, b"plain text notes")?;
+
+    assert_eq!(FileDetector::detect_category(&path)?, FileCategory::Document);
+    Ok(())
+}